@@ -1,6 +1,10 @@
+// A port of a C decoder that keeps its naming and layout (A64_* opcodes,
+// constant modules) so the two stay easy to compare; the items that carry
+// the C names allow it individually.
+
 use crate::aarch64_reader::ExtendType::{SXTB, SXTH, SXTW, UXTB, UXTH};
 use crate::aarch64_reader::FlagMasks::{SET_FLAGS, W32};
-use crate::aarch64_reader::Op::{A64_ADD_IMM, A64_ADR, A64_ADRP, A64_AND_IMM, A64_ASR_IMM, A64_BFC, A64_BFI, A64_BFM, A64_BFXIL, A64_CMN_IMM, A64_CMP_IMM, A64_EOR_IMM, A64_EXTEND, A64_EXTR, A64_LSL_IMM, A64_LSR_IMM, A64_MOV_IMM, A64_MOV_SP, A64_MOVK, A64_ORR_IMM, A64_ROR_IMM, A64_SBFIZ, A64_SBFM, A64_SBFX, A64_SUB_IMM, A64_TST_IMM, A64_UBFIZ, A64_UBFM, A64_UBFX, A64_UNKNOWN};
+use crate::aarch64_reader::Op::{A64_ADC, A64_BCOND, A64_LDNP, A64_LDNP_FP, A64_LDP, A64_LDP_FP, A64_LDR, A64_LDR_FP, A64_PRFM, A64_STNP, A64_STNP_FP, A64_STP, A64_STP_FP, A64_STR, A64_STR_FP, A64_FCMPE_REG, A64_FCMPE_ZERO, A64_FCMP_REG, A64_FCMP_ZERO, A64_ADD_EXT, A64_ADD_SHIFTED, A64_AND_SHIFTED, A64_ASRV, A64_BIC, A64_CCMN_IMM, A64_CCMN_REG, A64_CCMP_IMM, A64_CCMP_REG, A64_CINC, A64_CINV, A64_CLS, A64_CLZ, A64_CMN_EXT, A64_CMN_SHIFTED, A64_CMP_EXT, A64_CMP_SHIFTED, A64_CNEG, A64_CRC32B, A64_CRC32CB, A64_CRC32CH, A64_CRC32CW, A64_CRC32CX, A64_CRC32H, A64_CRC32W, A64_CRC32X, A64_CSEL, A64_CSET, A64_CSETM, A64_CSINC, A64_CSINV, A64_CSNEG, A64_EON, A64_EOR_SHIFTED, A64_LSLV, A64_LSRV, A64_MADD, A64_MNEG, A64_MOV_REG, A64_MSUB, A64_MUL, A64_MVN, A64_NEG, A64_NGC, A64_ORN, A64_ORR_SHIFTED, A64_RBIT, A64_REV, A64_REV16, A64_REV32, A64_RMIF, A64_RORV, A64_SBC, A64_SDIV, A64_SETF16, A64_SETF8, A64_SMADDL, A64_SMNEGL, A64_SMSUBL, A64_SMULH, A64_SMULL, A64_SUB_EXT, A64_SUB_SHIFTED, A64_TST_SHIFTED, A64_UDF, A64_UDIV, A64_UMADDL, A64_UMNEGL, A64_UMSUBL, A64_UMULH, A64_UMULL, A64_ADD_IMM, A64_ADR, A64_ADRP, A64_AND_IMM, A64_ASR_IMM, A64_BFC, A64_BFI, A64_BFM, A64_BFXIL, A64_CMN_IMM, A64_CMP_IMM, A64_EOR_IMM, A64_EXTEND, A64_EXTR, A64_LSL_IMM, A64_LSR_IMM, A64_MOV_IMM, A64_MOV_SP, A64_MOVK, A64_ORR_IMM, A64_ROR_IMM, A64_SBFIZ, A64_SBFM, A64_SBFX, A64_SUB_IMM, A64_TST_IMM, A64_UBFIZ, A64_UBFM, A64_UBFX};
 use crate::aarch64_reader::OpKind::{AddSub, AddSubTags, Bitfield, Extract, Logic, Move, PCRelAddr, Unknown};
 use crate::aarch64_reader::Registries::{STACK_POINTER, ZERO_REG};
 
@@ -11,7 +15,8 @@ use crate::aarch64_reader::Registries::{STACK_POINTER, ZERO_REG};
 ///We split up this overloaded register: when we encounter R31 and interpret it as
 ///the stack pointer, we assign a different number. This way, the user does not
 ///need to know which instructions use the SP and which use the ZR.
-mod Registries {
+#[allow(non_snake_case)]
+pub mod Registries {
     pub const ZERO_REG: u8 = 31;
     pub const STACK_POINTER: u8 = 100;
 }
@@ -32,7 +37,8 @@ mod Registries {
 /// condition encoded in the Inst.flags field. The various addressing
 /// modes of loads and stores are encoded similarly. See the Inst
 /// structure for more detail.
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[allow(non_camel_case_types)]
 pub enum Op {
     A64_UNKNOWN,
     /// unknown instruction (or Op field not set, by accident), Inst.imm contains raw binary instruction
@@ -797,6 +803,7 @@ pub enum Op {
 /// The condition bits used by conditial branches, selects and compares, stored in the
 /// upper four bit of the Inst.flags field. The first three bits determine the condition
 /// proper while the LSB inverts the condition if set.
+#[allow(non_snake_case)]
 pub mod Cond {
     /// =
     pub const COND_EQ: u8 = 0b0000;
@@ -836,6 +843,7 @@ pub mod Cond {
     pub const COND_NV: u8 = 0b1111;
 }
 
+#[allow(non_snake_case)]
 pub mod Shift {
     pub const SH_LSL: u8 = 0b00;
     pub const SH_LSR: u8 = 0b01;
@@ -855,9 +863,11 @@ pub mod Shift {
 /// (#4 for 128 bits (SIMD), #3 for 64 bits, #2 for 32 bits, #1 for
 /// 16 bits, #0 for 8 bits) and is used for array indexing:
 ///
-///     u64 a[128];
-///     u64 x0 = a[i]; → ldr x0, [a, i, LSL #3]
-///
+/// ```text
+/// u64 a[128];
+/// u64 x0 = a[i]; → ldr x0, [a, i, LSL #3]
+/// ```
+#[allow(non_snake_case)]
 pub mod AddrMode {
     /// [base] -- used by atomics, exclusive, ordered load/stores → check Inst.ldst_order
     pub const AM_SIMPLE: u8 = 0;
@@ -878,6 +888,7 @@ pub mod AddrMode {
 /// Memory ordering semantics for Atomic instructions and the Load/Stores in the
 /// Exclusive group.
 #[derive(Clone)]
+#[allow(non_camel_case_types)]
 pub enum MemOrdering {
     MO_NONE,
     /// Load-Acquire -- sequentially consistent Acquire
@@ -893,6 +904,7 @@ pub enum MemOrdering {
 }
 
 /// Size, encoded in two bits.
+#[allow(non_snake_case)]
 pub mod Size {
     /// Byte     -  8 bit
     pub const SZ_B: u8 = 0b00;
@@ -906,6 +918,7 @@ pub mod Size {
 
 /// Floating-point size, encoded in three bits. Mostly synonymous to Size, but
 /// with the 128-bit quadruple precision.
+#[allow(non_snake_case)]
 pub mod FPSize {
     use crate::aarch64_reader::Size;
 
@@ -930,6 +943,7 @@ pub mod FPSize {
 /// The vector registers V0...V31 are 128 bit long, but some arrangements use
 /// only the bottom 64 bits. Scalar SIMD instructions encode their scalars'
 /// precision as FPSize in the upper two bits.
+#[allow(non_snake_case, clippy::identity_op)]
 pub mod VectorArrangement {
     use crate::aarch64_reader::FPSize;
 
@@ -955,6 +969,7 @@ pub mod VectorArrangement {
 /// in the shared pseudocode functions of the A64 ISA documentation. The letter
 /// is the one used in the FCVT* mnemonics.
 #[derive(Clone)]
+#[allow(non_camel_case_types)]
 pub enum FPRounding {
     /// "Current rounding mode"
     FPR_CURRENT,
@@ -973,6 +988,7 @@ pub enum FPRounding {
 }
 
 /// ExtendType: signed(1):size(2)
+#[allow(non_snake_case, clippy::identity_op)]
 pub mod ExtendType {
     use crate::aarch64_reader::Size;
 
@@ -988,6 +1004,7 @@ pub mod ExtendType {
 
 /// PstateField: encodes which PSTATE bits the MSR_IMM instruction modifies.
 #[derive(Clone)]
+#[allow(non_camel_case_types)]
 pub enum PStateField {
     PSF_UAO,
     PSF_PAN,
@@ -998,6 +1015,7 @@ pub enum PStateField {
     PSF_DAIFClr,
 }
 
+#[allow(non_snake_case)]
 pub mod FlagMasks {
     /// use the 32-bit W0...W31 facets?
     pub const W32: u8 = 1 << 0;
@@ -1013,128 +1031,128 @@ pub mod FlagMasks {
 
 #[derive(Clone)]
 pub struct Movk {
-    imm16: u32,
-    lsl: u32,
+    pub imm16: u32,
+    pub lsl: u32,
 }
 
 #[derive(Clone)]
 pub struct Bfm {
-    lsb: u32,
-    width: u32,
+    pub lsb: u32,
+    pub width: u32,
 }
 
 #[derive(Clone)]
 pub struct Ccmp {
-    nzcv: u32,
-    imm5: u32,
+    pub nzcv: u32,
+    pub imm5: u32,
 }
 
 #[derive(Clone)]
 pub struct Sys {
-    op1: u16,
-    op2: u16,
-    crn: u16,
-    crm: u16,
+    pub op1: u16,
+    pub op2: u16,
+    pub crn: u16,
+    pub crm: u16,
 }
 
 #[derive(Clone)]
 pub struct MsrImm {
-    psfld: u32,
-    imm: u32,
+    pub psfld: u32,
+    pub imm: u32,
 }
 
 #[derive(Clone)]
 pub struct Tbz {
-    offset: i32,
-    bit: u32,
+    pub offset: i32,
+    pub bit: u32,
 }
 
 #[derive(Clone)]
 pub struct InstShift {
-    typ: u32,
-    amount: u32,
+    pub typ: u32,
+    pub amount: u32,
 }
 
 #[derive(Clone)]
 pub struct Rmif {
-    mask: u32,
-    ror: u32,
+    pub mask: u32,
+    pub ror: u32,
 }
 
 #[derive(Clone)]
 pub struct Extend {
-    typ: u32,
-    lsl: u32,
+    pub typ: u32,
+    pub lsl: u32,
 }
 
 #[derive(Clone)]
 pub struct LdstOrder {
-    load: u16,
-    store: u16,
-    rs: u8,
+    pub load: u16,
+    pub store: u16,
+    pub rs: u8,
 }
 
 #[derive(Clone)]
 pub struct SimdLdst {
-    nreg: u32,
-    index: u16,
-    offset: i16,
+    pub nreg: u32,
+    pub index: u16,
+    pub offset: i16,
 }
 
 #[derive(Clone)]
 pub struct Fcvt {
-    mode: u32,
-    fbits: u16,
-    sgn: u16,
+    pub mode: u32,
+    pub fbits: u16,
+    pub sgn: u16,
 }
 
 #[derive(Clone)]
 pub struct Frint {
-    mode: u32,
-    bits: u32,
+    pub mode: u32,
+    pub bits: u32,
 }
 
 #[derive(Clone)]
 pub struct InsElem {
-    dst: u32,
-    src: u32,
+    pub dst: u32,
+    pub src: u32,
 }
 
 #[derive(Clone)]
 pub struct FcmlaElem {
-    idx: u32,
-    rot: u32,
+    pub idx: u32,
+    pub rot: u32,
 }
 
 #[derive(Clone)]
 pub struct Inst {
-    op: Op,
-    flags: u8,
-    rd: u8,
-    rn: u8,
-    rm: u8,
-    rt2: u8,
-    rs: u8,
-    imm: u64,
-    fimm: f64,
-    offset: i64,
-    ra: u8,
-    error: String,
-    movk: Movk,
-    bfm: Bfm,
-    ccmp: Ccmp,
-    sys: Sys,
-    msr_imm: MsrImm,
-    tbz: Tbz,
-    shift: u8,
-    rmif: Rmif,
-    extend: Extend,
-    ldst_order: LdstOrder,
-    simd_ldst: SimdLdst,
-    fcvt: Fcvt,
-    frint: Frint,
-    ins_elem: InsElem,
-    fcmla_elem: FcmlaElem,
+    pub op: Op,
+    pub flags: u8,
+    pub rd: u8,
+    pub rn: u8,
+    pub rm: u8,
+    pub rt2: u8,
+    pub rs: u8,
+    pub imm: u64,
+    pub fimm: f64,
+    pub offset: i64,
+    pub ra: u8,
+    pub error: String,
+    pub movk: Movk,
+    pub bfm: Bfm,
+    pub ccmp: Ccmp,
+    pub sys: Sys,
+    pub msr_imm: MsrImm,
+    pub tbz: Tbz,
+    pub shift: u8,
+    pub rmif: Rmif,
+    pub extend: Extend,
+    pub ldst_order: LdstOrder,
+    pub simd_ldst: SimdLdst,
+    pub fcvt: Fcvt,
+    pub frint: Frint,
+    pub ins_elem: InsElem,
+    pub fcmla_elem: FcmlaElem,
 }
 
 const UNKNOWN_INST: Inst = Inst {
@@ -1188,97 +1206,103 @@ pub fn errinst(err: String) -> Inst {
     let mut inst = UNKNOWN_INST;
     inst.op = Op::A64_ERROR;
     inst.error = err;
-    return inst;
+    inst
 }
 
 pub fn fad_get_cond(flags: u8) -> u8 {
-    return (flags >> 4) & 0b1111;
+    (flags >> 4) & 0b1111
 }
 
 fn set_cond(flags: u8, cond: u8) -> u8 {
-    let cond = cond as u8 & 0xF;
+    let cond = cond & 0xF;
     let flags = flags & 0x0F;
     (cond << 4) | flags
 }
 
 pub fn invert_cond(flags: u8) -> u8 {
     let cond = fad_get_cond(flags);
-    return set_cond(flags, cond as u8 ^ 0b001); // invert LSB
+    set_cond(flags, cond ^ 0b001) // invert LSB
 }
 
 // Addressing mode, for Loads and Stores.
 pub fn fad_get_addrmode(flags: u8) -> u8 {
-    return (flags >> 5) & 0b111;
+    (flags >> 5) & 0b111
 }
 
 pub fn set_addrmode(flags: u8, mode: u8) -> u8 {
-    return ((mode & 0b111) << 5) | (flags & 0b11111);
+    ((mode & 0b111) << 5) | (flags & 0b11111)
 }
 
 // How much memory to load/store (access size) and whether to sign-
 // or zero-extend the value.
 pub fn fad_get_mem_extend(flags: u8) -> u8 {
-    return (flags >> 2) & 0b111;
+    (flags >> 2) & 0b111
 }
 
 pub fn set_mem_extend(flags: u8, memext: u8) -> u8 {
-    return ((memext & 0b111) << 2) | (flags & 0b11100011);
+    ((memext & 0b111) << 2) | (flags & 0b11100011)
 }
 
 pub fn fad_get_vec_arrangement(flags: u8) -> u8 {
-    return (flags >> 2) & 0b111;
+    (flags >> 2) & 0b111
 }
 
 pub fn set_vec_arrangement(flags: u8, va: u8) -> u8 {
-    return ((va & 0b111) << 2) | (flags & 0b11100011);
+    ((va & 0b111) << 2) | (flags & 0b11100011)
 }
 
 pub fn fad_get_prec(flags: u8) -> u8 {
-    return (flags >> 1) & 0b111;
+    (flags >> 1) & 0b111
 }
 
 pub fn set_prec(flags: u8, prec: u8) -> u8 {
-    return ((prec & 0b111) << 1) | (flags & 0b11110001);
+    ((prec & 0b111) << 1) | (flags & 0b11110001)
 }
 
 pub fn fad_size_from_vec_arrangement(va: u8) -> u8 {
-    return va >> 1;
+    va >> 1
 }
 
 // The destination register Rd, if present, occupies bits 0..4.
 // Register 31 is treated as the Zero/Discard register ZR/WZR.
+#[allow(non_snake_case)]
 pub fn regRd(binst: u32) -> u8 {
-    return (binst & 0b11111) as u8;
+    (binst & 0b11111) as u8
 }
 
 // Register 31 is treated as the stack pointer SP.
+#[allow(non_snake_case)]
 pub fn regRdSP(binst: u32) -> u8 {
     let rd = binst & 0b11111;
-    return if rd == 31 { Registries::STACK_POINTER } else { rd.try_into().unwrap() };
+    if rd == 31 { Registries::STACK_POINTER } else { rd.try_into().unwrap() }
 }
 
 // The first operand register Rn, if present, occupies bits 5..9.
 // Register 31 is treated as the Zero/Discard register ZR/WZR.
+#[allow(non_snake_case)]
 pub fn regRn(binst: u32) -> u8 {
-    return ((binst >> 5) & 0b11111).try_into().unwrap();
+    ((binst >> 5) & 0b11111).try_into().unwrap()
 }
 
 // Register 31 is treated as the stack pointer SP.
+#[allow(non_snake_case)]
 pub fn regRnSP(binst: u32) -> u8 {
     let rn = (binst >> 5) & 0b11111;
-    return if rn == 31 { Registries::STACK_POINTER } else { rn.try_into().unwrap() };
+    if rn == 31 { Registries::STACK_POINTER } else { rn.try_into().unwrap() }
 }
 
 // The second operand register Rm, if present, occupies bits 16..20.
 // Register 31 is treated as the Zero/Discard register ZR/WZR.
+#[allow(non_snake_case)]
 pub fn regRm(binst: u32) -> u8 {
-    return ((binst >> 16) & 0b11111).try_into().unwrap();
+    ((binst >> 16) & 0b11111).try_into().unwrap()
 }
 
 // Register 31 is treated as the stack pointer SP.
+#[allow(non_snake_case)]
 pub fn regRmSP(binst: u32) -> u8 {
     let rm = (binst >> 16) & 0b11111;
-    return if rm == 31 { Registries::STACK_POINTER } else { rm.try_into().unwrap() };
+    if rm == 31 { Registries::STACK_POINTER } else { rm.try_into().unwrap() }
 }
 
 // sext sign-extends the b-bits number in x to 64 bit. The upper (64-b) bits
@@ -1286,8 +1310,8 @@ pub fn regRmSP(binst: u32) -> u8 {
 //
 // Taken from https://graphics.stanford.edu/~seander/bithacks.html#VariableSignExtend
 pub fn sext(x: u64, b: u8) -> i64 {
-    let mask = (1 as i64) << (b - 1);
-    return ((x as i64) ^ mask) - mask;
+    let mask = 1i64 << (b - 1);
+    ((x as i64) ^ mask) - mask
 }
 
 enum OpKind {
@@ -1301,6 +1325,7 @@ enum OpKind {
     Extract,
 }
 
+#[allow(non_snake_case)]
 pub fn data_proc_imm(binst: u32) -> Inst {
     let mut inst = UNKNOWN_INST.clone();
 
@@ -1308,7 +1333,7 @@ pub fn data_proc_imm(binst: u32) -> Inst {
     let top3 = (binst >> 29) & 0b111;
 
     let kind = match op01 {
-        0b0000 | 0b0001 | 0b0010 | 0b0011 => PCRelAddr, // 00xx
+        0b0000..=0b0011 => PCRelAddr, // 00xx
         0b0110 | 0b0111 => AddSubTags, // 011x
         0b0100 | 0b0101 => AddSub, // 010x
         0b1000 | 0b1001 => Logic, // 100x
@@ -1349,7 +1374,7 @@ pub fn data_proc_imm(binst: u32) -> Inst {
 
             inst.rd = regRd(binst);
         }
-        AddSubTags => panic!("ADDG, SUBG not supported"),
+        AddSubTags => return errinst("ADDG, SUBG not supported".to_string()),
         AddSub => {
             let is_add = (top3 & 0b010) == 0;
             inst.op = if is_add { A64_ADD_IMM } else { A64_SUB_IMM };
@@ -1390,8 +1415,14 @@ pub fn data_proc_imm(binst: u32) -> Inst {
 
             let immr: u8 = ((binst >> 16) & 0b111111) as u8;
             let imms: u8 = ((binst >> 10) & 0b111111) as u8;
-            let N: u8 = if inst.flags & W32 != 0 { 0 } else { ((binst >> 22) & 1) as u8 }; // N is part of imm for 64-bit variants
-            inst.imm = decode_bitmask(N, imms, immr, inst.flags & W32 != 0);
+            let N: u8 = ((binst >> 22) & 1) as u8; // N is part of imm for 64-bit variants
+            if N == 1 && inst.flags & W32 != 0 {
+                return UNKNOWN_INST; // unallocated
+            }
+            match decode_bitmask(N, imms, immr, inst.flags & W32 != 0) {
+                Some(imm) => inst.imm = imm,
+                None => return UNKNOWN_INST, // reserved
+            }
 
             // ANDS and by extension TST interpret R31 as the zero register, while
             // regular immediate AND interprets it as the stack pointer.
@@ -1400,7 +1431,7 @@ pub fn data_proc_imm(binst: u32) -> Inst {
         }
         Move => {
             let hw: u8 = ((binst >> 21) & 0b11) as u8;
-            let shift: u8 = (16 * hw) as u8;
+            let shift: u8 = 16 * hw;
             let imm16: u64 = ((binst >> 5) & 0xFFFF) as u64;
 
             match top3 & 0b011 {
@@ -1428,19 +1459,30 @@ pub fn data_proc_imm(binst: u32) -> Inst {
                 0b00 => A64_SBFM,
                 0b01 => A64_BFM,
                 0b10 => A64_UBFM,
-                _ => panic!("data_proc_imm/Bitfield: neither SBFM, BFM or UBFM")
+                _ => return UNKNOWN_INST, // unallocated
             };
 
             let w32 = (inst.flags & W32) != 0;
             let immr: u8 = ((binst >> 16) & 0b111111) as u8;
             let imms: u8 = ((binst >> 10) & 0b111111) as u8;
+            // N must equal sf, and 32-bit fields stay below bit 32.
+            if ((binst >> 22) & 1 == 0) != w32 || (w32 && (immr >= 32 || imms >= 32)) {
+                return UNKNOWN_INST; // unallocated
+            }
             let rd = regRd(binst);
             let rn = regRn(binst);
             inst = find_bfm_alias(op, w32, rd, rn, immr, imms);
         }
         Extract => {
+            // Only op21 = 00, o0 = 0 with N equal to sf is allocated, and the
+            // 32-bit form takes lsb < 32.
+            let w32 = (inst.flags & W32) != 0;
+            let imms = (binst >> 10) & 0b111111;
+            if top3 & 0b011 != 0 || (binst >> 21) & 1 != 0 || ((binst >> 22) & 1 == 0) != w32 || (w32 && imms >= 32) {
+                return UNKNOWN_INST;
+            }
             inst.op = A64_EXTR;
-            inst.imm = ((binst >> 10) & 0b111111) as u64;
+            inst.imm = imms as u64;
             inst.rd = regRd(binst);
             inst.rn = regRn(binst);
             inst.rm = regRm(binst);
@@ -1455,6 +1497,467 @@ pub fn data_proc_imm(binst: u32) -> Inst {
     inst
 }
 
+// Shifted-register operands keep the shift type in Inst.shift and the
+// amount in Inst.imm.
+#[allow(non_snake_case)]
+pub fn data_proc_reg(binst: u32) -> Inst {
+    let mut inst = UNKNOWN_INST.clone();
+
+    let op0 = (binst >> 30) & 1;
+    let op1 = (binst >> 28) & 1;
+    let op2 = (binst >> 21) & 0b1111;
+    let op3 = (binst >> 10) & 0b111111;
+    let sf = (binst >> 31) & 1;
+    let S = (binst >> 29) & 1;
+
+    if sf == 0 {
+        inst.flags |= W32;
+    }
+    if S == 1 {
+        inst.flags |= SET_FLAGS;
+    }
+
+    if op1 == 0 {
+        let shift = ((binst >> 22) & 0b11) as u8;
+        let imm6 = ((binst >> 10) & 0b111111) as u64;
+        inst.rd = regRd(binst);
+        inst.rn = regRn(binst);
+        inst.rm = regRm(binst);
+
+        if (op2 & 0b1000) == 0 { // Logical (shifted register)
+            if sf == 0 && imm6 >= 32 {
+                return UNKNOWN_INST;
+            }
+            inst.shift = shift;
+            inst.imm = imm6;
+            let N = (binst >> 21) & 1;
+            inst.flags &= !SET_FLAGS; // only ANDS and BICS set flags
+            match ((binst >> 29) & 0b11, N) {
+                (0b00, 0) => inst.op = A64_AND_SHIFTED,
+                (0b00, _) => inst.op = A64_BIC,
+                (0b01, 0) => inst.op = A64_ORR_SHIFTED,
+                (0b01, _) => inst.op = A64_ORN,
+                (0b10, 0) => inst.op = A64_EOR_SHIFTED,
+                (0b10, _) => inst.op = A64_EON,
+                (_, N) => {
+                    inst.op = if N == 0 { A64_AND_SHIFTED } else { A64_BIC };
+                    inst.flags |= SET_FLAGS;
+                }
+            }
+
+            if inst.op == A64_AND_SHIFTED && (inst.flags & SET_FLAGS) != 0 && inst.rd == ZERO_REG {
+                inst.op = A64_TST_SHIFTED;
+            } else if inst.op == A64_ORR_SHIFTED && inst.rn == ZERO_REG && shift == 0 && imm6 == 0 {
+                inst.op = A64_MOV_REG;
+            } else if inst.op == A64_ORN && inst.rn == ZERO_REG {
+                inst.op = A64_MVN;
+            }
+            return inst;
+        }
+
+        let is_sub = (binst >> 30) & 1 == 1;
+        if (op2 & 0b0001) == 0 { // Add/subtract (shifted register)
+            if shift == Shift::SH_ROR || (sf == 0 && imm6 >= 32) {
+                return UNKNOWN_INST;
+            }
+            inst.shift = shift;
+            inst.imm = imm6;
+            inst.op = if is_sub { A64_SUB_SHIFTED } else { A64_ADD_SHIFTED };
+
+            if (inst.flags & SET_FLAGS) != 0 && inst.rd == ZERO_REG {
+                inst.op = if is_sub { A64_CMP_SHIFTED } else { A64_CMN_SHIFTED };
+            } else if is_sub && inst.rn == ZERO_REG {
+                inst.op = A64_NEG;
+            }
+            return inst;
+        }
+
+        // Add/subtract (extended register)
+        let opt = (binst >> 22) & 0b11;
+        let imm3 = (binst >> 10) & 0b111;
+        if opt != 0 || imm3 > 4 {
+            return UNKNOWN_INST;
+        }
+        inst.op = if is_sub { A64_SUB_EXT } else { A64_ADD_EXT };
+        inst.extend.typ = (binst >> 13) & 0b111;
+        inst.extend.lsl = imm3;
+        inst.rd = if (inst.flags & SET_FLAGS) != 0 { regRd(binst) } else { regRdSP(binst) };
+        inst.rn = regRnSP(binst);
+
+        if (inst.flags & SET_FLAGS) != 0 && inst.rd == ZERO_REG {
+            inst.op = if is_sub { A64_CMP_EXT } else { A64_CMN_EXT };
+        }
+        return inst;
+    }
+
+    inst.rd = regRd(binst);
+    inst.rn = regRn(binst);
+    inst.rm = regRm(binst);
+
+    match op2 {
+        0b0000 => {
+            if op3 == 0 { // Add/subtract (with carry)
+                let is_sbc = op0 == 1;
+                inst.op = if is_sbc { A64_SBC } else { A64_ADC };
+                if is_sbc && inst.rn == ZERO_REG {
+                    inst.op = A64_NGC;
+                }
+                return inst;
+            }
+            if (op3 & 0b011111) == 0b000001 { // Rotate right into flags
+                if sf == 0 || op0 == 1 || S == 0 || (binst >> 4) & 1 != 0 {
+                    return UNKNOWN_INST;
+                }
+                inst.op = A64_RMIF;
+                inst.rmif.ror = (binst >> 15) & 0b111111;
+                inst.rmif.mask = binst & 0b1111;
+                inst.flags &= !SET_FLAGS;
+                inst.rd = 0;
+                inst.rm = 0;
+                return inst;
+            }
+            if (op3 & 0b001111) == 0b000010 { // Evaluate into flags
+                if sf == 1 || op0 == 1 || S == 0 || (binst >> 15) & 0b111111 != 0 || binst & 0b11111 != 0b01101 {
+                    return UNKNOWN_INST;
+                }
+                inst.op = if (binst >> 14) & 1 == 0 { A64_SETF8 } else { A64_SETF16 };
+                inst.flags &= !(SET_FLAGS | W32);
+                inst.rd = 0;
+                inst.rm = 0;
+                return inst;
+            }
+            UNKNOWN_INST
+        }
+        0b0010 => { // Conditional compare (register and immediate)
+            if S == 0 || (binst >> 10) & 1 != 0 || (binst >> 4) & 1 != 0 {
+                return UNKNOWN_INST;
+            }
+            let is_imm = (binst >> 11) & 1 == 1;
+            inst.op = match (op0, is_imm) {
+                (0, false) => A64_CCMN_REG,
+                (0, true) => A64_CCMN_IMM,
+                (_, false) => A64_CCMP_REG,
+                (_, true) => A64_CCMP_IMM,
+            };
+            inst.flags = set_cond(inst.flags, ((binst >> 12) & 0b1111) as u8);
+            inst.flags &= !SET_FLAGS; // always set, so the bit is not needed
+            inst.ccmp.nzcv = binst & 0b1111;
+            if is_imm {
+                inst.ccmp.imm5 = (binst >> 16) & 0b11111;
+                inst.rm = 0;
+            }
+            inst.rd = 0;
+            inst
+        }
+        0b0100 => { // Conditional select
+            let op2_bits = (binst >> 10) & 0b11;
+            if S == 1 || op2_bits > 1 {
+                return UNKNOWN_INST;
+            }
+            let cond = ((binst >> 12) & 0b1111) as u8;
+            inst.flags = set_cond(inst.flags, cond);
+            let same = inst.rn == inst.rm;
+            let not_always = (cond >> 1) != 0b111; // neither AL nor NV
+            inst.op = match (op0, op2_bits) {
+                (0, 0) => A64_CSEL,
+                (0, _) if same && not_always && inst.rn == ZERO_REG => A64_CSET,
+                (0, _) if same && not_always => A64_CINC,
+                (0, _) => A64_CSINC,
+                (_, 0) if same && not_always && inst.rn == ZERO_REG => A64_CSETM,
+                (_, 0) if same && not_always => A64_CINV,
+                (_, 0) => A64_CSINV,
+                (_, _) if same && not_always => A64_CNEG,
+                (_, _) => A64_CSNEG,
+            };
+            if matches!(inst.op, A64_CSET | A64_CINC | A64_CSETM | A64_CINV | A64_CNEG) {
+                inst.flags = invert_cond(inst.flags);
+                inst.rm = 0;
+            }
+            inst
+        }
+        0b0110 | 0b0111 => {
+            if S == 1 {
+                return UNKNOWN_INST;
+            }
+            if op0 == 0 { // Data-processing (2 source)
+                inst.op = match op3 {
+                    0b000010 => A64_UDIV,
+                    0b000011 => A64_SDIV,
+                    0b001000 => A64_LSLV,
+                    0b001001 => A64_LSRV,
+                    0b001010 => A64_ASRV,
+                    0b001011 => A64_RORV,
+                    0b010000 | 0b010100 if sf == 0 => if op3 == 0b010000 { A64_CRC32B } else { A64_CRC32CB },
+                    0b010001 | 0b010101 if sf == 0 => if op3 == 0b010001 { A64_CRC32H } else { A64_CRC32CH },
+                    0b010010 | 0b010110 if sf == 0 => if op3 == 0b010010 { A64_CRC32W } else { A64_CRC32CW },
+                    0b010011 | 0b010111 if sf == 1 => if op3 == 0b010011 { A64_CRC32X } else { A64_CRC32CX },
+                    _ => return UNKNOWN_INST,
+                };
+                return inst;
+            }
+            // Data-processing (1 source)
+            if (binst >> 16) & 0b11111 != 0 {
+                return UNKNOWN_INST;
+            }
+            inst.op = match (op3, sf) {
+                (0b000000, _) => A64_RBIT,
+                (0b000001, _) => A64_REV16,
+                (0b000010, 0) => A64_REV,
+                (0b000010, _) => A64_REV32,
+                (0b000011, 1) => A64_REV,
+                (0b000100, _) => A64_CLZ,
+                (0b000101, _) => A64_CLS,
+                _ => return UNKNOWN_INST,
+            };
+            inst.rm = 0;
+            inst
+        }
+        _ if (op2 & 0b1000) != 0 => { // Data-processing (3 source)
+            if (binst >> 29) & 0b11 != 0 {
+                return UNKNOWN_INST;
+            }
+            let op31 = (binst >> 21) & 0b111;
+            let o0 = (binst >> 15) & 1;
+            inst.ra = ((binst >> 10) & 0b11111) as u8;
+            let no_ra = inst.ra == ZERO_REG;
+            inst.op = match (op31, o0) {
+                (0b000, 0) => if no_ra { A64_MUL } else { A64_MADD },
+                (0b000, _) => if no_ra { A64_MNEG } else { A64_MSUB },
+                (0b001, 0) if sf == 1 => if no_ra { A64_SMULL } else { A64_SMADDL },
+                (0b001, _) if sf == 1 => if no_ra { A64_SMNEGL } else { A64_SMSUBL },
+                (0b010, 0) if sf == 1 => A64_SMULH,
+                (0b101, 0) if sf == 1 => if no_ra { A64_UMULL } else { A64_UMADDL },
+                (0b101, _) if sf == 1 => if no_ra { A64_UMNEGL } else { A64_UMSUBL },
+                (0b110, 0) if sf == 1 => A64_UMULH,
+                _ => return UNKNOWN_INST,
+            };
+            inst
+        }
+        _ => UNKNOWN_INST,
+    }
+}
+
+/// B.cond: the condition goes in Inst.flags, the target in Inst.offset.
+pub fn cond_branch(binst: u32) -> Inst {
+    let mut inst = UNKNOWN_INST;
+    if (binst >> 4) & 1 != 0 {
+        return inst;
+    }
+    inst.op = A64_BCOND;
+    inst.flags = set_cond(0, (binst & 0b1111) as u8);
+    inst.offset = sext((((binst >> 5) & 0x7FFFF) << 2) as u64, 21);
+    inst
+}
+
+/// FCMP and FCMPE (scalar), with the precision in Inst.flags.prec. The
+/// zero variants leave Inst.rm at 0.
+pub fn fp_compare(binst: u32) -> Inst {
+    let mut inst = UNKNOWN_INST;
+    let prec = match (binst >> 22) & 0b11 {
+        0b00 => FPSize::FSZ_S,
+        0b01 => FPSize::FSZ_D,
+        0b11 => FPSize::FSZ_H,
+        _ => return inst,
+    };
+    let zero = (binst >> 3) & 1 != 0;
+    let signaling = (binst >> 4) & 1 != 0;
+    inst.op = match (zero, signaling) {
+        (false, false) => A64_FCMP_REG,
+        (true, false) => A64_FCMP_ZERO,
+        (false, true) => A64_FCMPE_REG,
+        (true, true) => A64_FCMPE_ZERO,
+    };
+    inst.flags = set_prec(0, prec);
+    inst.rn = ((binst >> 5) & 0b11111) as u8;
+    inst.rm = if zero { 0 } else { ((binst >> 16) & 0b11111) as u8 };
+    inst
+}
+
+/// Loads and stores of general-purpose and SIMD&FP registers: LDR/STR in
+/// every addressing mode, LDP/STP, LDAR/STLR and PRFM. Rt goes in Inst.rd,
+/// Rt2 in Inst.rt2 and the base in Inst.rn. Inst.flags.memext holds the
+/// access size and signedness as an ExtendType, or the FPSize for the FP
+/// variants, and Inst.offset the byte offset.
+pub fn loads_and_stores(binst: u32) -> Inst {
+    let mut inst = UNKNOWN_INST;
+    let size = (binst >> 30) as u8;
+    let fp = (binst >> 26) & 1 == 1;
+    inst.rd = regRd(binst);
+    inst.rn = regRnSP(binst);
+
+    match (binst >> 27) & 0b111 {
+        0b011 if (binst >> 24) & 0b11 == 0 => { // Load register (literal)
+            let (op, memext) = match (fp, size) {
+                (false, 0b00) => (A64_LDR, ExtendType::UXTW),
+                (false, 0b01) => (A64_LDR, ExtendType::UXTX),
+                (false, 0b10) => (A64_LDR, ExtendType::SXTW),
+                (false, _) => (A64_PRFM, 0),
+                (true, 0b00) => (A64_LDR_FP, FPSize::FSZ_S),
+                (true, 0b01) => (A64_LDR_FP, FPSize::FSZ_D),
+                (true, 0b10) => (A64_LDR_FP, FPSize::FSZ_Q),
+                (true, _) => return UNKNOWN_INST,
+            };
+            inst.op = op;
+            if !fp && size == 0b00 {
+                inst.flags |= W32;
+            }
+            inst.flags = set_mem_extend(inst.flags, memext);
+            inst.flags = set_addrmode(inst.flags, AddrMode::AM_LITERAL);
+            inst.offset = sext((((binst >> 5) & 0x7FFFF) << 2) as u64, 21);
+            inst.rn = 0;
+            inst
+        }
+        0b101 if (binst >> 25) & 1 == 0 => { // Load/store register pair
+            let load = (binst >> 22) & 1 == 1;
+            let mode = (binst >> 23) & 0b11;
+            let memext = match (fp, size) {
+                (false, 0b00) => ExtendType::UXTW,
+                (false, 0b01) if load && mode != 0b00 => ExtendType::SXTW, // LDPSW
+                (false, 0b10) => ExtendType::UXTX,
+                (true, 0b00) => FPSize::FSZ_S,
+                (true, 0b01) => FPSize::FSZ_D,
+                (true, 0b10) => FPSize::FSZ_Q,
+                _ => return UNKNOWN_INST,
+            };
+            inst.op = match (fp, load, mode) {
+                (false, false, 0b00) => A64_STNP,
+                (false, true, 0b00) => A64_LDNP,
+                (false, false, _) => A64_STP,
+                (false, true, _) => A64_LDP,
+                (true, false, 0b00) => A64_STNP_FP,
+                (true, true, 0b00) => A64_LDNP_FP,
+                (true, false, _) => A64_STP_FP,
+                (true, true, _) => A64_LDP_FP,
+            };
+            if !fp && size == 0b00 {
+                inst.flags |= W32;
+            }
+            let scale = if memext == FPSize::FSZ_Q { 4 } else { memext & 0b11 };
+            inst.flags = set_mem_extend(inst.flags, memext);
+            inst.flags = set_addrmode(inst.flags, match mode {
+                0b01 => AddrMode::AM_POST,
+                0b11 => AddrMode::AM_PRE,
+                _ => AddrMode::AM_OFF_IMM,
+            });
+            inst.offset = sext(((binst >> 15) & 0x7F) as u64, 7) << scale;
+            inst.rt2 = ((binst >> 10) & 0b11111) as u8;
+            inst
+        }
+        0b111 if (binst >> 25) & 1 == 0 => { // Load/store register
+            let opc = (binst >> 22) & 0b11;
+            let prfm = !fp && size == 0b11 && opc == 0b10;
+            let memext = if fp {
+                match (opc >> 1, size) {
+                    (0, _) => size,
+                    (_, 0b00) => FPSize::FSZ_Q,
+                    _ => return UNKNOWN_INST,
+                }
+            } else {
+                match (opc, size) {
+                    (0b00 | 0b01, _) => size,
+                    (0b10, 0b11) => 0,                           // PRFM
+                    (0b10, _) => (1 << 2) | size,                // LDRSB/H/W to X
+                    (0b11, 0b00 | 0b01) => (1 << 2) | size,      // LDRSB/H to W
+                    _ => return UNKNOWN_INST,
+                }
+            };
+            inst.op = match (fp, opc & 1 == 1) {
+                _ if prfm => A64_PRFM,
+                (false, false) if opc == 0b00 => A64_STR,
+                (false, _) => A64_LDR,
+                (true, false) => A64_STR_FP,
+                (true, true) => A64_LDR_FP,
+            };
+            if !fp && (opc == 0b11 || (opc < 0b10 && size != 0b11)) {
+                inst.flags |= W32;
+            }
+            let scale = if fp && memext == FPSize::FSZ_Q { 4 } else { size };
+            inst.flags = set_mem_extend(inst.flags, memext);
+
+            let mode = if (binst >> 24) & 1 == 1 { // unsigned immediate
+                inst.offset = (((binst >> 10) & 0xFFF) as i64) << scale;
+                AddrMode::AM_OFF_IMM
+            } else if (binst >> 21) & 1 == 0 { // 9-bit signed immediate
+                inst.offset = sext(((binst >> 12) & 0x1FF) as u64, 9);
+                match (binst >> 10) & 0b11 {
+                    0b00 => AddrMode::AM_OFF_IMM,
+                    0b01 if !prfm => AddrMode::AM_POST,
+                    0b11 if !prfm => AddrMode::AM_PRE,
+                    _ => return UNKNOWN_INST, // unprivileged
+                }
+            } else if (binst >> 10) & 0b11 == 0b10 { // register offset
+                let option = ((binst >> 13) & 0b111) as u8;
+                if option & 0b010 == 0 {
+                    return UNKNOWN_INST;
+                }
+                inst.rm = regRm(binst);
+                inst.extend.lsl = if (binst >> 12) & 1 == 1 { scale as u32 } else { 0 };
+                if option == ExtendType::UXTX {
+                    AddrMode::AM_OFF_REG
+                } else {
+                    // The option field encodes UXTW, SXTW and SXTX exactly
+                    // like ExtendType does.
+                    inst.extend.typ = option as u32;
+                    AddrMode::AM_OFF_EXT
+                }
+            } else {
+                return UNKNOWN_INST; // atomic memory operations
+            };
+            inst.flags = set_addrmode(inst.flags, mode);
+            inst
+        }
+        0b001 if (binst >> 23) & 0b1111111 == 0b0010001 && (binst >> 21) & 1 == 0 => {
+            // Load-acquire/store-release register, and the LORegion variants
+            let load = (binst >> 22) & 1 == 1;
+            let lo = (binst >> 15) & 1 == 0;
+            inst.op = if load { A64_LDR } else { A64_STR };
+            if size != 0b11 {
+                inst.flags |= W32;
+            }
+            inst.flags = set_mem_extend(inst.flags, size);
+            inst.flags = set_addrmode(inst.flags, AddrMode::AM_SIMPLE);
+            let ordering = match (load, lo) {
+                (true, false) => MemOrdering::MO_ACQUIRE,
+                (true, true) => MemOrdering::MO_LO_ACQUIRE,
+                (false, false) => MemOrdering::MO_RELEASE,
+                (false, true) => MemOrdering::MO_LO_RELEASE,
+            };
+            if load {
+                inst.ldst_order.load = ordering as u16;
+            } else {
+                inst.ldst_order.store = ordering as u16;
+            }
+            inst
+        }
+        _ => UNKNOWN_INST,
+    }
+}
+
+/// Decodes any instruction, dispatching on the top-level op0 field. Groups
+/// without a decoder yet come back as A64_UNKNOWN with the raw instruction
+/// in Inst.imm.
+pub fn decode(binst: u32) -> Inst {
+    let op0 = (binst >> 25) & 0b1111;
+    let mut inst = match op0 {
+        0b0000 if binst >> 16 == 0 => {
+            let mut inst = UNKNOWN_INST;
+            inst.op = A64_UDF;
+            inst.imm = (binst & 0xFFFF) as u64;
+            return inst;
+        }
+        0b1000 | 0b1001 => data_proc_imm(binst), // 100x
+        0b0101 | 0b1101 => data_proc_reg(binst), // x101
+        0b0100 | 0b0110 | 0b1100 | 0b1110 => loads_and_stores(binst), // x1x0
+        0b1010 if binst >> 24 == 0x54 => cond_branch(binst),
+        0b1111 if binst & 0xFF20_FC07 == 0x1E20_2000 => fp_compare(binst),
+        _ => UNKNOWN_INST,
+    };
+    if inst.op == Op::A64_UNKNOWN {
+        inst.imm = binst as u64;
+    }
+    inst
+}
+
 
 /// Returns the 0-based index of the highest bit. Should be compiled down
 /// to a single native instruction.
@@ -1464,18 +1967,21 @@ fn highest_bit(mut x: u32) -> i32 {
         x >>= 1;
         n += 1;
     }
-    return n;
+    n
 }
 
 /// Rotate the len-bit number x n places to the right. Based on the first
 /// example at https://en.wikipedia.org/wiki/Bitwise_operation#Circular_shifts
 /// (except turned around, to make it rotate right).
 fn ror(x: u64, n: u32, len: u32) -> u64 {
+    if n == 0 {
+        return x; // x << len would overflow for len = 64
+    }
     let raw = (x >> n) | (x << (len - n));
     if len == 64 {
         return raw;
     }
-    return raw & ((1u64 << len) - 1); // truncate left side to len bits
+    raw & ((1u64 << len) - 1) // truncate left side to len bits
 }
 
 
@@ -1483,8 +1989,10 @@ fn ror(x: u64, n: u32, len: u32) -> u64 {
 ///
 /// The logical immediate instructions encode 32-bit or 64-bit masks using merely
 /// 12 or 13 bits. We want the decoded mask in our Inst.imm field. We only need
-/// the "wmask" of DecodeBitMasks, so return only that.
-fn decode_bitmask(immN: u8, imms: u8, immr: u8, w32: bool) -> u64 {
+/// the "wmask" of DecodeBitMasks, so return only that, or None for the
+/// reserved encodings.
+#[allow(non_snake_case)]
+fn decode_bitmask(immN: u8, imms: u8, immr: u8, w32: bool) -> Option<u64> {
     let M: u32 = if w32 { 32 } else { 64 };
 
     // Guarantee it's only the number of bits in the pseudocode signature.
@@ -1492,18 +2000,25 @@ fn decode_bitmask(immN: u8, imms: u8, immr: u8, w32: bool) -> u64 {
     let imms = imms & 0b111111;
     let immr = immr & 0b111111;
 
-    // length of bitmask (1..6)
-    let len = highest_bit(((immN << 6) | ((!imms) & 0b111111)) as u32);
+    // length of bitmask (1..6); below 1 is reserved
+    let pattern = ((immN << 6) | ((!imms) & 0b111111)) as u32;
+    if pattern < 2 {
+        return None;
+    }
+    let len = highest_bit(pattern);
 
     // 1..6 consecutive ones, basis of pattern
     let mut levels = 0;
-    for i in 0..len {
+    for _ in 0..len {
         levels = (levels << 1) | 1;
     }
 
     let S: u32 = (imms & levels) as u32;
     let R: u32 = (immr & levels) as u32;
     let esize = 1 << len; // 2, 4, 8, 16, 32, 64
+    if S == levels as u32 {
+        return None; // an element of all ones is reserved
+    }
 
     // welem: pattern of 1s then zero-extended to esize
     // e.g. esize = 8; S+1 = 4 → welem = 0b00001111
@@ -1514,12 +2029,15 @@ fn decode_bitmask(immN: u8, imms: u8, immr: u8, w32: bool) -> u64 {
 
     // wmask = Replicate(ROR(welem, R));
     welem = ror(welem, R, esize);
+    if esize == 64 {
+        return Some(welem); // wmask << 64 would overflow
+    }
     let mut wmask = 0;
-    for i in (0..M).step_by(esize as usize) {
+    for _ in (0..M).step_by(esize as usize) {
         wmask = (wmask << esize) | welem;
     }
 
-    return wmask;
+    Some(wmask)
 }
 
 fn find_bfm_alias(op: Op, w32: bool, rd: u8, rn: u8, immr: u8, imms: u8) -> Inst {
@@ -1549,13 +2067,13 @@ fn find_bfm_alias(op: Op, w32: bool, rd: u8, rn: u8, immr: u8, imms: u8) -> Inst
 
     let sign = op == A64_SBFM;
 
-    if !sign && imms as u8 + 1 == immr as u8 && imms as u8 != all_ones {
+    if !sign && imms + 1 == immr && imms != all_ones {
         inst.op = A64_LSL_IMM;
         inst.imm = (all_ones - imms) as u64;
         return inst;
     }
 
-    if imms as u8 == all_ones {
+    if imms == all_ones {
         inst.op = if sign { A64_ASR_IMM } else { A64_LSR_IMM };
         inst.imm = immr as u64;
         return inst;
@@ -1580,10 +2098,10 @@ fn find_bfm_alias(op: Op, w32: bool, rd: u8, rn: u8, immr: u8, imms: u8) -> Inst
                 inst.extend.typ = if sign { SXTH } else { UXTH } as u32;
                 return inst;
             }
-            31 => {
+            31 if sign => { // there is no UXTW; the unsigned form is a UBFX
                 inst.op = A64_EXTEND;
                 inst.extend.typ = SXTW as u32;
-                return if sign { inst } else { panic!("find_bfm_alias: there is no UXTW instruction") };
+                return inst;
             }
             _ => {}
         }
@@ -0,0 +1,226 @@
+use crate::ir::{
+    BinaryOp, Block, BlockCall, Callee, CastOp, FloatCC, Function, InstId, InstKind, IntCC, MemoryOrder, Reg, ScalarType,
+    TrapKind, Type, UnaryOp, Value,
+};
+
+/// Appends instructions to the end of the current block of a [`Function`].
+/// Result types are inferred from the operands; whether the operands fit
+/// together is left to the verifier.
+pub struct FunctionBuilder<'f> {
+    pub func: &'f mut Function,
+    block: Option<Block>,
+}
+
+impl<'f> FunctionBuilder<'f> {
+    pub fn new(func: &'f mut Function) -> FunctionBuilder<'f> {
+        FunctionBuilder { func, block: None }
+    }
+
+    pub fn create_block(&mut self) -> Block {
+        self.func.add_block()
+    }
+
+    pub fn append_block_param(&mut self, block: Block, ty: Type) -> Value {
+        self.func.add_block_param(block, ty)
+    }
+
+    pub fn switch_to_block(&mut self, block: Block) {
+        self.block = Some(block);
+    }
+
+    pub fn current_block(&self) -> Option<Block> {
+        self.block
+    }
+
+    /// Whether the current block already ends in a terminator.
+    pub fn is_terminated(&self) -> bool {
+        self.block.is_some_and(|block| self.func.terminator(block).is_some())
+    }
+
+    pub fn value_type(&self, value: Value) -> Type {
+        self.func.value_type(value)
+    }
+
+    /// Appends `kind`, returning its result if it has one.
+    pub fn ins(&mut self, kind: InstKind) -> (InstId, Option<Value>) {
+        let block = self.block.expect("FunctionBuilder: no current block");
+        let ty = self.result_type(&kind);
+        let inst = self.func.append_inst(block, kind, ty);
+        (inst, self.func.inst(inst).result)
+    }
+
+    fn value(&mut self, kind: InstKind) -> Value {
+        self.ins(kind).1.expect("FunctionBuilder: instruction has no result")
+    }
+
+    fn result_type(&self, kind: &InstKind) -> Option<Type> {
+        let ty = |value: &Value| self.func.value_type(*value);
+        match kind {
+            InstKind::Const { ty, .. } | InstKind::Cast { ty, .. } | InstKind::Splat { ty, .. } => Some(*ty),
+            InstKind::Load { ty, .. } => Some(*ty),
+            InstKind::Unary { arg, .. } => Some(ty(arg)),
+            InstKind::Binary { lhs, .. } => Some(ty(lhs)),
+            InstKind::Icmp { lhs, .. } | InstKind::Fcmp { lhs, .. } => {
+                let lanes = ty(lhs).lanes();
+                Some(if ty(lhs).is_vector() { Type::vector(ScalarType::I1, lanes) } else { Type::I1 })
+            }
+            InstKind::Select { if_true, .. } => Some(ty(if_true)),
+            InstKind::ExtractLane { vector, .. } => Some(Type::Scalar(ty(vector).element())),
+            InstKind::InsertLane { vector, .. } => Some(ty(vector)),
+            InstKind::Crc32 { .. } => Some(Type::I32),
+            InstKind::ReadReg { reg } => Some(reg.ty()),
+            InstKind::Call { ret, .. } => *ret,
+            _ => None,
+        }
+    }
+
+    pub fn iconst(&mut self, ty: Type, imm: u128) -> Value {
+        let bits = match ty.bits() {
+            128 => imm,
+            width => imm & ((1u128 << width) - 1),
+        };
+        self.value(InstKind::Const { ty, bits })
+    }
+
+    pub fn fconst32(&mut self, value: f32) -> Value {
+        self.value(InstKind::Const { ty: Type::F32, bits: value.to_bits() as u128 })
+    }
+
+    pub fn fconst64(&mut self, value: f64) -> Value {
+        self.value(InstKind::Const { ty: Type::F64, bits: value.to_bits() as u128 })
+    }
+
+    pub fn bool(&mut self, value: bool) -> Value {
+        self.iconst(Type::I1, value as u128)
+    }
+
+    pub fn unary(&mut self, op: UnaryOp, arg: Value) -> Value {
+        self.value(InstKind::Unary { op, arg })
+    }
+
+    pub fn binary(&mut self, op: BinaryOp, lhs: Value, rhs: Value) -> Value {
+        self.value(InstKind::Binary { op, lhs, rhs })
+    }
+
+    pub fn add(&mut self, lhs: Value, rhs: Value) -> Value {
+        self.binary(BinaryOp::Add, lhs, rhs)
+    }
+
+    pub fn sub(&mut self, lhs: Value, rhs: Value) -> Value {
+        self.binary(BinaryOp::Sub, lhs, rhs)
+    }
+
+    pub fn and(&mut self, lhs: Value, rhs: Value) -> Value {
+        self.binary(BinaryOp::And, lhs, rhs)
+    }
+
+    pub fn or(&mut self, lhs: Value, rhs: Value) -> Value {
+        self.binary(BinaryOp::Or, lhs, rhs)
+    }
+
+    pub fn xor(&mut self, lhs: Value, rhs: Value) -> Value {
+        self.binary(BinaryOp::Xor, lhs, rhs)
+    }
+
+    pub fn not(&mut self, arg: Value) -> Value {
+        self.unary(UnaryOp::Not, arg)
+    }
+
+    pub fn icmp(&mut self, cond: IntCC, lhs: Value, rhs: Value) -> Value {
+        self.value(InstKind::Icmp { cond, lhs, rhs })
+    }
+
+    pub fn fcmp(&mut self, cond: FloatCC, lhs: Value, rhs: Value) -> Value {
+        self.value(InstKind::Fcmp { cond, lhs, rhs })
+    }
+
+    pub fn select(&mut self, cond: Value, if_true: Value, if_false: Value) -> Value {
+        self.value(InstKind::Select { cond, if_true, if_false })
+    }
+
+    pub fn cast(&mut self, op: CastOp, arg: Value, ty: Type) -> Value {
+        self.value(InstKind::Cast { op, arg, ty })
+    }
+
+    /// Zero-extends `arg` to `ty`, or returns it as is if it already has
+    /// that type.
+    pub fn zext(&mut self, arg: Value, ty: Type) -> Value {
+        if self.value_type(arg) == ty { arg } else { self.cast(CastOp::Zext, arg, ty) }
+    }
+
+    pub fn sext(&mut self, arg: Value, ty: Type) -> Value {
+        if self.value_type(arg) == ty { arg } else { self.cast(CastOp::Sext, arg, ty) }
+    }
+
+    pub fn trunc(&mut self, arg: Value, ty: Type) -> Value {
+        if self.value_type(arg) == ty { arg } else { self.cast(CastOp::Trunc, arg, ty) }
+    }
+
+    pub fn bitcast(&mut self, arg: Value, ty: Type) -> Value {
+        if self.value_type(arg) == ty { arg } else { self.cast(CastOp::Bitcast, arg, ty) }
+    }
+
+    pub fn extract_lane(&mut self, vector: Value, lane: u8) -> Value {
+        self.value(InstKind::ExtractLane { vector, lane })
+    }
+
+    pub fn insert_lane(&mut self, vector: Value, lane: u8, value: Value) -> Value {
+        self.value(InstKind::InsertLane { vector, lane, value })
+    }
+
+    pub fn splat(&mut self, ty: Type, value: Value) -> Value {
+        self.value(InstKind::Splat { ty, value })
+    }
+
+    pub fn crc32(&mut self, castagnoli: bool, crc: Value, data: Value) -> Value {
+        self.value(InstKind::Crc32 { castagnoli, crc, data })
+    }
+
+    pub fn read_reg(&mut self, reg: Reg) -> Value {
+        self.value(InstKind::ReadReg { reg })
+    }
+
+    pub fn write_reg(&mut self, reg: Reg, value: Value) {
+        self.ins(InstKind::WriteReg { reg, value });
+    }
+
+    pub fn load(&mut self, ty: Type, addr: Value, order: MemoryOrder) -> Value {
+        self.value(InstKind::Load { ty, addr, order })
+    }
+
+    pub fn store(&mut self, addr: Value, value: Value, order: MemoryOrder) {
+        self.ins(InstKind::Store { addr, value, order });
+    }
+
+    pub fn prefetch(&mut self, addr: Value, write: bool) {
+        self.ins(InstKind::Prefetch { addr, write });
+    }
+
+    pub fn fence(&mut self, order: MemoryOrder) {
+        self.ins(InstKind::Fence { order });
+    }
+
+    pub fn call(&mut self, callee: Callee, args: Vec<Value>, ret: Option<Type>) -> Option<Value> {
+        self.ins(InstKind::Call { callee, args, ret }).1
+    }
+
+    pub fn jump(&mut self, block: Block, args: Vec<Value>) {
+        self.ins(InstKind::Jump { dest: BlockCall { block, args } });
+    }
+
+    pub fn branch(&mut self, cond: Value, then_dest: BlockCall, else_dest: BlockCall) {
+        self.ins(InstKind::Branch { cond, then_dest, else_dest });
+    }
+
+    pub fn indirect_branch(&mut self, target: Value) {
+        self.ins(InstKind::IndirectBranch { target });
+    }
+
+    pub fn ret(&mut self) {
+        self.ins(InstKind::Return);
+    }
+
+    pub fn trap(&mut self, kind: TrapKind) {
+        self.ins(InstKind::Trap { kind });
+    }
+}
@@ -0,0 +1,666 @@
+use std::fmt;
+
+/// The type of a single lane: an integer of a given width or an IEEE float.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ScalarType {
+    I1,
+    I8,
+    I16,
+    I32,
+    I64,
+    I128,
+    F16,
+    F32,
+    F64,
+}
+
+impl ScalarType {
+    pub fn bits(self) -> u32 {
+        match self {
+            ScalarType::I1 => 1,
+            ScalarType::I8 => 8,
+            ScalarType::I16 | ScalarType::F16 => 16,
+            ScalarType::I32 | ScalarType::F32 => 32,
+            ScalarType::I64 | ScalarType::F64 => 64,
+            ScalarType::I128 => 128,
+        }
+    }
+
+    pub fn is_float(self) -> bool {
+        matches!(self, ScalarType::F16 | ScalarType::F32 | ScalarType::F64)
+    }
+
+    /// The integer type of the given width, if there is one.
+    pub fn int(bits: u32) -> Option<ScalarType> {
+        Some(match bits {
+            1 => ScalarType::I1,
+            8 => ScalarType::I8,
+            16 => ScalarType::I16,
+            32 => ScalarType::I32,
+            64 => ScalarType::I64,
+            128 => ScalarType::I128,
+            _ => return None,
+        })
+    }
+}
+
+/// The type of an SSA value. Vectors are at most 128 bits, like the guest's
+/// SIMD registers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Type {
+    Scalar(ScalarType),
+    Vector { element: ScalarType, lanes: u8 },
+}
+
+impl Type {
+    pub const I1: Type = Type::Scalar(ScalarType::I1);
+    pub const I8: Type = Type::Scalar(ScalarType::I8);
+    pub const I16: Type = Type::Scalar(ScalarType::I16);
+    pub const I32: Type = Type::Scalar(ScalarType::I32);
+    pub const I64: Type = Type::Scalar(ScalarType::I64);
+    pub const I128: Type = Type::Scalar(ScalarType::I128);
+    pub const F16: Type = Type::Scalar(ScalarType::F16);
+    pub const F32: Type = Type::Scalar(ScalarType::F32);
+    pub const F64: Type = Type::Scalar(ScalarType::F64);
+
+    pub fn vector(element: ScalarType, lanes: u8) -> Type {
+        Type::Vector { element, lanes }
+    }
+
+    pub fn int(bits: u32) -> Option<Type> {
+        ScalarType::int(bits).map(Type::Scalar)
+    }
+
+    pub fn bits(self) -> u32 {
+        match self {
+            Type::Scalar(scalar) => scalar.bits(),
+            Type::Vector { element, lanes } => element.bits() * lanes as u32,
+        }
+    }
+
+    pub fn element(self) -> ScalarType {
+        match self {
+            Type::Scalar(scalar) | Type::Vector { element: scalar, .. } => scalar,
+        }
+    }
+
+    pub fn lanes(self) -> u8 {
+        match self {
+            Type::Scalar(_) => 1,
+            Type::Vector { lanes, .. } => lanes,
+        }
+    }
+
+    pub fn is_vector(self) -> bool {
+        matches!(self, Type::Vector { .. })
+    }
+
+    /// Integer scalars and vectors of integers.
+    pub fn is_int(self) -> bool {
+        !self.element().is_float()
+    }
+
+    pub fn is_float(self) -> bool {
+        self.element().is_float()
+    }
+}
+
+impl fmt::Display for ScalarType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let prefix = if self.is_float() { 'f' } else { 'i' };
+        write!(f, "{}{}", prefix, self.bits())
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Scalar(scalar) => write!(f, "{}", scalar),
+            Type::Vector { element, lanes } => write!(f, "{}x{}", element, lanes),
+        }
+    }
+}
+
+/// An SSA value, defined once by an instruction or a block parameter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Value(pub u32);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Block(pub u32);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct InstId(pub u32);
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "v{}", self.0)
+    }
+}
+
+impl fmt::Display for Block {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "block{}", self.0)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Flag {
+    N,
+    Z,
+    C,
+    V,
+}
+
+/// Guest architectural state that lifted code reads and writes. Each
+/// register is one IR value; the W views of X registers are expressed with
+/// truncation and zero-extension.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Reg {
+    /// X0 to X30.
+    X(u8),
+    Sp,
+    /// The 128-bit SIMD&FP registers V0 to V31.
+    V(u8),
+    Flag(Flag),
+    Fpcr,
+    Fpsr,
+    TpidrEl0,
+    TpidrroEl0,
+}
+
+impl Reg {
+    pub fn ty(self) -> Type {
+        match self {
+            Reg::V(_) => Type::I128,
+            Reg::Flag(_) => Type::I1,
+            _ => Type::I64,
+        }
+    }
+}
+
+impl fmt::Display for Reg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reg::X(n) => write!(f, "x{}", n),
+            Reg::Sp => write!(f, "sp"),
+            Reg::V(n) => write!(f, "v{}", n),
+            Reg::Flag(flag) => write!(f, "{}f", lower(flag)),
+            Reg::Fpcr => write!(f, "fpcr"),
+            Reg::Fpsr => write!(f, "fpsr"),
+            Reg::TpidrEl0 => write!(f, "tpidr_el0"),
+            Reg::TpidrroEl0 => write!(f, "tpidrro_el0"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum UnaryOp {
+    /// Bitwise not; logical not on i1.
+    Not,
+    Neg,
+    /// Count leading zeros.
+    Clz,
+    /// Count leading sign bits, not counting the sign bit itself.
+    Cls,
+    Popcount,
+    BitReverse,
+    ByteSwap,
+    FNeg,
+    FAbs,
+    FSqrt,
+}
+
+/// Division by zero yields zero and the signed `MIN / -1` overflow yields
+/// `MIN`, as on AArch64. Shift and rotate amounts are taken modulo the
+/// operand width.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    /// The high half of the double-width unsigned product.
+    UMulHigh,
+    SMulHigh,
+    UDiv,
+    SDiv,
+    And,
+    Or,
+    Xor,
+    Shl,
+    LShr,
+    AShr,
+    RotR,
+    FAdd,
+    FSub,
+    FMul,
+    FDiv,
+    FMin,
+    FMax,
+}
+
+impl BinaryOp {
+    pub fn is_float(self) -> bool {
+        matches!(self, BinaryOp::FAdd | BinaryOp::FSub | BinaryOp::FMul | BinaryOp::FDiv | BinaryOp::FMin | BinaryOp::FMax)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum IntCC {
+    Eq,
+    Ne,
+    Ult,
+    Ule,
+    Ugt,
+    Uge,
+    Slt,
+    Sle,
+    Sgt,
+    Sge,
+}
+
+/// `Ne` and `Uno` hold for unordered operands, every other condition
+/// is false if either operand is NaN.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FloatCC {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Ord,
+    Uno,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CastOp {
+    Zext,
+    Sext,
+    Trunc,
+    /// Reinterpret the bits as another type of the same width.
+    Bitcast,
+    FpExt,
+    FpTrunc,
+    /// Float to integer conversions round toward zero and saturate.
+    FpToSi,
+    FpToUi,
+    SiToFp,
+    UiToFp,
+}
+
+/// The ordering constraint of a memory access, in C++11 terms.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum MemoryOrder {
+    /// A plain access with no ordering beyond single-copy atomicity.
+    #[default]
+    Plain,
+    Acquire,
+    Release,
+    AcqRel,
+    SeqCst,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Callee {
+    /// A guest function at a fixed address.
+    Guest(u64),
+    /// A guest function at a computed address.
+    Indirect(Value),
+    /// `SVC #imm`: hands control to the kernel emulation.
+    Supervisor(u16),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TrapKind {
+    /// UDF, or an instruction the lifter does not understand.
+    Undefined,
+    /// BRK #imm.
+    Breakpoint(u16),
+    /// Code the lifter has proven to be unreachable.
+    Unreachable,
+}
+
+/// A branch target and the arguments passed to its block parameters.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct BlockCall {
+    pub block: Block,
+    pub args: Vec<Value>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum InstKind {
+    /// Integers and floats alike are given by their bit pattern.
+    Const { ty: Type, bits: u128 },
+    Unary { op: UnaryOp, arg: Value },
+    Binary { op: BinaryOp, lhs: Value, rhs: Value },
+    Icmp { cond: IntCC, lhs: Value, rhs: Value },
+    Fcmp { cond: FloatCC, lhs: Value, rhs: Value },
+    Select { cond: Value, if_true: Value, if_false: Value },
+    Cast { op: CastOp, arg: Value, ty: Type },
+    ExtractLane { vector: Value, lane: u8 },
+    InsertLane { vector: Value, lane: u8, value: Value },
+    Splat { ty: Type, value: Value },
+    /// One step of the CRC-32 (or CRC-32C) checksum over all bytes of `data`;
+    /// `crc` and the result are i32.
+    Crc32 { castagnoli: bool, crc: Value, data: Value },
+    ReadReg { reg: Reg },
+    WriteReg { reg: Reg, value: Value },
+    /// Reads `ty` from the 64-bit guest virtual address `addr`, as laid out by
+    /// the module's [`MemoryModel`].
+    Load { ty: Type, addr: Value, order: MemoryOrder },
+    Store { addr: Value, value: Value, order: MemoryOrder },
+    /// A hint that `addr` will soon be read, or written if `write` is set.
+    Prefetch { addr: Value, write: bool },
+    Fence { order: MemoryOrder },
+    Call { callee: Callee, args: Vec<Value>, ret: Option<Type> },
+
+    // Terminators.
+    Jump { dest: BlockCall },
+    Branch { cond: Value, then_dest: BlockCall, else_dest: BlockCall },
+    /// Continue guest execution at a computed address outside the function.
+    IndirectBranch { target: Value },
+    /// Leave the function; its results live in guest registers.
+    Return,
+    Trap { kind: TrapKind },
+}
+
+impl InstKind {
+    pub fn is_terminator(&self) -> bool {
+        matches!(
+            self,
+            InstKind::Jump { .. }
+                | InstKind::Branch { .. }
+                | InstKind::IndirectBranch { .. }
+                | InstKind::Return
+                | InstKind::Trap { .. }
+        )
+    }
+
+    /// Every value the instruction reads, including branch arguments.
+    pub fn arguments(&self) -> Vec<Value> {
+        match self {
+            InstKind::Const { .. }
+            | InstKind::ReadReg { .. }
+            | InstKind::Fence { .. }
+            | InstKind::Return
+            | InstKind::Trap { .. } => vec![],
+            InstKind::Unary { arg, .. } | InstKind::Cast { arg, .. } => vec![*arg],
+            InstKind::Binary { lhs, rhs, .. } | InstKind::Icmp { lhs, rhs, .. } | InstKind::Fcmp { lhs, rhs, .. } => {
+                vec![*lhs, *rhs]
+            }
+            InstKind::Select { cond, if_true, if_false } => vec![*cond, *if_true, *if_false],
+            InstKind::ExtractLane { vector, .. } => vec![*vector],
+            InstKind::InsertLane { vector, value, .. } => vec![*vector, *value],
+            InstKind::Crc32 { crc, data, .. } => vec![*crc, *data],
+            InstKind::Splat { value, .. } | InstKind::WriteReg { value, .. } => vec![*value],
+            InstKind::Load { addr, .. } | InstKind::Prefetch { addr, .. } => vec![*addr],
+            InstKind::Store { addr, value, .. } => vec![*addr, *value],
+            InstKind::Call { callee, args, .. } => {
+                let mut values = match callee {
+                    Callee::Indirect(target) => vec![*target],
+                    _ => vec![],
+                };
+                values.extend(args);
+                values
+            }
+            InstKind::Jump { dest } => dest.args.clone(),
+            InstKind::Branch { cond, then_dest, else_dest } => {
+                let mut values = vec![*cond];
+                values.extend(&then_dest.args);
+                values.extend(&else_dest.args);
+                values
+            }
+            InstKind::IndirectBranch { target } => vec![*target],
+        }
+    }
+
+    pub fn successors(&self) -> Vec<&BlockCall> {
+        match self {
+            InstKind::Jump { dest } => vec![dest],
+            InstKind::Branch { then_dest, else_dest, .. } => vec![then_dest, else_dest],
+            _ => vec![],
+        }
+    }
+
+    /// Whether the instruction must be kept even when its result is unused.
+    pub fn has_side_effects(&self) -> bool {
+        matches!(
+            self,
+            InstKind::WriteReg { .. } | InstKind::Load { .. } | InstKind::Store { .. } | InstKind::Fence { .. } | InstKind::Call { .. }
+        ) || self.is_terminator()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InstData {
+    pub kind: InstKind,
+    pub result: Option<Value>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ValueDef {
+    Inst(InstId),
+    Param(Block, usize),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct ValueData {
+    ty: Type,
+    def: ValueDef,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct BlockData {
+    params: Vec<Value>,
+    insts: Vec<InstId>,
+}
+
+/// A lifted function in SSA form. The first block is the entry; values
+/// flow between blocks through block parameters.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Function {
+    pub name: String,
+    /// The guest address the function was lifted from.
+    pub address: u64,
+    blocks: Vec<BlockData>,
+    insts: Vec<InstData>,
+    values: Vec<ValueData>,
+}
+
+impl Function {
+    pub fn new(name: &str, address: u64) -> Function {
+        Function { name: name.to_string(), address, ..Default::default() }
+    }
+
+    pub fn entry_block(&self) -> Option<Block> {
+        (!self.blocks.is_empty()).then_some(Block(0))
+    }
+
+    pub fn blocks(&self) -> impl Iterator<Item = Block> {
+        (0..self.blocks.len() as u32).map(Block)
+    }
+
+    pub fn num_blocks(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn block_params(&self, block: Block) -> &[Value] {
+        &self.blocks[block.0 as usize].params
+    }
+
+    pub fn block_insts(&self, block: Block) -> &[InstId] {
+        &self.blocks[block.0 as usize].insts
+    }
+
+    /// The last instruction of `block`, if it is a terminator.
+    pub fn terminator(&self, block: Block) -> Option<&InstKind> {
+        let last = self.block_insts(block).last()?;
+        Some(&self.inst(*last).kind).filter(|kind| kind.is_terminator())
+    }
+
+    pub fn inst(&self, inst: InstId) -> &InstData {
+        &self.insts[inst.0 as usize]
+    }
+
+    pub fn num_values(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn value_type(&self, value: Value) -> Type {
+        self.values[value.0 as usize].ty
+    }
+
+    pub fn value_def(&self, value: Value) -> ValueDef {
+        self.values[value.0 as usize].def
+    }
+
+    /// Whether `value` names a value of this function at all.
+    pub fn is_valid(&self, value: Value) -> bool {
+        (value.0 as usize) < self.values.len()
+    }
+
+    pub(crate) fn add_block(&mut self) -> Block {
+        self.blocks.push(BlockData::default());
+        Block(self.blocks.len() as u32 - 1)
+    }
+
+    pub(crate) fn add_block_param(&mut self, block: Block, ty: Type) -> Value {
+        let index = self.blocks[block.0 as usize].params.len();
+        let value = self.add_value(ty, ValueDef::Param(block, index));
+        self.blocks[block.0 as usize].params.push(value);
+        value
+    }
+
+    pub(crate) fn append_inst(&mut self, block: Block, kind: InstKind, result: Option<Type>) -> InstId {
+        let inst = InstId(self.insts.len() as u32);
+        let result = result.map(|ty| self.add_value(ty, ValueDef::Inst(inst)));
+        self.insts.push(InstData { kind, result });
+        self.blocks[block.0 as usize].insts.push(inst);
+        inst
+    }
+
+    fn add_value(&mut self, ty: Type, def: ValueDef) -> Value {
+        self.values.push(ValueData { ty, def });
+        Value(self.values.len() as u32 - 1)
+    }
+}
+
+/// What lifted code assumes about guest memory. Loads and stores only ever
+/// see guest virtual addresses, never host pointers, so a backend is free to
+/// map them with a fixed offset, a page table walk or a call into the
+/// emulator.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct MemoryModel {
+    /// Significant bits of a guest virtual address.
+    pub address_bits: u8,
+    /// Whether values wider than a byte are stored little-endian.
+    pub little_endian: bool,
+    /// Whether plain accesses may be unaligned. Accesses with any other
+    /// [`MemoryOrder`] must always be naturally aligned.
+    pub unaligned: bool,
+}
+
+impl MemoryModel {
+    /// A Horizon process: a 39-bit little-endian address space with
+    /// unaligned access to normal memory.
+    pub const HORIZON: MemoryModel = MemoryModel { address_bits: 39, little_endian: true, unaligned: true };
+}
+
+impl Default for MemoryModel {
+    fn default() -> MemoryModel {
+        MemoryModel::HORIZON
+    }
+}
+
+/// A set of lifted functions, keyed by guest address.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Module {
+    pub functions: Vec<Function>,
+    pub memory: MemoryModel,
+}
+
+impl Module {
+    pub fn function_at(&self, address: u64) -> Option<&Function> {
+        self.functions.iter().find(|f| f.address == address)
+    }
+}
+
+impl fmt::Display for BlockCall {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.block)?;
+        if !self.args.is_empty() {
+            write!(f, "({})", comma_separated(&self.args))?;
+        }
+        Ok(())
+    }
+}
+
+fn comma_separated<T: fmt::Display>(items: &[T]) -> String {
+    items.iter().map(|item| item.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+fn lower(value: impl fmt::Debug) -> String {
+    format!("{:?}", value).to_lowercase()
+}
+
+impl fmt::Display for InstKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InstKind::Const { ty, bits } => write!(f, "const.{} {:#x}", ty, bits),
+            InstKind::Unary { op, arg } => write!(f, "{} {}", lower(op), arg),
+            InstKind::Binary { op, lhs, rhs } => write!(f, "{} {}, {}", lower(op), lhs, rhs),
+            InstKind::Icmp { cond, lhs, rhs } => write!(f, "icmp {} {}, {}", lower(cond), lhs, rhs),
+            InstKind::Fcmp { cond, lhs, rhs } => write!(f, "fcmp {} {}, {}", lower(cond), lhs, rhs),
+            InstKind::Select { cond, if_true, if_false } => write!(f, "select {}, {}, {}", cond, if_true, if_false),
+            InstKind::Cast { op, arg, ty } => write!(f, "{} {} to {}", lower(op), arg, ty),
+            InstKind::ExtractLane { vector, lane } => write!(f, "extract_lane {}, {}", vector, lane),
+            InstKind::InsertLane { vector, lane, value } => write!(f, "insert_lane {}, {}, {}", vector, lane, value),
+            InstKind::Splat { ty, value } => write!(f, "splat.{} {}", ty, value),
+            InstKind::Crc32 { castagnoli, crc, data } => {
+                write!(f, "{} {}, {}", if *castagnoli { "crc32c" } else { "crc32" }, crc, data)
+            }
+            InstKind::ReadReg { reg } => write!(f, "read_reg {}", reg),
+            InstKind::WriteReg { reg, value } => write!(f, "write_reg {}, {}", reg, value),
+            InstKind::Load { ty, addr, order } => write!(f, "load.{} {} [{}]", ty, lower(order), addr),
+            InstKind::Store { addr, value, order } => write!(f, "store {} [{}], {}", lower(order), addr, value),
+            InstKind::Prefetch { addr, write } => write!(f, "prefetch {} [{}]", if *write { "write" } else { "read" }, addr),
+            InstKind::Fence { order } => write!(f, "fence {}", lower(order)),
+            InstKind::Call { callee, args, .. } => {
+                match callee {
+                    Callee::Guest(address) => write!(f, "call {:#x}", address)?,
+                    Callee::Indirect(target) => write!(f, "call_indirect {}", target)?,
+                    Callee::Supervisor(imm) => write!(f, "svc {:#x}", imm)?,
+                }
+                write!(f, "({})", comma_separated(args))
+            }
+            InstKind::Jump { dest } => write!(f, "jump {}", dest),
+            InstKind::Branch { cond, then_dest, else_dest } => write!(f, "branch {}, {}, {}", cond, then_dest, else_dest),
+            InstKind::IndirectBranch { target } => write!(f, "indirect_branch {}", target),
+            InstKind::Return => write!(f, "return"),
+            InstKind::Trap { kind } => write!(f, "trap {}", lower(kind)),
+        }
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "function {} @ {:#x} {{", self.name, self.address)?;
+        for block in self.blocks() {
+            let params: Vec<_> =
+                self.block_params(block).iter().map(|v| format!("{}: {}", v, self.value_type(*v))).collect();
+            if params.is_empty() {
+                writeln!(f, "{}:", block)?;
+            } else {
+                writeln!(f, "{}({}):", block, params.join(", "))?;
+            }
+            for inst in self.block_insts(block) {
+                let data = self.inst(*inst);
+                match data.result {
+                    Some(value) => writeln!(f, "    {}: {} = {}", value, self.value_type(value), data.kind)?,
+                    None => writeln!(f, "    {}", data.kind)?,
+                }
+            }
+        }
+        writeln!(f, "}}")
+    }
+}
@@ -1,5 +1,27 @@
-mod aarch64_reader;
+use nx_utils::executable::Executable;
+use nx_utils::loader::ProcessImage;
 
-pub fn convertProgram() {
-    // TODO: give it some abstracted form of an executable
-}
\ No newline at end of file
+use crate::ir::Module;
+use crate::lifter::FlagsMode;
+
+pub mod aarch64_reader;
+pub mod builder;
+pub mod ir;
+pub mod lifter;
+pub mod verifier;
+
+/// Lifts the code at the executable's entry point, as loaded at the default
+/// process base.
+#[allow(non_snake_case)]
+pub fn convertProgram(executable: &dyn Executable) -> Module {
+    let entry = executable.entry_point();
+    let mut module = Module::default();
+    for segment in executable.segments().into_iter().filter(|s| s.permissions.execute) {
+        if (segment.vaddr..segment.vaddr + segment.data.len() as u64).contains(&entry) {
+            let name = executable.module_name().unwrap_or_else(|| "entry".to_string());
+            let code = &segment.data[(entry - segment.vaddr) as usize..];
+            module.functions.push(lifter::lift_function(&name, code, ProcessImage::DEFAULT_BASE + entry, FlagsMode::Lazy));
+        }
+    }
+    module
+}
@@ -0,0 +1,889 @@
+use crate::aarch64_reader::AddrMode::{AM_LITERAL, AM_OFF_EXT, AM_OFF_IMM, AM_OFF_REG, AM_POST, AM_PRE, AM_SIMPLE};
+use crate::aarch64_reader::Cond::COND_AL;
+use crate::aarch64_reader::ExtendType::{SXTB, SXTH, SXTW};
+use crate::aarch64_reader::FPSize::{FSZ_D, FSZ_H, FSZ_Q};
+use crate::aarch64_reader::FlagMasks::{SET_FLAGS, W32};
+use crate::aarch64_reader::Op::*;
+use crate::aarch64_reader::Registries::{STACK_POINTER, ZERO_REG};
+use crate::aarch64_reader::Shift::{SH_ASR, SH_LSL, SH_LSR, SH_ROR};
+use crate::aarch64_reader::Size::{SZ_B, SZ_H, SZ_X};
+use crate::aarch64_reader::{decode, fad_get_addrmode, fad_get_cond, fad_get_mem_extend, fad_get_prec, Inst, MemOrdering};
+use crate::builder::FunctionBuilder;
+use crate::ir::{BinaryOp, BlockCall, Flag, FloatCC, Function, IntCC, MemoryOrder, Reg, TrapKind, Type, UnaryOp, Value};
+
+/// How the lifter keeps the guest's NZCV flags up to date.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FlagsMode {
+    /// Every flag-setting instruction writes all four flag registers.
+    Faithful,
+    /// Flag registers are only written when control leaves the lifted code.
+    /// Within it, consumers compute the flags they need from the operands
+    /// of the last flag-setting instruction.
+    Lazy,
+}
+
+/// Where the current NZCV flags came from, so consumers can compute a
+/// condition from the compared values instead of from the four flags.
+#[derive(Clone, Copy, Debug)]
+enum FlagSource {
+    /// `lhs + rhs`, or `lhs - rhs` for a subtraction (ADDS, SUBS, CMP, CMN).
+    AddSub { lhs: Value, rhs: Value, result: Value, sub: bool },
+    /// `lhs + rhs + carry` (ADCS, and SBCS with `rhs` inverted).
+    AddCarry { lhs: Value, rhs: Value, carry: Value, result: Value },
+    /// A logical result (ANDS, BICS, TST): N and Z from it, C and V clear.
+    Logic { result: Value },
+    /// A floating-point comparison (FCMP, FCMPE).
+    Float { lhs: Value, rhs: Value },
+    /// The four flags as i1 values, in NZCV order.
+    Values([Value; 4]),
+}
+
+const NZCV: [Flag; 4] = [Flag::N, Flag::Z, Flag::C, Flag::V];
+
+/// Translates decoded instructions into IR, appending to the current block
+/// of `builder`. Guest registers are read and written through `ReadReg` and
+/// `WriteReg`; W-register results are zero-extended into the X register.
+pub struct Lifter<'f> {
+    pub builder: FunctionBuilder<'f>,
+    pub mode: FlagsMode,
+    /// The last flag-setting instruction, if it was lifted in this function.
+    flags: Option<FlagSource>,
+}
+
+fn operand_type(inst: &Inst) -> Type {
+    if inst.flags & W32 != 0 { Type::I32 } else { Type::I64 }
+}
+
+fn low_mask(width: u32) -> u128 {
+    (1u128 << width) - 1
+}
+
+/// The integer comparison a condition amounts to after `CMP lhs, rhs`, for
+/// the conditions that do not depend on the sign of the difference.
+fn compare_cc(cond: u8) -> Option<IntCC> {
+    let cc = match cond >> 1 {
+        0b000 => IntCC::Eq,
+        0b001 => IntCC::Uge,
+        0b100 => IntCC::Ugt,
+        0b101 => IntCC::Sge,
+        0b110 => IntCC::Sgt,
+        _ => return None,
+    };
+    Some(if cond & 1 == 0 { cc } else { invert_cc(cc) })
+}
+
+fn invert_cc(cc: IntCC) -> IntCC {
+    match cc {
+        IntCC::Eq => IntCC::Ne,
+        IntCC::Ne => IntCC::Eq,
+        IntCC::Ult => IntCC::Uge,
+        IntCC::Ule => IntCC::Ugt,
+        IntCC::Ugt => IntCC::Ule,
+        IntCC::Uge => IntCC::Ult,
+        IntCC::Slt => IntCC::Sge,
+        IntCC::Sle => IntCC::Sgt,
+        IntCC::Sgt => IntCC::Sle,
+        IntCC::Sge => IntCC::Slt,
+    }
+}
+
+/// The float comparison a condition amounts to after `FCMP lhs, rhs`, and
+/// whether its result is negated. FCMP sets NZCV to 0110 for equal, 1000
+/// for less than, 0010 for greater than and 0011 for unordered.
+fn float_cc(cond: u8) -> (FloatCC, bool) {
+    let (cc, negate) = match cond >> 1 {
+        0b000 => (FloatCC::Eq, false),
+        0b001 => (FloatCC::Lt, true),
+        0b010 => (FloatCC::Lt, false),
+        0b011 => (FloatCC::Uno, false),
+        0b100 => (FloatCC::Le, true),
+        0b101 => (FloatCC::Ge, false),
+        _ => (FloatCC::Gt, false),
+    };
+    (cc, negate != (cond & 1 != 0))
+}
+
+impl<'f> Lifter<'f> {
+    /// Starts lifting into a new block of `func`.
+    pub fn new(func: &'f mut Function, mode: FlagsMode) -> Lifter<'f> {
+        let mut builder = FunctionBuilder::new(func);
+        let block = builder.create_block();
+        builder.switch_to_block(block);
+        Lifter { builder, mode, flags: None }
+    }
+
+    /// Reads a general-purpose register as i32 or i64. The zero register
+    /// reads as a constant.
+    pub fn get(&mut self, reg: u8, ty: Type) -> Value {
+        let value = match reg {
+            ZERO_REG => return self.builder.iconst(ty, 0),
+            STACK_POINTER => self.builder.read_reg(Reg::Sp),
+            n => self.builder.read_reg(Reg::X(n)),
+        };
+        self.builder.trunc(value, ty)
+    }
+
+    /// Writes a general-purpose register, zero-extending 32-bit values.
+    /// Writes to the zero register are dropped.
+    pub fn set(&mut self, reg: u8, value: Value) {
+        let value = self.builder.zext(value, Type::I64);
+        match reg {
+            ZERO_REG => {}
+            STACK_POINTER => self.builder.write_reg(Reg::Sp, value),
+            n => self.builder.write_reg(Reg::X(n), value),
+        }
+    }
+
+    /// Reads the low bits of a SIMD&FP register as a float of type `ty`.
+    pub fn get_float(&mut self, reg: u8, ty: Type) -> Value {
+        let value = self.builder.read_reg(Reg::V(reg));
+        let bits = self.builder.trunc(value, Type::int(ty.bits()).unwrap());
+        self.builder.bitcast(bits, ty)
+    }
+
+    fn imm(&mut self, ty: Type, imm: u64) -> Value {
+        self.builder.iconst(ty, imm as u128)
+    }
+
+    fn binary(&mut self, op: BinaryOp, lhs: Value, rhs: Value) -> Value {
+        self.builder.binary(op, lhs, rhs)
+    }
+
+    /// A register operand with its LSL/LSR/ASR/ROR amount applied.
+    fn shifted(&mut self, reg: u8, shift: u8, amount: u64, ty: Type) -> Value {
+        let value = self.get(reg, ty);
+        if amount == 0 {
+            return value;
+        }
+        let amount = self.imm(ty, amount);
+        let op = match shift {
+            SH_LSL => BinaryOp::Shl,
+            SH_LSR => BinaryOp::LShr,
+            SH_ASR => BinaryOp::AShr,
+            _ => BinaryOp::RotR,
+        };
+        self.binary(op, value, amount)
+    }
+
+    /// A register operand extended from a byte, halfword, word or doubleword
+    /// and shifted left by up to 4.
+    fn extended(&mut self, reg: u8, typ: u8, lsl: u32, ty: Type) -> Value {
+        let size = typ & 0b11;
+        let source = if size == SZ_X { ty } else { Type::I32 };
+        let value = self.get(reg, source);
+        let from = Type::int((8 << size).min(ty.bits())).unwrap();
+        let narrow = self.builder.trunc(value, from);
+        let value = if typ & 0b100 != 0 { self.builder.sext(narrow, ty) } else { self.builder.zext(narrow, ty) };
+        if lsl == 0 {
+            return value;
+        }
+        let lsl = self.imm(ty, lsl as u64);
+        self.binary(BinaryOp::Shl, value, lsl)
+    }
+
+    fn bit(&mut self, value: Value, index: u32) -> Value {
+        let ty = self.builder.value_type(value);
+        let index = self.imm(ty, index as u64);
+        let shifted = self.binary(BinaryOp::LShr, value, index);
+        self.builder.trunc(shifted, Type::I1)
+    }
+
+    fn is_negative(&mut self, value: Value) -> Value {
+        let ty = self.builder.value_type(value);
+        let zero = self.imm(ty, 0);
+        self.builder.icmp(IntCC::Slt, value, zero)
+    }
+
+    /// The current value of one flag, computed from the last flag-setting
+    /// instruction when it is known and read from the register otherwise.
+    pub fn flag(&mut self, flag: Flag) -> Value {
+        let Some(source) = self.flags else {
+            return self.builder.read_reg(Reg::Flag(flag));
+        };
+        let b = &mut self.builder;
+        match (source, flag) {
+            (FlagSource::Values(values), _) => values[flag as usize],
+            (FlagSource::Float { lhs, rhs }, Flag::N) => b.fcmp(FloatCC::Lt, lhs, rhs),
+            (FlagSource::Float { lhs, rhs }, Flag::Z) => b.fcmp(FloatCC::Eq, lhs, rhs),
+            (FlagSource::Float { lhs, rhs }, Flag::C) => {
+                let less = b.fcmp(FloatCC::Lt, lhs, rhs);
+                b.not(less)
+            }
+            (FlagSource::Float { lhs, rhs }, Flag::V) => b.fcmp(FloatCC::Uno, lhs, rhs),
+            (FlagSource::Logic { .. }, Flag::C | Flag::V) => b.bool(false),
+            (FlagSource::AddSub { result, .. } | FlagSource::AddCarry { result, .. } | FlagSource::Logic { result }, Flag::N) => {
+                self.is_negative(result)
+            }
+            (FlagSource::AddSub { lhs, rhs, sub: true, .. }, Flag::Z) => b.icmp(IntCC::Eq, lhs, rhs),
+            (FlagSource::AddSub { result, .. } | FlagSource::AddCarry { result, .. } | FlagSource::Logic { result }, Flag::Z) => {
+                let zero = b.iconst(b.value_type(result), 0);
+                b.icmp(IntCC::Eq, result, zero)
+            }
+            (FlagSource::AddSub { lhs, rhs, sub: true, .. }, Flag::C) => b.icmp(IntCC::Uge, lhs, rhs),
+            (FlagSource::AddSub { lhs, result, sub: false, .. }, Flag::C) => b.icmp(IntCC::Ult, result, lhs),
+            (FlagSource::AddCarry { lhs, carry, result, .. }, Flag::C) => {
+                let wrapped = b.icmp(IntCC::Ult, result, lhs);
+                let wrapped_or_same = b.icmp(IntCC::Ule, result, lhs);
+                b.select(carry, wrapped_or_same, wrapped)
+            }
+            (FlagSource::AddSub { lhs, rhs, result, sub }, Flag::V) => {
+                // Signed overflow: the operands' signs make the result's sign
+                // impossible.
+                let (a, c) = if sub { (b.xor(lhs, rhs), b.xor(lhs, result)) } else { (b.xor(lhs, result), b.xor(rhs, result)) };
+                let overflow = b.and(a, c);
+                self.is_negative(overflow)
+            }
+            (FlagSource::AddCarry { lhs, rhs, result, .. }, Flag::V) => {
+                let a = b.xor(lhs, result);
+                let c = b.xor(rhs, result);
+                let overflow = b.and(a, c);
+                self.is_negative(overflow)
+            }
+        }
+    }
+
+    /// Records the source of new NZCV flags, writing them back right away in
+    /// faithful mode.
+    fn set_flags(&mut self, source: FlagSource) {
+        self.flags = Some(source);
+        if self.mode == FlagsMode::Faithful {
+            self.write_flags();
+        }
+    }
+
+    fn write_flags(&mut self) {
+        for flag in NZCV {
+            let value = self.flag(flag);
+            self.builder.write_reg(Reg::Flag(flag), value);
+        }
+    }
+
+    /// Writes back lazily tracked flags before control leaves the lifted
+    /// code. Faithful mode has already written them.
+    fn flush_flags(&mut self) {
+        if self.mode == FlagsMode::Lazy && self.flags.is_some() {
+            self.write_flags();
+        }
+    }
+
+    /// Ends the current block with a jump to guest code at `target`.
+    pub fn exit(&mut self, target: u64) {
+        self.flush_flags();
+        let target = self.imm(Type::I64, target);
+        self.builder.indirect_branch(target);
+    }
+
+    /// Evaluates a condition code against the guest's NZCV flags. After a
+    /// compare, the condition becomes a single comparison of its operands.
+    pub fn condition(&mut self, cond: u8) -> Value {
+        if cond >= COND_AL {
+            return self.builder.bool(true); // AL and NV
+        }
+        match self.flags {
+            Some(FlagSource::AddSub { lhs, rhs, sub: true, .. }) if compare_cc(cond).is_some() => {
+                return self.builder.icmp(compare_cc(cond).unwrap(), lhs, rhs);
+            }
+            Some(FlagSource::Logic { result }) if !matches!(cond >> 1, 0b001 | 0b011 | 0b100) => {
+                // With C and V clear, GE and GT only look at N and Z.
+                let cc = match cond >> 1 {
+                    0b000 => IntCC::Eq,
+                    0b010 => IntCC::Slt,
+                    0b101 => IntCC::Sge,
+                    _ => IntCC::Sgt,
+                };
+                let cc = if cond & 1 == 0 { cc } else { invert_cc(cc) };
+                let zero = self.imm(self.builder.value_type(result), 0);
+                return self.builder.icmp(cc, result, zero);
+            }
+            Some(FlagSource::Float { lhs, rhs }) => {
+                let (cc, negate) = float_cc(cond);
+                let holds = self.builder.fcmp(cc, lhs, rhs);
+                return if negate { self.builder.not(holds) } else { holds };
+            }
+            _ => {}
+        }
+        let base = match cond >> 1 {
+            0b000 => self.flag(Flag::Z),
+            0b001 => self.flag(Flag::C),
+            0b010 => self.flag(Flag::N),
+            0b011 => self.flag(Flag::V),
+            0b100 => {
+                let c = self.flag(Flag::C);
+                let z = self.flag(Flag::Z);
+                let not_z = self.builder.not(z);
+                self.builder.and(c, not_z)
+            }
+            _ => {
+                let n = self.flag(Flag::N);
+                let v = self.flag(Flag::V);
+                let ge = self.builder.icmp(IntCC::Eq, n, v);
+                if cond >> 1 == 0b101 {
+                    ge
+                } else {
+                    let z = self.flag(Flag::Z);
+                    let not_z = self.builder.not(z);
+                    self.builder.and(ge, not_z)
+                }
+            }
+        };
+        if cond & 1 != 0 { self.builder.not(base) } else { base }
+    }
+
+    /// Lifts one instruction located at `pc`. Returns false without emitting
+    /// anything if the instruction is not handled, so the caller can end the
+    /// block there.
+    pub fn lift(&mut self, inst: &Inst, pc: u64) -> bool {
+        match inst.op {
+            A64_UDF => {
+                self.flush_flags();
+                self.builder.trap(TrapKind::Undefined);
+                true
+            }
+            A64_BCOND => {
+                // The taken side leaves the lifted code; lifting continues on
+                // the fall-through side.
+                let cond = self.condition(fad_get_cond(inst.flags));
+                let taken = self.builder.create_block();
+                let next = self.builder.create_block();
+                self.builder.branch(cond, BlockCall { block: taken, args: vec![] }, BlockCall { block: next, args: vec![] });
+                self.builder.switch_to_block(taken);
+                self.exit(pc.wrapping_add(inst.offset as u64));
+                self.builder.switch_to_block(next);
+                true
+            }
+            A64_CCMN_REG | A64_CCMN_IMM | A64_CCMP_REG | A64_CCMP_IMM | A64_RMIF | A64_SETF8 | A64_SETF16
+            | A64_FCMP_REG | A64_FCMP_ZERO | A64_FCMPE_REG | A64_FCMPE_ZERO => {
+                self.lift_flag_setting(inst);
+                true
+            }
+            A64_LDR | A64_STR | A64_LDP | A64_STP | A64_LDNP | A64_STNP | A64_LDR_FP | A64_STR_FP | A64_LDP_FP
+            | A64_STP_FP | A64_LDNP_FP | A64_STNP_FP | A64_PRFM => {
+                self.lift_memory(inst, pc);
+                true
+            }
+            _ => self.lift_data_processing(inst, pc),
+        }
+    }
+
+    /// Instructions whose only effect is on NZCV.
+    fn lift_flag_setting(&mut self, inst: &Inst) {
+        let source = match inst.op {
+            A64_CCMN_REG | A64_CCMN_IMM | A64_CCMP_REG | A64_CCMP_IMM => {
+                // The flags of the comparison if the condition holds, the
+                // immediate NZCV otherwise.
+                let ty = operand_type(inst);
+                let holds = self.condition(fad_get_cond(inst.flags));
+                let lhs = self.get(inst.rn, ty);
+                let rhs = match inst.op {
+                    A64_CCMN_IMM | A64_CCMP_IMM => self.imm(ty, inst.ccmp.imm5 as u64),
+                    _ => self.get(inst.rm, ty),
+                };
+                let sub = matches!(inst.op, A64_CCMP_REG | A64_CCMP_IMM);
+                let result = self.binary(if sub { BinaryOp::Sub } else { BinaryOp::Add }, lhs, rhs);
+                let previous = self.flags.replace(FlagSource::AddSub { lhs, rhs, result, sub });
+                let mut values = [holds; 4];
+                for (index, flag) in NZCV.into_iter().enumerate() {
+                    let compared = self.flag(flag);
+                    let fallback = self.builder.bool(inst.ccmp.nzcv >> (3 - index) & 1 != 0);
+                    values[index] = self.builder.select(holds, compared, fallback);
+                }
+                self.flags = previous;
+                FlagSource::Values(values)
+            }
+            A64_RMIF => {
+                // Bits 3..0 of the rotated register go to N, Z, C and V where
+                // the mask selects them.
+                let value = self.get(inst.rn, Type::I64);
+                let ror = self.imm(Type::I64, inst.rmif.ror as u64);
+                let rotated = self.binary(BinaryOp::RotR, value, ror);
+                let mut values = [rotated; 4];
+                for (index, flag) in NZCV.into_iter().enumerate() {
+                    let bit = 3 - index as u32;
+                    values[index] =
+                        if inst.rmif.mask >> bit & 1 != 0 { self.bit(rotated, bit) } else { self.flag(flag) };
+                }
+                FlagSource::Values(values)
+            }
+            A64_SETF8 | A64_SETF16 => {
+                let width = if inst.op == A64_SETF8 { 8 } else { 16 };
+                let value = self.get(inst.rn, Type::I32);
+                let n = self.bit(value, width - 1);
+                let narrow = self.builder.trunc(value, Type::int(width).unwrap());
+                let zero = self.imm(Type::int(width).unwrap(), 0);
+                let z = self.builder.icmp(IntCC::Eq, narrow, zero);
+                let c = self.flag(Flag::C);
+                let above = self.bit(value, width);
+                let v = self.builder.xor(above, n);
+                FlagSource::Values([n, z, c, v])
+            }
+            _ => {
+                let (float, int) = match fad_get_prec(inst.flags) {
+                    FSZ_H => (Type::F16, Type::I16),
+                    FSZ_D => (Type::F64, Type::I64),
+                    _ => (Type::F32, Type::I32),
+                };
+                let lhs = self.get_float(inst.rn, float);
+                let rhs = if matches!(inst.op, A64_FCMP_ZERO | A64_FCMPE_ZERO) {
+                    let zero = self.imm(int, 0);
+                    self.builder.bitcast(zero, float)
+                } else {
+                    self.get_float(inst.rm, float)
+                };
+                FlagSource::Float { lhs, rhs }
+            }
+        };
+        self.set_flags(source);
+    }
+
+    /// The guest address an access goes to and, for the pre- and post-index
+    /// modes, the base register's new value.
+    fn address(&mut self, inst: &Inst, pc: u64) -> (Value, Option<Value>) {
+        let mode = fad_get_addrmode(inst.flags);
+        if mode == AM_LITERAL {
+            return (self.imm(Type::I64, pc.wrapping_add(inst.offset as u64)), None);
+        }
+        let base = self.get(inst.rn, Type::I64);
+        let offset = match mode {
+            AM_SIMPLE => return (base, None),
+            AM_OFF_REG => self.shifted(inst.rm, SH_LSL, inst.extend.lsl as u64, Type::I64),
+            AM_OFF_EXT => self.extended(inst.rm, inst.extend.typ as u8, inst.extend.lsl, Type::I64),
+            _ if inst.offset == 0 && mode == AM_OFF_IMM => return (base, None),
+            _ => self.imm(Type::I64, inst.offset as u64),
+        };
+        let moved = self.builder.add(base, offset);
+        match mode {
+            AM_PRE => (moved, Some(moved)),
+            AM_POST => (base, Some(moved)),
+            _ => (moved, None),
+        }
+    }
+
+    /// LDR, STR, LDP, STP and their FP and non-temporal variants, and PRFM.
+    /// Every access is a `Load` or `Store` of exactly the guest access size,
+    /// with extension to the register width done separately.
+    fn lift_memory(&mut self, inst: &Inst, pc: u64) {
+        let memext = fad_get_mem_extend(inst.flags);
+        let fp = matches!(inst.op, A64_LDR_FP | A64_STR_FP | A64_LDP_FP | A64_STP_FP | A64_LDNP_FP | A64_STNP_FP);
+        let ty = if fp && memext == FSZ_Q { Type::I128 } else { Type::int(8 << (memext & 0b11)).unwrap() };
+        let (addr, writeback) = self.address(inst, pc);
+        let order = match fad_get_addrmode(inst.flags) {
+            AM_SIMPLE if inst.ldst_order.load != MemOrdering::MO_NONE as u16 => MemoryOrder::Acquire,
+            AM_SIMPLE if inst.ldst_order.store != MemOrdering::MO_NONE as u16 => MemoryOrder::Release,
+            _ => MemoryOrder::Plain,
+        };
+
+        let mut loaded = Vec::new();
+        match inst.op {
+            A64_PRFM => {
+                // Bits 4:3 of the prefetch operation are PLD, PLI or PST.
+                self.builder.prefetch(addr, inst.rd >> 3 & 0b11 == 0b10);
+            }
+            A64_STR | A64_STR_FP => {
+                let value = self.get_sized(inst.rd, ty, fp);
+                self.builder.store(addr, value, order);
+            }
+            A64_STP | A64_STNP | A64_STP_FP | A64_STNP_FP => {
+                let first = self.get_sized(inst.rd, ty, fp);
+                let second = self.get_sized(inst.rt2, ty, fp);
+                self.builder.store(addr, first, order);
+                let size = self.imm(Type::I64, ty.bits() as u64 / 8);
+                let addr = self.builder.add(addr, size);
+                self.builder.store(addr, second, order);
+            }
+            A64_LDR | A64_LDR_FP => loaded.push((inst.rd, self.builder.load(ty, addr, order))),
+            _ => {
+                // LDP, LDNP and their FP variants
+                loaded.push((inst.rd, self.builder.load(ty, addr, order)));
+                let size = self.imm(Type::I64, ty.bits() as u64 / 8);
+                let addr = self.builder.add(addr, size);
+                loaded.push((inst.rt2, self.builder.load(ty, addr, order)));
+            }
+        }
+
+        // Loading into the base register of a writeback access, and LDP
+        // with Rt == Rt2, are CONSTRAINED UNPREDICTABLE. Here the loaded
+        // value wins over the new base, and Rt2 wins over Rt.
+        if let Some(base) = writeback {
+            self.set(inst.rn, base);
+        }
+        for (reg, value) in loaded {
+            self.set_loaded(inst, reg, value, fp);
+        }
+    }
+
+    /// A general-purpose or SIMD&FP register truncated to the access type.
+    fn get_sized(&mut self, reg: u8, ty: Type, fp: bool) -> Value {
+        if fp {
+            let value = self.builder.read_reg(Reg::V(reg));
+            self.builder.trunc(value, ty)
+        } else {
+            self.get(reg, ty)
+        }
+    }
+
+    /// Writes a loaded value to its register, extended as the instruction
+    /// requires. Scalar FP loads clear the rest of the vector register.
+    fn set_loaded(&mut self, inst: &Inst, reg: u8, value: Value, fp: bool) {
+        if fp {
+            let value = self.builder.zext(value, Type::I128);
+            return self.builder.write_reg(Reg::V(reg), value);
+        }
+        let ty = operand_type(inst);
+        let value = if fad_get_mem_extend(inst.flags) & 0b100 != 0 {
+            self.builder.sext(value, ty)
+        } else {
+            self.builder.zext(value, ty)
+        };
+        self.set(reg, value);
+    }
+
+    fn lift_data_processing(&mut self, inst: &Inst, pc: u64) -> bool {
+        let ty = operand_type(inst);
+        let bits = ty.bits();
+        let (rd, rn, rm) = (inst.rd, inst.rn, inst.rm);
+
+        let result = match inst.op {
+            // PC-relative addressing
+            A64_ADR => self.imm(Type::I64, pc.wrapping_add(inst.offset as u64)),
+            A64_ADRP => self.imm(Type::I64, (pc & !0xFFF).wrapping_add(inst.offset as u64)),
+
+            // Add/subtract, logical and move (immediate)
+            A64_ADD_IMM | A64_CMN_IMM | A64_SUB_IMM | A64_CMP_IMM | A64_AND_IMM | A64_TST_IMM | A64_ORR_IMM
+            | A64_EOR_IMM => {
+                let op = match inst.op {
+                    A64_ADD_IMM | A64_CMN_IMM => BinaryOp::Add,
+                    A64_SUB_IMM | A64_CMP_IMM => BinaryOp::Sub,
+                    A64_AND_IMM | A64_TST_IMM => BinaryOp::And,
+                    A64_ORR_IMM => BinaryOp::Or,
+                    _ => BinaryOp::Xor,
+                };
+                let lhs = self.get(rn, ty);
+                let rhs = self.imm(ty, inst.imm);
+                self.arith(inst, op, lhs, rhs)
+            }
+            A64_MOV_SP => self.get(rn, ty),
+            A64_MOV_IMM => self.imm(ty, inst.imm),
+            A64_MOVK => {
+                let old = self.get(rd, ty);
+                let keep = self.imm(ty, !(0xFFFFu64 << inst.movk.lsl));
+                let kept = self.builder.and(old, keep);
+                let new = self.imm(ty, (inst.movk.imm16 as u64) << inst.movk.lsl);
+                self.builder.or(kept, new)
+            }
+
+            // Bitfield
+            A64_LSL_IMM | A64_LSR_IMM | A64_ASR_IMM | A64_ROR_IMM => {
+                let shift = match inst.op {
+                    A64_LSL_IMM => SH_LSL,
+                    A64_LSR_IMM => SH_LSR,
+                    A64_ASR_IMM => SH_ASR,
+                    _ => SH_ROR,
+                };
+                self.shifted(rn, shift, inst.imm, ty)
+            }
+            A64_SBFIZ | A64_SBFX => {
+                // Move the field to the top, then shift it down arithmetically.
+                let (lsb, width) = (inst.bfm.lsb, inst.bfm.width);
+                let field_top = if inst.op == A64_SBFX { lsb + width } else { width };
+                let value = self.get(rn, ty);
+                let up = self.imm(ty, (bits - field_top) as u64);
+                let top = self.binary(BinaryOp::Shl, value, up);
+                let down = if inst.op == A64_SBFX { bits - width } else { bits - width - lsb };
+                let down = self.imm(ty, down as u64);
+                self.binary(BinaryOp::AShr, top, down)
+            }
+            A64_UBFIZ => {
+                let value = self.get(rn, ty);
+                let mask = self.builder.iconst(ty, low_mask(inst.bfm.width));
+                let field = self.builder.and(value, mask);
+                let lsb = self.imm(ty, inst.bfm.lsb as u64);
+                self.binary(BinaryOp::Shl, field, lsb)
+            }
+            A64_UBFX => {
+                let value = self.get(rn, ty);
+                let lsb = self.imm(ty, inst.bfm.lsb as u64);
+                let down = self.binary(BinaryOp::LShr, value, lsb);
+                let mask = self.builder.iconst(ty, low_mask(inst.bfm.width));
+                self.builder.and(down, mask)
+            }
+            A64_BFI | A64_BFC | A64_BFXIL => {
+                let (lsb, width) = (inst.bfm.lsb, inst.bfm.width);
+                let dest_lsb = if inst.op == A64_BFXIL { 0 } else { lsb };
+                let old = self.get(rd, ty);
+                let clear = self.builder.iconst(ty, !(low_mask(width) << dest_lsb));
+                let kept = self.builder.and(old, clear);
+                if inst.op == A64_BFC {
+                    kept
+                } else {
+                    let value = self.get(rn, ty);
+                    let field = if inst.op == A64_BFXIL {
+                        let lsb = self.imm(ty, lsb as u64);
+                        self.binary(BinaryOp::LShr, value, lsb)
+                    } else {
+                        value
+                    };
+                    let mask = self.builder.iconst(ty, low_mask(width));
+                    let field = self.builder.and(field, mask);
+                    let field = if dest_lsb == 0 {
+                        field
+                    } else {
+                        let lsb = self.imm(ty, dest_lsb as u64);
+                        self.binary(BinaryOp::Shl, field, lsb)
+                    };
+                    self.builder.or(kept, field)
+                }
+            }
+            A64_EXTEND => {
+                let typ = inst.extend.typ as u8;
+                let value = self.get(rn, ty);
+                let from = match typ & 0b11 {
+                    SZ_B => Type::I8,
+                    SZ_H => Type::I16,
+                    _ => Type::I32,
+                };
+                let narrow = self.builder.trunc(value, from);
+                if matches!(typ, SXTB | SXTH | SXTW) {
+                    self.builder.sext(narrow, ty)
+                } else {
+                    self.builder.zext(narrow, ty)
+                }
+            }
+            A64_EXTR => {
+                let low = self.get(rm, ty);
+                if inst.imm == 0 {
+                    low
+                } else {
+                    let high = self.get(rn, ty);
+                    let lsb = self.imm(ty, inst.imm);
+                    let low = self.binary(BinaryOp::LShr, low, lsb);
+                    let up = self.imm(ty, bits as u64 - inst.imm);
+                    let high = self.binary(BinaryOp::Shl, high, up);
+                    self.builder.or(high, low)
+                }
+            }
+
+            // Logical (shifted register)
+            A64_AND_SHIFTED | A64_TST_SHIFTED | A64_BIC | A64_ORR_SHIFTED | A64_ORN | A64_EOR_SHIFTED | A64_EON => {
+                let lhs = self.get(rn, ty);
+                let rhs = self.shifted(rm, inst.shift, inst.imm, ty);
+                let rhs = if matches!(inst.op, A64_BIC | A64_ORN | A64_EON) { self.builder.not(rhs) } else { rhs };
+                let op = match inst.op {
+                    A64_AND_SHIFTED | A64_TST_SHIFTED | A64_BIC => BinaryOp::And,
+                    A64_ORR_SHIFTED | A64_ORN => BinaryOp::Or,
+                    _ => BinaryOp::Xor,
+                };
+                self.arith(inst, op, lhs, rhs)
+            }
+            A64_MOV_REG => self.get(rm, ty),
+            A64_MVN => {
+                let value = self.shifted(rm, inst.shift, inst.imm, ty);
+                self.builder.not(value)
+            }
+
+            // Add/subtract (shifted and extended register)
+            A64_ADD_SHIFTED | A64_CMN_SHIFTED | A64_SUB_SHIFTED | A64_CMP_SHIFTED | A64_NEG => {
+                let lhs = self.get(rn, ty);
+                let rhs = self.shifted(rm, inst.shift, inst.imm, ty);
+                let add = matches!(inst.op, A64_ADD_SHIFTED | A64_CMN_SHIFTED);
+                self.arith(inst, if add { BinaryOp::Add } else { BinaryOp::Sub }, lhs, rhs)
+            }
+            A64_ADD_EXT | A64_CMN_EXT | A64_SUB_EXT | A64_CMP_EXT => {
+                let lhs = self.get(rn, ty);
+                let rhs = self.extended(rm, inst.extend.typ as u8, inst.extend.lsl, ty);
+                let add = matches!(inst.op, A64_ADD_EXT | A64_CMN_EXT);
+                self.arith(inst, if add { BinaryOp::Add } else { BinaryOp::Sub }, lhs, rhs)
+            }
+
+            // Add/subtract (with carry)
+            A64_ADC | A64_SBC | A64_NGC => {
+                let lhs = self.get(rn, ty);
+                let rhs = self.get(rm, ty);
+                let rhs = if inst.op == A64_ADC { rhs } else { self.builder.not(rhs) };
+                let carry = self.flag(Flag::C);
+                let carry_in = self.builder.zext(carry, ty);
+                let sum = self.builder.add(lhs, rhs);
+                let result = self.builder.add(sum, carry_in);
+                if inst.flags & SET_FLAGS != 0 {
+                    self.set_flags(FlagSource::AddCarry { lhs, rhs, carry, result });
+                }
+                result
+            }
+
+            // Conditional select
+            A64_CSEL | A64_CSINC | A64_CSINV | A64_CSNEG => {
+                let cond = self.condition(fad_get_cond(inst.flags));
+                let if_true = self.get(rn, ty);
+                let other = self.get(rm, ty);
+                let if_false = match inst.op {
+                    A64_CSEL => other,
+                    A64_CSINC => {
+                        let one = self.imm(ty, 1);
+                        self.builder.add(other, one)
+                    }
+                    A64_CSINV => self.builder.not(other),
+                    _ => self.builder.unary(UnaryOp::Neg, other),
+                };
+                self.builder.select(cond, if_true, if_false)
+            }
+            A64_CSET | A64_CSETM => {
+                let cond = self.condition(fad_get_cond(inst.flags));
+                if inst.op == A64_CSET { self.builder.zext(cond, ty) } else { self.builder.sext(cond, ty) }
+            }
+            A64_CINC | A64_CINV | A64_CNEG => {
+                let cond = self.condition(fad_get_cond(inst.flags));
+                let value = self.get(rn, ty);
+                let changed = match inst.op {
+                    A64_CINC => {
+                        let one = self.imm(ty, 1);
+                        self.builder.add(value, one)
+                    }
+                    A64_CINV => self.builder.not(value),
+                    _ => self.builder.unary(UnaryOp::Neg, value),
+                };
+                self.builder.select(cond, changed, value)
+            }
+
+            // Data-processing (2 source)
+            A64_UDIV | A64_SDIV | A64_LSLV | A64_LSRV | A64_ASRV | A64_RORV => {
+                let op = match inst.op {
+                    A64_UDIV => BinaryOp::UDiv,
+                    A64_SDIV => BinaryOp::SDiv,
+                    A64_LSLV => BinaryOp::Shl,
+                    A64_LSRV => BinaryOp::LShr,
+                    A64_ASRV => BinaryOp::AShr,
+                    _ => BinaryOp::RotR,
+                };
+                let lhs = self.get(rn, ty);
+                let rhs = self.get(rm, ty);
+                self.binary(op, lhs, rhs)
+            }
+            A64_CRC32B | A64_CRC32H | A64_CRC32W | A64_CRC32X | A64_CRC32CB | A64_CRC32CH | A64_CRC32CW
+            | A64_CRC32CX => {
+                let (castagnoli, data_type) = match inst.op {
+                    A64_CRC32B => (false, Type::I8),
+                    A64_CRC32H => (false, Type::I16),
+                    A64_CRC32W => (false, Type::I32),
+                    A64_CRC32X => (false, Type::I64),
+                    A64_CRC32CB => (true, Type::I8),
+                    A64_CRC32CH => (true, Type::I16),
+                    A64_CRC32CW => (true, Type::I32),
+                    _ => (true, Type::I64),
+                };
+                let crc = self.get(rn, Type::I32);
+                let data = self.get(rm, if data_type == Type::I64 { Type::I64 } else { Type::I32 });
+                let data = self.builder.trunc(data, data_type);
+                self.builder.crc32(castagnoli, crc, data)
+            }
+
+            // Data-processing (1 source)
+            A64_RBIT | A64_REV | A64_CLZ | A64_CLS => {
+                let op = match inst.op {
+                    A64_RBIT => UnaryOp::BitReverse,
+                    A64_REV => UnaryOp::ByteSwap,
+                    A64_CLZ => UnaryOp::Clz,
+                    _ => UnaryOp::Cls,
+                };
+                let value = self.get(rn, ty);
+                self.builder.unary(op, value)
+            }
+            A64_REV16 => {
+                let value = self.get(rn, ty);
+                let eight = self.imm(ty, 8);
+                let low_bytes = self.imm(ty, 0x00FF_00FF_00FF_00FF);
+                let down = self.binary(BinaryOp::LShr, value, eight);
+                let down = self.builder.and(down, low_bytes);
+                let up = self.builder.and(value, low_bytes);
+                let up = self.binary(BinaryOp::Shl, up, eight);
+                self.builder.or(up, down)
+            }
+            A64_REV32 => {
+                let value = self.get(rn, ty);
+                let swapped = self.builder.unary(UnaryOp::ByteSwap, value);
+                let half = self.imm(ty, 32);
+                self.binary(BinaryOp::RotR, swapped, half)
+            }
+
+            // Data-processing (3 source)
+            A64_MADD | A64_MSUB | A64_MUL | A64_MNEG => {
+                let lhs = self.get(rn, ty);
+                let rhs = self.get(rm, ty);
+                let product = self.binary(BinaryOp::Mul, lhs, rhs);
+                self.accumulate(inst, product, ty)
+            }
+            A64_SMADDL | A64_SMSUBL | A64_SMULL | A64_SMNEGL | A64_UMADDL | A64_UMSUBL | A64_UMULL | A64_UMNEGL => {
+                let signed = matches!(inst.op, A64_SMADDL | A64_SMSUBL | A64_SMULL | A64_SMNEGL);
+                let lhs = self.get(rn, Type::I32);
+                let rhs = self.get(rm, Type::I32);
+                let (lhs, rhs) = if signed {
+                    (self.builder.sext(lhs, Type::I64), self.builder.sext(rhs, Type::I64))
+                } else {
+                    (self.builder.zext(lhs, Type::I64), self.builder.zext(rhs, Type::I64))
+                };
+                let product = self.binary(BinaryOp::Mul, lhs, rhs);
+                self.accumulate(inst, product, Type::I64)
+            }
+            A64_SMULH | A64_UMULH => {
+                let op = if inst.op == A64_SMULH { BinaryOp::SMulHigh } else { BinaryOp::UMulHigh };
+                let lhs = self.get(rn, Type::I64);
+                let rhs = self.get(rm, Type::I64);
+                self.binary(op, lhs, rhs)
+            }
+
+            _ => return false,
+        };
+        self.set(rd, result);
+        true
+    }
+
+    /// `lhs op rhs`, recording the flags of the result for the flag-setting
+    /// variants.
+    fn arith(&mut self, inst: &Inst, op: BinaryOp, lhs: Value, rhs: Value) -> Value {
+        let result = self.binary(op, lhs, rhs);
+        if inst.flags & SET_FLAGS != 0 {
+            self.set_flags(match op {
+                BinaryOp::Add | BinaryOp::Sub => FlagSource::AddSub { lhs, rhs, result, sub: op == BinaryOp::Sub },
+                _ => FlagSource::Logic { result },
+            });
+        }
+        result
+    }
+
+    /// Adds the product to Ra, subtracts it from Ra, or negates it, as the
+    /// multiply-accumulate variant requires.
+    fn accumulate(&mut self, inst: &Inst, product: Value, ty: Type) -> Value {
+        match inst.op {
+            A64_MUL | A64_SMULL | A64_UMULL => product,
+            A64_MNEG | A64_SMNEGL | A64_UMNEGL => self.builder.unary(UnaryOp::Neg, product),
+            A64_MADD | A64_SMADDL | A64_UMADDL => {
+                let acc = self.get(inst.ra, ty);
+                self.builder.add(acc, product)
+            }
+            _ => {
+                let acc = self.get(inst.ra, ty);
+                self.builder.sub(acc, product)
+            }
+        }
+    }
+}
+
+/// Lifts straight-line code starting at `address`, following the
+/// fall-through side of conditional branches. Lifting stops at the first
+/// instruction the lifter does not handle, with an indirect branch to that
+/// instruction so an interpreter can take over.
+pub fn lift_function(name: &str, code: &[u8], address: u64, mode: FlagsMode) -> Function {
+    let mut func = Function::new(name, address);
+    let mut lifter = Lifter::new(&mut func, mode);
+    let mut pc = address;
+    for word in code.chunks_exact(4) {
+        let inst = decode(u32::from_le_bytes(word.try_into().unwrap()));
+        if !lifter.lift(&inst, pc) {
+            break;
+        }
+        if lifter.builder.is_terminated() {
+            return func;
+        }
+        pc += 4;
+    }
+    lifter.exit(pc);
+    func
+}
@@ -0,0 +1,302 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::ir::{
+    BinaryOp, Block, BlockCall, Callee, CastOp, Function, InstId, InstKind, ScalarType, Type, UnaryOp, Value, ValueDef,
+};
+
+/// A rule a function breaks, located at a block and, for most rules, an
+/// instruction in it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VerifierError {
+    pub block: Block,
+    pub inst: Option<InstId>,
+    pub message: String,
+}
+
+impl fmt::Display for VerifierError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.inst {
+            Some(inst) => write!(f, "{}, inst{}: {}", self.block, inst.0, self.message),
+            None => write!(f, "{}: {}", self.block, self.message),
+        }
+    }
+}
+
+/// Checks that every block ends in exactly one terminator, that operand
+/// types fit each instruction, that branches pass the right arguments, and
+/// that every use is dominated by its definition.
+pub fn verify(func: &Function) -> Result<(), Vec<VerifierError>> {
+    let mut verifier = Verifier { func, errors: Vec::new(), positions: HashMap::new() };
+    verifier.run();
+    if verifier.errors.is_empty() { Ok(()) } else { Err(verifier.errors) }
+}
+
+struct Verifier<'a> {
+    func: &'a Function,
+    errors: Vec<VerifierError>,
+    /// The block and index of each instruction in the layout.
+    positions: HashMap<InstId, (Block, usize)>,
+}
+
+impl Verifier<'_> {
+    fn error(&mut self, block: Block, inst: Option<InstId>, message: String) {
+        self.errors.push(VerifierError { block, inst, message });
+    }
+
+    fn run(&mut self) {
+        let func = self.func;
+        let Some(entry) = func.entry_block() else {
+            return self.error(Block(0), None, "function has no blocks".to_string());
+        };
+        if !func.block_params(entry).is_empty() {
+            self.error(entry, None, "entry block has parameters".to_string());
+        }
+
+        for block in func.blocks() {
+            for (index, inst) in func.block_insts(block).iter().enumerate() {
+                if let Some((other, _)) = self.positions.insert(*inst, (block, index)) {
+                    self.error(block, Some(*inst), format!("instruction is also placed in {}", other));
+                }
+            }
+        }
+
+        for block in func.blocks() {
+            let insts = func.block_insts(block);
+            if func.terminator(block).is_none() {
+                self.error(block, None, "block does not end in a terminator".to_string());
+            }
+            for inst in insts.iter().take(insts.len().saturating_sub(1)) {
+                if func.inst(*inst).kind.is_terminator() {
+                    self.error(block, Some(*inst), "terminator in the middle of a block".to_string());
+                }
+            }
+            for inst in insts {
+                self.check_inst(block, *inst);
+            }
+        }
+
+        if self.errors.is_empty() {
+            self.check_dominance();
+        }
+    }
+
+    fn check_inst(&mut self, block: Block, inst: InstId) {
+        let func = self.func;
+        let kind = &func.inst(inst).kind;
+        for arg in kind.arguments() {
+            if !func.is_valid(arg) {
+                return self.error(block, Some(inst), format!("{} is not defined", arg));
+            }
+        }
+        let ty = |value: &Value| func.value_type(*value);
+        let problem = match kind {
+            InstKind::Const { ty, bits } => {
+                (ty.bits() < 128 && bits >> ty.bits() != 0).then(|| format!("constant {:#x} does not fit {}", bits, ty))
+            }
+            InstKind::Unary { op, arg } => {
+                let float_op = matches!(op, UnaryOp::FNeg | UnaryOp::FAbs | UnaryOp::FSqrt);
+                (float_op != ty(arg).is_float()).then(|| format!("{:?} on {}", op, ty(arg)))
+            }
+            InstKind::Binary { op, lhs, rhs } => {
+                if ty(lhs) != ty(rhs) {
+                    Some(format!("{:?} of {} and {}", op, ty(lhs), ty(rhs)))
+                } else if op.is_float() != ty(lhs).is_float() {
+                    Some(format!("{:?} on {}", op, ty(lhs)))
+                } else if matches!(op, BinaryOp::UMulHigh | BinaryOp::SMulHigh) && ty(lhs).is_vector() {
+                    Some(format!("{:?} on a vector", op))
+                } else {
+                    None
+                }
+            }
+            InstKind::Icmp { lhs, rhs, .. } => {
+                (ty(lhs) != ty(rhs) || !ty(lhs).is_int()).then(|| format!("icmp of {} and {}", ty(lhs), ty(rhs)))
+            }
+            InstKind::Fcmp { lhs, rhs, .. } => {
+                (ty(lhs) != ty(rhs) || !ty(lhs).is_float()).then(|| format!("fcmp of {} and {}", ty(lhs), ty(rhs)))
+            }
+            InstKind::Select { cond, if_true, if_false } => {
+                if ty(cond) != Type::I1 {
+                    Some(format!("select condition is {}", ty(cond)))
+                } else {
+                    (ty(if_true) != ty(if_false)).then(|| format!("select of {} and {}", ty(if_true), ty(if_false)))
+                }
+            }
+            InstKind::Cast { op, arg, ty: to } => check_cast(*op, ty(arg), *to),
+            InstKind::ExtractLane { vector, lane } => {
+                (*lane >= ty(vector).lanes() || !ty(vector).is_vector()).then(|| format!("lane {} of {}", lane, ty(vector)))
+            }
+            InstKind::InsertLane { vector, lane, value } => {
+                if *lane >= ty(vector).lanes() || !ty(vector).is_vector() {
+                    Some(format!("lane {} of {}", lane, ty(vector)))
+                } else {
+                    (Type::Scalar(ty(vector).element()) != ty(value))
+                        .then(|| format!("inserting {} into {}", ty(value), ty(vector)))
+                }
+            }
+            InstKind::Splat { ty: to, value } => {
+                (!to.is_vector() || Type::Scalar(to.element()) != ty(value)).then(|| format!("splat of {} to {}", ty(value), to))
+            }
+            InstKind::Crc32 { crc, data, .. } => {
+                (ty(crc) != Type::I32 || ty(data).is_vector() || !ty(data).is_int() || ty(data).bits() > 64)
+                    .then(|| format!("crc32 of {} and {}", ty(crc), ty(data)))
+            }
+            InstKind::ReadReg { .. } => None,
+            InstKind::WriteReg { reg, value } => {
+                (reg.ty() != ty(value)).then(|| format!("writing {} to {} which is {}", ty(value), reg, reg.ty()))
+            }
+            InstKind::Load { addr, .. } | InstKind::Store { addr, .. } | InstKind::Prefetch { addr, .. } => {
+                (ty(addr) != Type::I64).then(|| format!("address is {}", ty(addr)))
+            }
+            InstKind::Fence { .. } | InstKind::Return | InstKind::Trap { .. } => None,
+            InstKind::Call { callee: Callee::Indirect(target), .. } | InstKind::IndirectBranch { target } => {
+                (ty(target) != Type::I64).then(|| format!("branch target is {}", ty(target)))
+            }
+            InstKind::Call { .. } => None,
+            InstKind::Jump { dest } => self.check_block_call(dest),
+            InstKind::Branch { cond, then_dest, else_dest } => {
+                if ty(cond) != Type::I1 {
+                    Some(format!("branch condition is {}", ty(cond)))
+                } else {
+                    self.check_block_call(then_dest).or_else(|| self.check_block_call(else_dest))
+                }
+            }
+        };
+        if let Some(message) = problem {
+            self.error(block, Some(inst), message);
+        }
+    }
+
+    fn check_block_call(&self, call: &BlockCall) -> Option<String> {
+        let func = self.func;
+        if call.block.0 as usize >= func.num_blocks() {
+            return Some(format!("{} does not exist", call.block));
+        }
+        let params = func.block_params(call.block);
+        if params.len() != call.args.len() {
+            return Some(format!("{} takes {} arguments, got {}", call.block, params.len(), call.args.len()));
+        }
+        params.iter().zip(&call.args).find(|(param, arg)| func.value_type(**param) != func.value_type(**arg)).map(
+            |(param, arg)| format!("passing {} to {} of {}", func.value_type(*arg), func.value_type(*param), call.block),
+        )
+    }
+
+    fn check_dominance(&mut self) {
+        let func = self.func;
+        let idom = dominators(func);
+        let dominates = |a: Block, mut b: Block| loop {
+            if a == b {
+                return true;
+            }
+            match idom.get(&b) {
+                Some(&parent) if parent != b => b = parent,
+                _ => return false,
+            }
+        };
+
+        for block in func.blocks() {
+            if !idom.contains_key(&block) {
+                continue; // unreachable code is not checked
+            }
+            for (index, inst) in func.block_insts(block).iter().enumerate() {
+                for arg in func.inst(*inst).kind.arguments() {
+                    let ok = match func.value_def(arg) {
+                        ValueDef::Param(def_block, _) => dominates(def_block, block),
+                        ValueDef::Inst(def) => match self.positions.get(&def) {
+                            Some(&(def_block, def_index)) if def_block == block => def_index < index,
+                            Some(&(def_block, _)) => dominates(def_block, block),
+                            None => false,
+                        },
+                    };
+                    if !ok {
+                        self.error(block, Some(*inst), format!("{} does not dominate its use", arg));
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn check_cast(op: CastOp, from: Type, to: Type) -> Option<String> {
+    let ok = match op {
+        CastOp::Bitcast => from.bits() == to.bits(),
+        _ if from.lanes() != to.lanes() => false,
+        CastOp::Zext | CastOp::Sext => from.is_int() && to.is_int() && from.element().bits() < to.element().bits(),
+        CastOp::Trunc => from.is_int() && to.is_int() && from.element().bits() > to.element().bits(),
+        CastOp::FpExt => from.is_float() && to.is_float() && from.element().bits() < to.element().bits(),
+        CastOp::FpTrunc => from.is_float() && to.is_float() && from.element().bits() > to.element().bits(),
+        CastOp::FpToSi | CastOp::FpToUi => from.is_float() && to.is_int() && to.element() != ScalarType::I1,
+        CastOp::SiToFp | CastOp::UiToFp => from.is_int() && to.is_float(),
+    };
+    (!ok).then(|| format!("{:?} from {} to {}", op, from, to))
+}
+
+/// Immediate dominators of the reachable blocks, computed with the
+/// Cooper-Harvey-Kennedy algorithm. The entry block is its own dominator.
+pub fn dominators(func: &Function) -> HashMap<Block, Block> {
+    let Some(entry) = func.entry_block() else {
+        return HashMap::new();
+    };
+    let successors = |block: Block| -> Vec<Block> {
+        func.terminator(block).map(|t| t.successors().iter().map(|call| call.block).collect()).unwrap_or_default()
+    };
+
+    // Reverse postorder of the reachable blocks.
+    let mut postorder = Vec::new();
+    let mut visited = vec![false; func.num_blocks()];
+    let mut stack = vec![(entry, 0)];
+    visited[entry.0 as usize] = true;
+    while let Some((block, next)) = stack.pop() {
+        let succ = successors(block);
+        if let Some(&child) = succ.get(next) {
+            stack.push((block, next + 1));
+            if !std::mem::replace(&mut visited[child.0 as usize], true) {
+                stack.push((child, 0));
+            }
+        } else {
+            postorder.push(block);
+        }
+    }
+    let order: HashMap<Block, usize> = postorder.iter().enumerate().map(|(i, b)| (*b, i)).collect();
+    let mut predecessors: HashMap<Block, Vec<Block>> = HashMap::new();
+    for &block in &postorder {
+        for succ in successors(block) {
+            predecessors.entry(succ).or_default().push(block);
+        }
+    }
+
+    let mut idom = HashMap::from([(entry, entry)]);
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &block in postorder.iter().rev().filter(|b| **b != entry) {
+            let mut new_idom: Option<Block> = None;
+            for &pred in predecessors.get(&block).into_iter().flatten() {
+                if !idom.contains_key(&pred) {
+                    continue;
+                }
+                new_idom = Some(match new_idom {
+                    None => pred,
+                    Some(mut a) => {
+                        let mut b = pred;
+                        while a != b {
+                            while order[&a] < order[&b] {
+                                a = idom[&a];
+                            }
+                            while order[&b] < order[&a] {
+                                b = idom[&b];
+                            }
+                        }
+                        a
+                    }
+                });
+            }
+            if let Some(new_idom) = new_idom {
+                if idom.insert(block, new_idom) != Some(new_idom) {
+                    changed = true;
+                }
+            }
+        }
+    }
+    idom
+}
@@ -0,0 +1,68 @@
+/// Decoder for Nintendo's backwards LZ ("BLZ"), used by KIP1 segments.
+///
+/// The compressed region sits at the end of the input and ends with a
+/// 12-byte footer: the size of the compressed region, the offset from its
+/// end to where the stream starts, and how many bytes decompression adds.
+/// Anything before the compressed region is stored verbatim.
+///
+/// Decoding runs from the end towards the start, in place: a control byte is
+/// read, then for each of its bits (MSB first) either one literal byte, or a
+/// two-byte back reference of 3 to 18 bytes copied from 3 to 4098 bytes
+/// above the current output position. References are copied front to back,
+/// so one that overlaps its own destination reads bytes not yet written.
+///
+/// Returns None if the footer or the stream is malformed.
+pub fn decompress(input: &[u8]) -> Option<Vec<u8>> {
+    if input.len() < 0xC {
+        return None;
+    }
+    let footer = &input[input.len() - 0xC..];
+    let word = |i: usize| u32::from_le_bytes([footer[i], footer[i + 1], footer[i + 2], footer[i + 3]]) as usize;
+    let compressed_size = word(0);
+    let init_index = word(4);
+    let additional_size = word(8);
+
+    // A two-byte reference expands to at most 18 bytes, so no valid stream
+    // grows by more than eight times its size.
+    if compressed_size > input.len() || init_index > compressed_size || additional_size > compressed_size * 8 {
+        return None;
+    }
+    let mut out = input.to_vec();
+    out.resize(input.len() + additional_size, 0);
+
+    // Everything below is relative to the start of the compressed region.
+    let base = input.len() - compressed_size;
+    let buf = &mut out[base..];
+    let mut cmp_ofs = compressed_size - init_index;
+    let mut out_ofs = compressed_size + additional_size;
+
+    while out_ofs > 0 {
+        cmp_ofs = cmp_ofs.checked_sub(1)?;
+        let mut control = buf[cmp_ofs];
+        for _ in 0..8 {
+            if control & 0x80 != 0 {
+                cmp_ofs = cmp_ofs.checked_sub(2)?;
+                let seg_val = u16::from_le_bytes([buf[cmp_ofs], buf[cmp_ofs + 1]]) as usize;
+                let seg_size = ((seg_val >> 12) & 0xF) + 3;
+                let seg_ofs = (seg_val & 0x0FFF) + 3;
+                // Like the kernel, clamp copies that would run past the start,
+                // then copy upwards from the new output position.
+                let seg_size = seg_size.min(out_ofs);
+                out_ofs -= seg_size;
+                for i in out_ofs..out_ofs + seg_size {
+                    buf[i] = *buf.get(i + seg_ofs)?;
+                }
+            } else {
+                cmp_ofs = cmp_ofs.checked_sub(1)?;
+                out_ofs -= 1;
+                buf[out_ofs] = buf[cmp_ofs];
+            }
+            control <<= 1;
+            if out_ofs == 0 {
+                break;
+            }
+        }
+    }
+
+    Some(out)
+}
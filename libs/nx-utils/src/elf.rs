@@ -0,0 +1,570 @@
+use std::borrow::Cow;
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use crate::executable::Executable;
+use crate::loader::{page_align, Symbol, DT_STRSZ, DT_STRTAB, PAGE_SIZE, SYMBOL_SIZE};
+use crate::NroSegmentType::{self, DATA, RO, TEXT};
+use crate::{SegmentBytes, SwitchExecutable};
+
+pub const ET_DYN: u16 = 3;
+pub const EM_AARCH64: u16 = 183;
+
+pub const PT_LOAD: u32 = 1;
+pub const PT_DYNAMIC: u32 = 2;
+pub const PT_NOTE: u32 = 4;
+
+pub const PF_X: u32 = 1;
+pub const PF_W: u32 = 2;
+pub const PF_R: u32 = 4;
+
+pub const SHT_NULL: u32 = 0;
+pub const SHT_PROGBITS: u32 = 1;
+pub const SHT_SYMTAB: u32 = 2;
+pub const SHT_STRTAB: u32 = 3;
+pub const SHT_DYNAMIC: u32 = 6;
+pub const SHT_NOTE: u32 = 7;
+pub const SHT_NOBITS: u32 = 8;
+
+/// Section indices from SHN_LORESERVE up are not real sections.
+pub const SHN_LORESERVE: u16 = 0xFF00;
+pub const SHN_ABS: u16 = 0xFFF1;
+pub const SHN_COMMON: u16 = 0xFFF2;
+
+pub const SHF_WRITE: u64 = 1;
+pub const SHF_ALLOC: u64 = 2;
+pub const SHF_EXECINSTR: u64 = 4;
+
+pub const NT_GNU_BUILD_ID: u32 = 3;
+
+/// An Elf64_Phdr.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ElfProgramHeader {
+    pub typ: u32,
+    pub flags: u32,
+    pub offset: u64,
+    pub vaddr: u64,
+    pub paddr: u64,
+    pub filesz: u64,
+    pub memsz: u64,
+    pub align: u64,
+}
+
+impl ElfProgramHeader {
+    pub const SIZE: usize = 0x38;
+
+    fn read(raw: &[u8]) -> ElfProgramHeader {
+        ElfProgramHeader {
+            typ: LittleEndian::read_u32(raw),
+            flags: LittleEndian::read_u32(&raw[0x04..]),
+            offset: LittleEndian::read_u64(&raw[0x08..]),
+            vaddr: LittleEndian::read_u64(&raw[0x10..]),
+            paddr: LittleEndian::read_u64(&raw[0x18..]),
+            filesz: LittleEndian::read_u64(&raw[0x20..]),
+            memsz: LittleEndian::read_u64(&raw[0x28..]),
+            align: LittleEndian::read_u64(&raw[0x30..]),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = vec![0u8; ElfProgramHeader::SIZE];
+        LittleEndian::write_u32(&mut out[0x00..], self.typ);
+        LittleEndian::write_u32(&mut out[0x04..], self.flags);
+        LittleEndian::write_u64(&mut out[0x08..], self.offset);
+        LittleEndian::write_u64(&mut out[0x10..], self.vaddr);
+        LittleEndian::write_u64(&mut out[0x18..], self.paddr);
+        LittleEndian::write_u64(&mut out[0x20..], self.filesz);
+        LittleEndian::write_u64(&mut out[0x28..], self.memsz);
+        LittleEndian::write_u64(&mut out[0x30..], self.align);
+        out
+    }
+
+    /// Which Switch segment a PT_LOAD maps to: executable ones are .text,
+    /// writable ones .data and everything else .rodata.
+    pub fn segment_type(&self) -> Option<NroSegmentType> {
+        if self.typ != PT_LOAD {
+            None
+        } else if self.flags & PF_X != 0 {
+            Some(TEXT)
+        } else if self.flags & PF_W != 0 {
+            Some(DATA)
+        } else {
+            Some(RO)
+        }
+    }
+}
+
+/// An Elf64_Shdr, with its name looked up in .shstrtab.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ElfSectionHeader {
+    pub name: String,
+    pub typ: u32,
+    pub flags: u64,
+    pub addr: u64,
+    pub offset: u64,
+    pub size: u64,
+    pub link: u32,
+    pub info: u32,
+    pub addralign: u64,
+    pub entsize: u64,
+}
+
+impl ElfSectionHeader {
+    pub const SIZE: usize = 0x40;
+
+    fn read(raw: &[u8], shstrtab: &[u8]) -> ElfSectionHeader {
+        let name = &shstrtab[LittleEndian::read_u32(raw) as usize..];
+        let name_len = name.iter().position(|b| *b == 0).unwrap_or(name.len());
+        ElfSectionHeader {
+            name: String::from_utf8_lossy(&name[..name_len]).into_owned(),
+            typ: LittleEndian::read_u32(&raw[0x04..]),
+            flags: LittleEndian::read_u64(&raw[0x08..]),
+            addr: LittleEndian::read_u64(&raw[0x10..]),
+            offset: LittleEndian::read_u64(&raw[0x18..]),
+            size: LittleEndian::read_u64(&raw[0x20..]),
+            link: LittleEndian::read_u32(&raw[0x28..]),
+            info: LittleEndian::read_u32(&raw[0x2C..]),
+            addralign: LittleEndian::read_u64(&raw[0x30..]),
+            entsize: LittleEndian::read_u64(&raw[0x38..]),
+        }
+    }
+
+    /// Serializes the header; the name is given as an offset into .shstrtab.
+    pub fn to_bytes(&self, name_offset: u32) -> Vec<u8> {
+        let mut out = vec![0u8; ElfSectionHeader::SIZE];
+        LittleEndian::write_u32(&mut out[0x00..], name_offset);
+        LittleEndian::write_u32(&mut out[0x04..], self.typ);
+        LittleEndian::write_u64(&mut out[0x08..], self.flags);
+        LittleEndian::write_u64(&mut out[0x10..], self.addr);
+        LittleEndian::write_u64(&mut out[0x18..], self.offset);
+        LittleEndian::write_u64(&mut out[0x20..], self.size);
+        LittleEndian::write_u32(&mut out[0x28..], self.link);
+        LittleEndian::write_u32(&mut out[0x2C..], self.info);
+        LittleEndian::write_u64(&mut out[0x30..], self.addralign);
+        LittleEndian::write_u64(&mut out[0x38..], self.entsize);
+        out
+    }
+
+    /// The section contents; empty for SHT_NOBITS.
+    pub fn data<'a>(&self, file_bytes: &'a [u8]) -> &'a [u8] {
+        if self.typ == SHT_NOBITS {
+            return &[];
+        }
+        &file_bytes[self.offset as usize..][..self.size as usize]
+    }
+}
+
+/// What an ELF carries beyond the loadable segments: the headers, the full
+/// static symbol table and, through the section headers, the DWARF debug
+/// sections.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ElfFile {
+    pub entry: u64,
+    pub program_headers: Vec<ElfProgramHeader>,
+    pub section_headers: Vec<ElfSectionHeader>,
+    /// The `.symtab` entries, including the null symbol at index 0.
+    pub symbols: Vec<Symbol>,
+    /// The descriptor of the GNU build ID note, if the linker emitted one.
+    pub build_id: Option<Vec<u8>>,
+}
+
+impl ElfFile {
+    pub const MAGIC: &'static [u8; 4] = b"\x7FELF";
+    pub const HEADER_SIZE: usize = 0x40;
+
+    /// Reads a little-endian ELF64 for AArch64.
+    pub fn read(bytes: &[u8]) -> ElfFile {
+        assert_eq!(&bytes[..4], ElfFile::MAGIC);
+        assert_eq!(bytes[4], 2, "only ELF64 is supported");
+        assert_eq!(bytes[5], 1, "only little-endian ELF is supported");
+        assert_eq!(LittleEndian::read_u16(&bytes[0x12..]), EM_AARCH64);
+
+        let entry = LittleEndian::read_u64(&bytes[0x18..]);
+        let phoff = LittleEndian::read_u64(&bytes[0x20..]) as usize;
+        let shoff = LittleEndian::read_u64(&bytes[0x28..]) as usize;
+        let phentsize = LittleEndian::read_u16(&bytes[0x36..]) as usize;
+        let phnum = LittleEndian::read_u16(&bytes[0x38..]) as usize;
+        let shentsize = LittleEndian::read_u16(&bytes[0x3A..]) as usize;
+        let shnum = LittleEndian::read_u16(&bytes[0x3C..]) as usize;
+        let shstrndx = LittleEndian::read_u16(&bytes[0x3E..]) as usize;
+
+        let program_headers = (0..phnum)
+            .map(|i| ElfProgramHeader::read(&bytes[phoff + i * phentsize..]))
+            .collect();
+
+        let section_headers: Vec<ElfSectionHeader> = if shoff == 0 || shnum == 0 {
+            Vec::new()
+        } else {
+            let shstrtab = &bytes[shoff + shstrndx * shentsize..];
+            let names = &bytes[LittleEndian::read_u64(&shstrtab[0x18..]) as usize..]
+                [..LittleEndian::read_u64(&shstrtab[0x20..]) as usize];
+            (0..shnum).map(|i| ElfSectionHeader::read(&bytes[shoff + i * shentsize..], names)).collect()
+        };
+
+        let symbols = match section_headers.iter().find(|s| s.typ == SHT_SYMTAB) {
+            Some(symtab) => {
+                let strtab = section_headers[symtab.link as usize].data(bytes);
+                let entry_size = if symtab.entsize == 0 { SYMBOL_SIZE } else { symtab.entsize as usize };
+                symtab.data(bytes).chunks_exact(entry_size).map(|raw| Symbol::read(raw, strtab)).collect()
+            }
+            None => Vec::new(),
+        };
+
+        let build_id = section_headers.iter()
+            .filter(|s| s.typ == SHT_NOTE)
+            .find_map(|s| read_gnu_build_id(s.data(bytes)));
+
+        ElfFile { entry, program_headers, section_headers, symbols, build_id }
+    }
+
+    pub fn section(&self, name: &str) -> Option<&ElfSectionHeader> {
+        self.section_headers.iter().find(|s| s.name == name)
+    }
+
+    /// The `.debug_*` sections holding DWARF debug info.
+    pub fn dwarf_sections(&self) -> impl Iterator<Item = &ElfSectionHeader> {
+        self.section_headers.iter().filter(|s| s.name.starts_with(".debug_"))
+    }
+
+    /// Lowest virtual address of the PT_LOADs backing a segment.
+    pub fn load_address(&self, segment: NroSegmentType) -> Option<u64> {
+        self.load_segments(segment).map(|p| p.vaddr).min()
+    }
+
+    fn load_segments(&self, segment: NroSegmentType) -> impl Iterator<Item = &ElfProgramHeader> {
+        self.program_headers.iter().filter(move |p| p.segment_type() == Some(segment))
+    }
+
+    /// Concatenates the PT_LOADs of one kind in address order, zero-filling
+    /// the gaps between them. Returns the file-backed bytes and how much
+    /// zero-initialized memory follows them.
+    /// A single PT_LOAD is borrowed from `file_bytes` as-is.
+    fn load<'b>(&self, file_bytes: &'b [u8], segment: NroSegmentType) -> (Cow<'b, [u8]>, usize) {
+        let mut headers: Vec<&ElfProgramHeader> = self.load_segments(segment).collect();
+        headers.sort_by_key(|p| p.vaddr);
+        let Some(start) = headers.first().map(|p| p.vaddr) else {
+            return (Cow::Borrowed(&[]), 0);
+        };
+        if let [header] = headers[..] {
+            let contents = &file_bytes[header.offset as usize..][..header.filesz as usize];
+            return (Cow::Borrowed(contents), (header.memsz - header.filesz) as usize);
+        }
+
+        let mut contents = Vec::new();
+        let mut memory_end = start;
+        for header in headers {
+            contents.resize((header.vaddr - start) as usize, 0);
+            contents.extend_from_slice(&file_bytes[header.offset as usize..][..header.filesz as usize]);
+            memory_end = memory_end.max(header.vaddr + header.memsz);
+        }
+        let zero_fill = (memory_end - start) as usize - contents.len();
+        (Cow::Owned(contents), zero_fill)
+    }
+}
+
+/// Walks the notes in a SHT_NOTE section: namesz, descsz and type, then the
+/// name and the descriptor, each padded to four bytes.
+fn read_gnu_build_id(mut notes: &[u8]) -> Option<Vec<u8>> {
+    let pad = |len: usize| (len + 3) & !3;
+    while notes.len() >= 12 {
+        let name_size = LittleEndian::read_u32(notes) as usize;
+        let desc_size = LittleEndian::read_u32(&notes[4..]) as usize;
+        let typ = LittleEndian::read_u32(&notes[8..]);
+        let name = notes.get(12..12 + name_size)?;
+        let desc = notes.get(12 + pad(name_size)..)?.get(..desc_size)?;
+        if typ == NT_GNU_BUILD_ID && name == b"GNU\0" {
+            return Some(desc.to_vec());
+        }
+        notes = notes.get(12 + pad(name_size) + pad(desc_size)..)?;
+    }
+    None
+}
+
+impl<'a> SwitchExecutable<'a> {
+    /// Loads an AArch64 ELF such as the one devkitA64 links before elf2nro.
+    /// Executable PT_LOADs become .text, writable ones .data and the rest
+    /// .rodata; the memory past the end of .data's file contents is .bss.
+    pub fn read_elf(file_bytes: impl Into<Cow<'a, [u8]>>) -> SwitchExecutable<'a> {
+        let file_bytes = file_bytes.into();
+        let elf = ElfFile::read(&file_bytes);
+        let (text, _) = elf.load(&file_bytes, TEXT);
+        let (ro, _) = elf.load(&file_bytes, RO);
+        let (data, bss_size) = elf.load(&file_bytes, DATA);
+        let (text, ro, data) = (
+            SegmentBytes::new(&file_bytes, text),
+            SegmentBytes::new(&file_bytes, ro),
+            SegmentBytes::new(&file_bytes, data),
+        );
+
+        SwitchExecutable {
+            program: file_bytes,
+            text,
+            ro,
+            data,
+            bss: vec![0; bss_size],
+            icon: None,
+            nacp: None,
+            romfs: None,
+            asset_version: 0,
+            nro_header: None,
+            nso_header: None,
+            kip_header: None,
+            elf: Some(elf),
+        }
+    }
+
+    /// Headers, symbols and debug info, if this executable was read from an ELF.
+    pub fn elf(&self) -> Option<&ElfFile> {
+        self.elf.as_ref()
+    }
+
+    /// Contents of a DWARF section such as `.debug_info`, if this executable
+    /// was read from an ELF that has it.
+    pub fn dwarf_section(&self, name: &str) -> Option<&[u8]> {
+        let elf = self.elf.as_ref()?;
+        elf.dwarf_sections().find(|s| s.name == name).map(|s| s.data(&self.program))
+    }
+
+    /// Writes the executable as an AArch64 ELF. There is one PT_LOAD per
+    /// segment, with .bss folded into the .data one, plus PT_DYNAMIC when the
+    /// module has a MOD0 and PT_NOTE when it has a build ID. Section headers
+    /// are synthesized for .text, .rodata, .data, .bss, .dynamic and its
+    /// .dynstr and .note.gnu.build-id, and the symbols end up in .symtab: the
+    /// original ones for an executable read from an ELF, otherwise the
+    /// dynamic symbols. Addresses are image-relative, as in memory_image().
+    ///
+    /// The build ID note is not loaded: its PT_NOTE has a p_vaddr of 0 that
+    /// no PT_LOAD maps, and its section is not SHF_ALLOC. An ELF's entry
+    /// point and special symbol section indices such as SHN_ABS are kept.
+    pub fn write_elf(&self) -> Vec<u8> {
+        const PHDR_COUNT: usize = 5;
+        let [text_addr, ro_addr, data_addr, bss_addr] = self.segment_offsets().map(|a| a as u64);
+        let mod0 = self.mod0();
+
+        let mut out = vec![0u8; page_align(ElfFile::HEADER_SIZE + PHDR_COUNT * ElfProgramHeader::SIZE)];
+        let mut place = |contents: &[u8]| {
+            let offset = out.len() as u64;
+            out.extend_from_slice(contents);
+            out.resize(page_align(out.len()), 0);
+            offset
+        };
+        let text_offset = place(self.text());
+        let ro_offset = place(self.ro());
+        let data_offset = place(self.data());
+
+        let load = |flags: u32, offset: u64, vaddr: u64, filesz: usize, memsz: usize| ElfProgramHeader {
+            typ: PT_LOAD,
+            flags,
+            offset,
+            vaddr,
+            paddr: vaddr,
+            filesz: filesz as u64,
+            memsz: memsz as u64,
+            align: PAGE_SIZE as u64,
+        };
+        let mut program_headers = vec![
+            load(PF_R | PF_X, text_offset, text_addr, self.text().len(), self.text().len()),
+            load(PF_R, ro_offset, ro_addr, self.ro().len(), self.ro().len()),
+            load(PF_R | PF_W, data_offset, data_addr, self.data().len(), self.data().len() + self.bss.len()),
+        ];
+
+        // Translates an address inside one of the segments to its file offset.
+        let file_offset = |addr: u64| {
+            let segments = [(text_addr, text_offset, self.text().len()), (ro_addr, ro_offset, self.ro().len()), (data_addr, data_offset, self.data().len())];
+            segments.iter()
+                .find(|(start, _, len)| (*start..*start + *len as u64).contains(&addr))
+                .map_or(0, |(start, offset, _)| offset + (addr - start))
+        };
+        let section = |name: &str, typ: u32, flags: u64, addr: u64, size: usize| ElfSectionHeader {
+            name: name.to_string(),
+            typ,
+            flags,
+            addr,
+            offset: if typ == SHT_NOBITS { data_offset + self.data().len() as u64 } else { file_offset(addr) },
+            size: size as u64,
+            addralign: if flags & SHF_EXECINSTR != 0 { 4 } else { 8 },
+            ..Default::default()
+        };
+        let mut sections = vec![
+            ElfSectionHeader::default(),
+            section(".text", SHT_PROGBITS, SHF_ALLOC | SHF_EXECINSTR, text_addr, self.text().len()),
+            section(".rodata", SHT_PROGBITS, SHF_ALLOC, ro_addr, self.ro().len()),
+            section(".data", SHT_PROGBITS, SHF_ALLOC | SHF_WRITE, data_addr, self.data().len()),
+            section(".bss", SHT_NOBITS, SHF_ALLOC | SHF_WRITE, bss_addr, self.bss.len()),
+        ];
+        let section_of = |addr: u64| {
+            let ends = [(1, text_addr, self.text().len()), (2, ro_addr, self.ro().len()), (3, data_addr, self.data().len()), (4, bss_addr, self.bss.len())];
+            ends.iter().find(|(_, start, len)| (*start..*start + *len as u64).contains(&addr)).map_or(0, |(index, _, _)| *index)
+        };
+
+        if let Some(mod0) = &mod0 {
+            let dynamic_addr = mod0.dynamic as u64;
+            let entries = self.dynamic_entries();
+            let size = (entries.len() + 1) * 16;
+            let writable = section_of(dynamic_addr) >= 3;
+            let flags = if writable { SHF_ALLOC | SHF_WRITE } else { SHF_ALLOC };
+            let mut dynamic = section(".dynamic", SHT_DYNAMIC, flags, dynamic_addr, size);
+            dynamic.entsize = 16;
+            // Tools expect .dynamic to link to the string table it refers to.
+            let lookup = |tag: i64| entries.iter().find(|(t, _)| *t == tag).map(|(_, v)| *v);
+            if let (Some(addr), Some(size)) = (lookup(DT_STRTAB), lookup(DT_STRSZ)) {
+                let mut dynstr = section(".dynstr", SHT_STRTAB, SHF_ALLOC, addr, size as usize);
+                dynstr.addralign = 1;
+                sections.push(dynstr);
+                dynamic.link = sections.len() as u32 - 1;
+            }
+            program_headers.push(ElfProgramHeader {
+                typ: PT_DYNAMIC,
+                flags: if writable { PF_R | PF_W } else { PF_R },
+                offset: dynamic.offset,
+                vaddr: dynamic_addr,
+                paddr: dynamic_addr,
+                filesz: size as u64,
+                memsz: size as u64,
+                align: 8,
+            });
+            sections.push(dynamic);
+        }
+
+        // NRO and NSO headers pad the build ID to 0x20 bytes; the note
+        // carries it without the padding.
+        let build_id = match &self.elf {
+            Some(elf) => elf.build_id.clone(),
+            None => Executable::build_id(self).map(|id| {
+                let len = id.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1);
+                id[..len].to_vec()
+            }),
+        };
+        if let Some(build_id) = build_id.filter(|id| !id.is_empty()) {
+            let mut note = Vec::new();
+            note.extend_from_slice(&4u32.to_le_bytes());
+            note.extend_from_slice(&(build_id.len() as u32).to_le_bytes());
+            note.extend_from_slice(&NT_GNU_BUILD_ID.to_le_bytes());
+            note.extend_from_slice(b"GNU\0");
+            note.extend_from_slice(&build_id);
+            note.resize((note.len() + 3) & !3, 0);
+            program_headers.push(ElfProgramHeader {
+                typ: PT_NOTE,
+                flags: PF_R,
+                offset: out.len() as u64,
+                filesz: note.len() as u64,
+                align: 4,
+                ..Default::default()
+            });
+            sections.push(ElfSectionHeader {
+                name: ".note.gnu.build-id".to_string(),
+                typ: SHT_NOTE,
+                offset: out.len() as u64,
+                size: note.len() as u64,
+                addralign: 4,
+                ..Default::default()
+            });
+            out.extend_from_slice(&note);
+        }
+
+        // ELF requires the local symbols to come first.
+        let mut symbols: Vec<Symbol> = Executable::symbols(self).into_iter().skip(1).collect();
+        symbols.sort_by_key(|s| s.binding() != Symbol::STB_LOCAL);
+        let first_global = 1 + symbols.iter().take_while(|s| s.binding() == Symbol::STB_LOCAL).count();
+
+        let mut strtab = vec![0u8];
+        let mut symtab = vec![0u8; SYMBOL_SIZE];
+        for symbol in &symbols {
+            let shndx = match symbol.shndx {
+                0 => 0,
+                special if special >= SHN_LORESERVE => special as usize,
+                _ => section_of(symbol.value),
+            };
+            let name = if symbol.name.is_empty() { 0 } else { strtab.len() as u32 };
+            if !symbol.name.is_empty() {
+                strtab.extend_from_slice(symbol.name.as_bytes());
+                strtab.push(0);
+            }
+            let mut raw = [0u8; SYMBOL_SIZE];
+            LittleEndian::write_u32(&mut raw[0..], name);
+            raw[4] = symbol.info;
+            raw[5] = symbol.other;
+            LittleEndian::write_u16(&mut raw[6..], shndx as u16);
+            LittleEndian::write_u64(&mut raw[8..], symbol.value);
+            LittleEndian::write_u64(&mut raw[16..], symbol.size);
+            symtab.extend_from_slice(&raw);
+        }
+
+        let symtab_index = sections.len();
+        sections.push(ElfSectionHeader {
+            name: ".symtab".to_string(),
+            typ: SHT_SYMTAB,
+            offset: out.len() as u64,
+            size: symtab.len() as u64,
+            link: symtab_index as u32 + 1,
+            info: first_global as u32,
+            addralign: 8,
+            entsize: SYMBOL_SIZE as u64,
+            ..Default::default()
+        });
+        out.extend_from_slice(&symtab);
+        sections.push(ElfSectionHeader {
+            name: ".strtab".to_string(),
+            typ: SHT_STRTAB,
+            offset: out.len() as u64,
+            size: strtab.len() as u64,
+            addralign: 1,
+            ..Default::default()
+        });
+        out.extend_from_slice(&strtab);
+
+        let mut shstrtab = vec![0u8];
+        let mut name_offsets = Vec::new();
+        sections.push(ElfSectionHeader { name: ".shstrtab".to_string(), typ: SHT_STRTAB, addralign: 1, ..Default::default() });
+        for section in &sections {
+            if section.name.is_empty() {
+                name_offsets.push(0);
+                continue;
+            }
+            name_offsets.push(shstrtab.len() as u32);
+            shstrtab.extend_from_slice(section.name.as_bytes());
+            shstrtab.push(0);
+        }
+        let shstrndx = sections.len() - 1;
+        sections[shstrndx].offset = out.len() as u64;
+        sections[shstrndx].size = shstrtab.len() as u64;
+        out.extend_from_slice(&shstrtab);
+
+        out.resize((out.len() + 7) & !7, 0);
+        let shoff = out.len();
+        for (section, name_offset) in sections.iter().zip(name_offsets) {
+            out.extend_from_slice(&section.to_bytes(name_offset));
+        }
+        for (index, header) in program_headers.iter().enumerate() {
+            let at = ElfFile::HEADER_SIZE + index * ElfProgramHeader::SIZE;
+            out[at..][..ElfProgramHeader::SIZE].copy_from_slice(&header.to_bytes());
+        }
+
+        out[..4].copy_from_slice(ElfFile::MAGIC);
+        out[4] = 2; // ELFCLASS64
+        out[5] = 1; // ELFDATA2LSB
+        out[6] = 1; // EV_CURRENT
+        LittleEndian::write_u16(&mut out[0x10..], ET_DYN);
+        LittleEndian::write_u16(&mut out[0x12..], EM_AARCH64);
+        LittleEndian::write_u32(&mut out[0x14..], 1);
+        LittleEndian::write_u64(&mut out[0x18..], Executable::entry_point(self));
+        LittleEndian::write_u64(&mut out[0x20..], ElfFile::HEADER_SIZE as u64);
+        LittleEndian::write_u64(&mut out[0x28..], shoff as u64);
+        LittleEndian::write_u16(&mut out[0x34..], ElfFile::HEADER_SIZE as u16);
+        LittleEndian::write_u16(&mut out[0x36..], ElfProgramHeader::SIZE as u16);
+        LittleEndian::write_u16(&mut out[0x38..], program_headers.len() as u16);
+        LittleEndian::write_u16(&mut out[0x3A..], ElfSectionHeader::SIZE as u16);
+        LittleEndian::write_u16(&mut out[0x3C..], sections.len() as u16);
+        LittleEndian::write_u16(&mut out[0x3E..], shstrndx as u16);
+        out
+    }
+
+    /// Image-relative start of text, ro, data and bss as the ELF's program
+    /// headers place them. Segments the ELF lacks follow the previous one.
+    pub(crate) fn elf_segment_offsets(&self, elf: &ElfFile) -> [usize; 4] {
+        let text = elf.load_address(TEXT).map_or(0, |a| a as usize);
+        let ro = elf.load_address(RO).map_or(page_align(text + self.text().len()), |a| a as usize);
+        let data = elf.load_address(DATA).map_or(page_align(ro + self.ro().len()), |a| a as usize);
+        [text, ro, data, data + self.data().len()]
+    }
+}
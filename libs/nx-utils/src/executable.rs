@@ -0,0 +1,154 @@
+use crate::loader::{Relocation, Symbol};
+use crate::SwitchExecutable;
+
+/// Memory protection of a segment.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Permissions {
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
+}
+
+impl Permissions {
+    pub const R: Permissions = Permissions { read: true, write: false, execute: false };
+    pub const RW: Permissions = Permissions { read: true, write: true, execute: false };
+    pub const RX: Permissions = Permissions { read: true, write: false, execute: true };
+}
+
+/// One mapped region of an executable. Memory past the end of `data` up to
+/// `memory_size` is zero-initialized.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Segment<'a> {
+    pub name: &'static str,
+    pub vaddr: u64,
+    pub memory_size: u64,
+    pub permissions: Permissions,
+    pub data: &'a [u8],
+}
+
+impl<'a> Segment<'a> {
+    /// A segment that is exactly as large in memory as in the file.
+    pub fn new(name: &'static str, vaddr: u64, data: &'a [u8], permissions: Permissions) -> Segment<'a> {
+        Segment { name, vaddr, memory_size: data.len() as u64, permissions, data }
+    }
+}
+
+/// A container-agnostic view of a loadable module. Addresses are relative
+/// to the module's load base.
+pub trait Executable {
+    fn segments(&self) -> Vec<Segment<'_>>;
+
+    fn entry_point(&self) -> u64;
+
+    /// The 0x20-byte build ID the loader and patch formats key modules by.
+    /// Shorter IDs, such as a GNU build ID note, are zero-padded.
+    fn build_id(&self) -> Option<[u8; 0x20]>;
+
+    fn module_name(&self) -> Option<String>;
+
+    fn symbols(&self) -> Vec<Symbol>;
+
+    fn relocations(&self) -> Vec<Relocation>;
+}
+
+/// The container a [`SwitchExecutable`] was read from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExecutableFormat {
+    Nro,
+    Nso,
+    Kip,
+    Elf,
+}
+
+impl<'a> SwitchExecutable<'a> {
+    pub fn format(&self) -> ExecutableFormat {
+        if self.nro_header.is_some() {
+            ExecutableFormat::Nro
+        } else if self.nso_header.is_some() {
+            ExecutableFormat::Nso
+        } else if self.kip_header.is_some() {
+            ExecutableFormat::Kip
+        } else {
+            ExecutableFormat::Elf
+        }
+    }
+
+    /// The path the SDK toolchain records at the start of .rodata: a zero
+    /// word, the path length, then the path itself.
+    fn rodata_module_path(&self) -> Option<&str> {
+        if self.ro().len() < 8 || self.ro()[..4] != [0; 4] {
+            return None;
+        }
+        let len = u32::from_le_bytes(self.ro()[4..8].try_into().unwrap()) as usize;
+        let path = std::str::from_utf8(self.ro().get(8..8 + len)?).ok()?;
+        let path = path.trim_end_matches('\0');
+        (!path.is_empty()).then_some(path)
+    }
+}
+
+/// Every format the crate reads is loaded into a [`SwitchExecutable`]; this
+/// picks the build ID, name and symbols from whichever container it was.
+impl Executable for SwitchExecutable<'_> {
+    fn segments(&self) -> Vec<Segment<'_>> {
+        let [text, ro, data, bss] = self.segment_offsets().map(|o| o as u64);
+        let mut bss_segment = Segment::new(".bss", bss, &[], Permissions::RW);
+        bss_segment.memory_size = self.bss.len() as u64;
+        vec![
+            Segment::new(".text", text, self.text(), Permissions::RX),
+            Segment::new(".rodata", ro, self.ro(), Permissions::R),
+            Segment::new(".data", data, self.data(), Permissions::RW),
+            bss_segment,
+        ]
+    }
+
+    fn entry_point(&self) -> u64 {
+        match &self.elf {
+            Some(elf) => elf.entry,
+            None => self.segment_offsets()[0] as u64,
+        }
+    }
+
+    fn build_id(&self) -> Option<[u8; 0x20]> {
+        let pad = |id: &[u8]| {
+            let mut padded = [0u8; 0x20];
+            let len = id.len().min(0x20);
+            padded[..len].copy_from_slice(&id[..len]);
+            padded
+        };
+        match self.format() {
+            ExecutableFormat::Nro => self.nro_header.as_ref().map(|h| h.build_id),
+            ExecutableFormat::Nso => self.nso_header.as_ref().map(|h| h.build_id),
+            ExecutableFormat::Kip => None,
+            ExecutableFormat::Elf => self.elf.as_ref()?.build_id.as_deref().map(pad),
+        }
+    }
+
+    /// The KIP name, the NSO module name, or else the file name of the
+    /// module path in .rodata.
+    fn module_name(&self) -> Option<String> {
+        if let Some(header) = &self.kip_header {
+            return Some(header.name.clone());
+        }
+        if let Some(header) = &self.nso_header {
+            let name = String::from_utf8_lossy(&header.module_name);
+            let name = name.trim_end_matches('\0');
+            if !name.is_empty() {
+                return Some(name.to_string());
+            }
+        }
+        let path = self.rodata_module_path()?;
+        Some(path.rsplit(['/', '\\']).next().unwrap_or(path).to_string())
+    }
+
+    /// The full .symtab of an ELF, otherwise the dynamic symbols.
+    fn symbols(&self) -> Vec<Symbol> {
+        match &self.elf {
+            Some(elf) if !elf.symbols.is_empty() => elf.symbols.clone(),
+            _ => self.dynamic_symbols(),
+        }
+    }
+
+    fn relocations(&self) -> Vec<Relocation> {
+        SwitchExecutable::relocations(self)
+    }
+}
@@ -0,0 +1,842 @@
+use std::collections::HashMap;
+use std::io::Cursor;
+
+use byteorder::{ByteOrder, LittleEndian, ReadBytesExt};
+
+use crate::result::NxResult;
+
+/// The two words at the start of every HIPC message.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HipcHeader {
+    pub message_type: u16,
+    pub num_send_statics: u8,
+    pub num_send_buffers: u8,
+    pub num_recv_buffers: u8,
+    pub num_exch_buffers: u8,
+    pub num_data_words: u16,
+    /// 0 for no receive list, 2 for a single entry spanning the pointer
+    /// buffer, otherwise two more than the number of entries.
+    pub recv_static_mode: u8,
+    /// In words from the start of the message; 0 places the receive list
+    /// right after the data words.
+    pub recv_list_offset: u16,
+    pub has_special_header: bool,
+}
+
+impl HipcHeader {
+    pub const SIZE: usize = 8;
+
+    pub fn new(reader: &mut Cursor<&[u8]>) -> HipcHeader {
+        let word0 = reader.read_u32::<LittleEndian>().unwrap();
+        let word1 = reader.read_u32::<LittleEndian>().unwrap();
+        HipcHeader {
+            message_type: word0 as u16,
+            num_send_statics: (word0 >> 16) as u8 & 0xF,
+            num_send_buffers: (word0 >> 20) as u8 & 0xF,
+            num_recv_buffers: (word0 >> 24) as u8 & 0xF,
+            num_exch_buffers: (word0 >> 28) as u8 & 0xF,
+            num_data_words: word1 as u16 & 0x3FF,
+            recv_static_mode: (word1 >> 10) as u8 & 0xF,
+            recv_list_offset: (word1 >> 20) as u16 & 0x7FF,
+            has_special_header: word1 >> 31 != 0,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let word0 = self.message_type as u32
+            | (self.num_send_statics as u32 & 0xF) << 16
+            | (self.num_send_buffers as u32 & 0xF) << 20
+            | (self.num_recv_buffers as u32 & 0xF) << 24
+            | (self.num_exch_buffers as u32 & 0xF) << 28;
+        let word1 = (self.num_data_words as u32 & 0x3FF)
+            | (self.recv_static_mode as u32 & 0xF) << 10
+            | (self.recv_list_offset as u32 & 0x7FF) << 20
+            | (self.has_special_header as u32) << 31;
+        let mut out = vec![0u8; HipcHeader::SIZE];
+        LittleEndian::write_u32(&mut out[0..], word0);
+        LittleEndian::write_u32(&mut out[4..], word1);
+        out
+    }
+
+    /// How many receive list (C) entries `recv_static_mode` implies.
+    pub fn num_recv_statics(&self) -> usize {
+        match self.recv_static_mode {
+            0 | 1 => 0,
+            2 => 1,
+            mode => mode as usize - 2,
+        }
+    }
+}
+
+/// The word after the HIPC header when the kernel has to translate a
+/// process ID or handles.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SpecialHeader {
+    pub send_pid: bool,
+    pub num_copy_handles: u8,
+    pub num_move_handles: u8,
+}
+
+impl SpecialHeader {
+    pub const SIZE: usize = 4;
+
+    pub fn new(reader: &mut Cursor<&[u8]>) -> SpecialHeader {
+        let word = reader.read_u32::<LittleEndian>().unwrap();
+        SpecialHeader {
+            send_pid: word & 1 != 0,
+            num_copy_handles: (word >> 1) as u8 & 0xF,
+            num_move_handles: (word >> 5) as u8 & 0xF,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let word = self.send_pid as u32
+            | (self.num_copy_handles as u32 & 0xF) << 1
+            | (self.num_move_handles as u32 & 0xF) << 5;
+        word.to_le_bytes().to_vec()
+    }
+}
+
+/// An X descriptor: a buffer the kernel copies into the receiver's
+/// receive list entry with the same index.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StaticDescriptor {
+    pub index: u8,
+    pub address: u64,
+    pub size: u16,
+}
+
+impl StaticDescriptor {
+    pub const SIZE: usize = 8;
+
+    pub fn new(reader: &mut Cursor<&[u8]>) -> StaticDescriptor {
+        let word0 = reader.read_u32::<LittleEndian>().unwrap();
+        let address_low = reader.read_u32::<LittleEndian>().unwrap();
+        let address_high = (word0 >> 6) as u64 & 0x3F;
+        let address_mid = (word0 >> 12) as u64 & 0xF;
+        StaticDescriptor {
+            index: word0 as u8 & 0x3F,
+            address: address_low as u64 | address_mid << 32 | address_high << 36,
+            size: (word0 >> 16) as u16,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let word0 = (self.index as u32 & 0x3F)
+            | ((self.address >> 36) as u32 & 0x3F) << 6
+            | ((self.address >> 32) as u32 & 0xF) << 12
+            | (self.size as u32) << 16;
+        let mut out = vec![0u8; StaticDescriptor::SIZE];
+        LittleEndian::write_u32(&mut out[0..], word0);
+        LittleEndian::write_u32(&mut out[4..], self.address as u32);
+        out
+    }
+}
+
+/// How the kernel maps an A/B/W buffer into the receiver.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BufferMode {
+    #[default]
+    Normal = 0,
+    NonSecure = 1,
+    Invalid = 2,
+    NonDevice = 3,
+}
+
+impl BufferMode {
+    fn from_bits(bits: u32) -> BufferMode {
+        match bits & 3 {
+            0 => BufferMode::Normal,
+            1 => BufferMode::NonSecure,
+            2 => BufferMode::Invalid,
+            _ => BufferMode::NonDevice,
+        }
+    }
+}
+
+/// An A (send), B (receive) or W (exchange) descriptor for a buffer that
+/// is mapped into the receiver rather than copied.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BufferDescriptor {
+    pub address: u64,
+    pub size: u64,
+    pub mode: BufferMode,
+}
+
+impl BufferDescriptor {
+    pub const SIZE: usize = 12;
+
+    pub fn new(reader: &mut Cursor<&[u8]>) -> BufferDescriptor {
+        let size_low = reader.read_u32::<LittleEndian>().unwrap();
+        let address_low = reader.read_u32::<LittleEndian>().unwrap();
+        let word2 = reader.read_u32::<LittleEndian>().unwrap();
+        let address_high = (word2 >> 2) as u64 & 0x3F_FFFF;
+        let size_high = (word2 >> 24) as u64 & 0xF;
+        let address_mid = (word2 >> 28) as u64 & 0xF;
+        BufferDescriptor {
+            address: address_low as u64 | address_mid << 32 | address_high << 36,
+            size: size_low as u64 | size_high << 32,
+            mode: BufferMode::from_bits(word2),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let word2 = self.mode as u32
+            | ((self.address >> 36) as u32 & 0x3F_FFFF) << 2
+            | ((self.size >> 32) as u32 & 0xF) << 24
+            | ((self.address >> 32) as u32 & 0xF) << 28;
+        let mut out = vec![0u8; BufferDescriptor::SIZE];
+        LittleEndian::write_u32(&mut out[0..], self.size as u32);
+        LittleEndian::write_u32(&mut out[4..], self.address as u32);
+        LittleEndian::write_u32(&mut out[8..], word2);
+        out
+    }
+}
+
+/// A C descriptor: where the receiver accepts X buffers.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RecvListEntry {
+    pub address: u64,
+    pub size: u16,
+}
+
+impl RecvListEntry {
+    pub const SIZE: usize = 8;
+
+    pub fn new(reader: &mut Cursor<&[u8]>) -> RecvListEntry {
+        let address_low = reader.read_u32::<LittleEndian>().unwrap();
+        let word1 = reader.read_u32::<LittleEndian>().unwrap();
+        RecvListEntry {
+            address: address_low as u64 | (word1 as u64 & 0xFFFF) << 32,
+            size: (word1 >> 16) as u16,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = vec![0u8; RecvListEntry::SIZE];
+        LittleEndian::write_u32(&mut out[0..], self.address as u32);
+        LittleEndian::write_u32(&mut out[4..], (self.address >> 32) as u32 & 0xFFFF | (self.size as u32) << 16);
+        out
+    }
+}
+
+/// A complete HIPC message, as found in TLS at `svcSendSyncRequest` or
+/// after `svcReplyAndReceive`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HipcMessage {
+    pub message_type: u16,
+    pub pid: Option<u64>,
+    pub copy_handles: Vec<u32>,
+    pub move_handles: Vec<u32>,
+    /// X descriptors.
+    pub send_statics: Vec<StaticDescriptor>,
+    /// A descriptors.
+    pub send_buffers: Vec<BufferDescriptor>,
+    /// B descriptors.
+    pub recv_buffers: Vec<BufferDescriptor>,
+    /// W descriptors.
+    pub exch_buffers: Vec<BufferDescriptor>,
+    /// The raw data words, including any CMIF alignment padding.
+    pub data: Vec<u8>,
+    /// C descriptors.
+    pub recv_list: Vec<RecvListEntry>,
+}
+
+impl HipcMessage {
+    /// The size of a thread's IPC buffer at the start of TLS.
+    pub const MAX_SIZE: usize = 0x100;
+
+    /// Returns None if the counts in the headers run past the end of `bytes`.
+    pub fn parse(bytes: &[u8]) -> Option<HipcMessage> {
+        if bytes.len() < HipcHeader::SIZE {
+            return None;
+        }
+        let mut reader = Cursor::new(bytes);
+        let header = HipcHeader::new(&mut reader);
+        let special = if header.has_special_header {
+            (bytes.len() >= HipcHeader::SIZE + SpecialHeader::SIZE).then(|| SpecialHeader::new(&mut reader))?
+        } else {
+            SpecialHeader::default()
+        };
+
+        let data_offset = HipcHeader::SIZE
+            + header.has_special_header as usize * SpecialHeader::SIZE
+            + special.send_pid as usize * 8
+            + (special.num_copy_handles + special.num_move_handles) as usize * 4
+            + header.num_send_statics as usize * StaticDescriptor::SIZE
+            + (header.num_send_buffers + header.num_recv_buffers + header.num_exch_buffers) as usize
+                * BufferDescriptor::SIZE;
+        let data_end = data_offset + header.num_data_words as usize * 4;
+        let recv_list_offset = match header.recv_list_offset {
+            0 => data_end,
+            words => words as usize * 4,
+        };
+        let recv_list_end = recv_list_offset + header.num_recv_statics() * RecvListEntry::SIZE;
+        if bytes.len() < data_end || bytes.len() < recv_list_end {
+            return None;
+        }
+
+        let pid = special.send_pid.then(|| reader.read_u64::<LittleEndian>().unwrap());
+        let mut handles = |count: u8| (0..count).map(|_| reader.read_u32::<LittleEndian>().unwrap()).collect();
+        let copy_handles = handles(special.num_copy_handles);
+        let move_handles = handles(special.num_move_handles);
+        let send_statics = (0..header.num_send_statics).map(|_| StaticDescriptor::new(&mut reader)).collect();
+        let mut buffers = |count: u8| (0..count).map(|_| BufferDescriptor::new(&mut reader)).collect();
+        let send_buffers = buffers(header.num_send_buffers);
+        let recv_buffers = buffers(header.num_recv_buffers);
+        let exch_buffers = buffers(header.num_exch_buffers);
+
+        reader.set_position(recv_list_offset as u64);
+        let recv_list = (0..header.num_recv_statics()).map(|_| RecvListEntry::new(&mut reader)).collect();
+
+        Some(HipcMessage {
+            message_type: header.message_type,
+            pid,
+            copy_handles,
+            move_handles,
+            send_statics,
+            send_buffers,
+            recv_buffers,
+            exch_buffers,
+            data: bytes[data_offset..data_end].to_vec(),
+            recv_list,
+        })
+    }
+
+    fn has_special_header(&self) -> bool {
+        self.pid.is_some() || !self.copy_handles.is_empty() || !self.move_handles.is_empty()
+    }
+
+    /// Where the data words start, which CMIF aligns its payload against.
+    pub fn data_offset(&self) -> usize {
+        HipcHeader::SIZE
+            + self.has_special_header() as usize * SpecialHeader::SIZE
+            + self.pid.map_or(0, |_| 8)
+            + (self.copy_handles.len() + self.move_handles.len()) * 4
+            + self.send_statics.len() * StaticDescriptor::SIZE
+            + (self.send_buffers.len() + self.recv_buffers.len() + self.exch_buffers.len()) * BufferDescriptor::SIZE
+    }
+
+    pub fn header(&self) -> HipcHeader {
+        HipcHeader {
+            message_type: self.message_type,
+            num_send_statics: self.send_statics.len() as u8,
+            num_send_buffers: self.send_buffers.len() as u8,
+            num_recv_buffers: self.recv_buffers.len() as u8,
+            num_exch_buffers: self.exch_buffers.len() as u8,
+            num_data_words: self.data.len().div_ceil(4) as u16,
+            recv_static_mode: match self.recv_list.len() {
+                0 => 0,
+                count => count as u8 + 2,
+            },
+            recv_list_offset: 0,
+            has_special_header: self.has_special_header(),
+        }
+    }
+
+    /// Data is padded to whole words and the receive list follows it.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = self.header().to_bytes();
+        if self.has_special_header() {
+            let special = SpecialHeader {
+                send_pid: self.pid.is_some(),
+                num_copy_handles: self.copy_handles.len() as u8,
+                num_move_handles: self.move_handles.len() as u8,
+            };
+            out.extend(special.to_bytes());
+        }
+        if let Some(pid) = self.pid {
+            out.extend(pid.to_le_bytes());
+        }
+        for handle in self.copy_handles.iter().chain(&self.move_handles) {
+            out.extend(handle.to_le_bytes());
+        }
+        for descriptor in &self.send_statics {
+            out.extend(descriptor.to_bytes());
+        }
+        for descriptor in self.send_buffers.iter().chain(&self.recv_buffers).chain(&self.exch_buffers) {
+            out.extend(descriptor.to_bytes());
+        }
+        out.extend(&self.data);
+        out.resize(out.len().next_multiple_of(4), 0);
+        for entry in &self.recv_list {
+            out.extend(entry.to_bytes());
+        }
+        out
+    }
+}
+
+/// The HIPC message type of a CMIF request.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CmifCommandType {
+    Invalid = 0,
+    LegacyRequest = 1,
+    Close = 2,
+    LegacyControl = 3,
+    Request = 4,
+    Control = 5,
+    RequestWithContext = 6,
+    ControlWithContext = 7,
+}
+
+impl CmifCommandType {
+    pub fn from_message_type(message_type: u16) -> Option<CmifCommandType> {
+        Some(match message_type {
+            0 => CmifCommandType::Invalid,
+            1 => CmifCommandType::LegacyRequest,
+            2 => CmifCommandType::Close,
+            3 => CmifCommandType::LegacyControl,
+            4 => CmifCommandType::Request,
+            5 => CmifCommandType::Control,
+            6 => CmifCommandType::RequestWithContext,
+            7 => CmifCommandType::ControlWithContext,
+            _ => return None,
+        })
+    }
+}
+
+/// Command IDs of `Control` requests, which act on the session itself.
+#[allow(non_snake_case)]
+pub mod CmifControl {
+    pub const CONVERT_CURRENT_OBJECT_TO_DOMAIN: u32 = 0;
+    pub const COPY_FROM_CURRENT_DOMAIN: u32 = 1;
+    pub const CLONE_CURRENT_OBJECT: u32 = 2;
+    pub const QUERY_POINTER_BUFFER_SIZE: u32 = 3;
+    pub const CLONE_CURRENT_OBJECT_EX: u32 = 4;
+}
+
+/// The `SFCI` header in front of a request's arguments.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CmifInHeader {
+    pub version: u32,
+    pub command_id: u32,
+    pub token: u32,
+}
+
+impl CmifInHeader {
+    pub const MAGIC: &'static [u8; 4] = b"SFCI";
+    pub const SIZE: usize = 0x10;
+
+    pub fn new(reader: &mut Cursor<&[u8]>) -> CmifInHeader {
+        let magic = reader.read_u32::<LittleEndian>().unwrap();
+        assert_eq!(&magic.to_le_bytes(), CmifInHeader::MAGIC);
+        CmifInHeader {
+            version: reader.read_u32::<LittleEndian>().unwrap(),
+            command_id: reader.read_u32::<LittleEndian>().unwrap(),
+            token: reader.read_u32::<LittleEndian>().unwrap(),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = vec![0u8; CmifInHeader::SIZE];
+        out[0x0..0x4].copy_from_slice(CmifInHeader::MAGIC);
+        LittleEndian::write_u32(&mut out[0x4..], self.version);
+        LittleEndian::write_u32(&mut out[0x8..], self.command_id);
+        LittleEndian::write_u32(&mut out[0xC..], self.token);
+        out
+    }
+}
+
+/// The `SFCO` header in front of a response's return values.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CmifOutHeader {
+    pub version: u32,
+    pub result: NxResult,
+    pub token: u32,
+}
+
+impl CmifOutHeader {
+    pub const MAGIC: &'static [u8; 4] = b"SFCO";
+    pub const SIZE: usize = 0x10;
+
+    pub fn new(reader: &mut Cursor<&[u8]>) -> CmifOutHeader {
+        let magic = reader.read_u32::<LittleEndian>().unwrap();
+        assert_eq!(&magic.to_le_bytes(), CmifOutHeader::MAGIC);
+        CmifOutHeader {
+            version: reader.read_u32::<LittleEndian>().unwrap(),
+            result: NxResult(reader.read_u32::<LittleEndian>().unwrap()),
+            token: reader.read_u32::<LittleEndian>().unwrap(),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = vec![0u8; CmifOutHeader::SIZE];
+        out[0x0..0x4].copy_from_slice(CmifOutHeader::MAGIC);
+        LittleEndian::write_u32(&mut out[0x4..], self.version);
+        LittleEndian::write_u32(&mut out[0x8..], self.result.0);
+        LittleEndian::write_u32(&mut out[0xC..], self.token);
+        out
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DomainRequestType {
+    Invalid = 0,
+    SendMessage = 1,
+    Close = 2,
+}
+
+/// The header a domain session puts in front of the `SFCI` header to pick
+/// the object the request is for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DomainInHeader {
+    pub request_type: DomainRequestType,
+    pub num_in_objects: u8,
+    /// Size of the `SFCI` header and arguments that follow.
+    pub data_size: u16,
+    pub object_id: u32,
+    pub token: u32,
+}
+
+impl DomainInHeader {
+    pub const SIZE: usize = 0x10;
+
+    pub fn new(reader: &mut Cursor<&[u8]>) -> DomainInHeader {
+        let request_type = match reader.read_u8().unwrap() {
+            1 => DomainRequestType::SendMessage,
+            2 => DomainRequestType::Close,
+            _ => DomainRequestType::Invalid,
+        };
+        let num_in_objects = reader.read_u8().unwrap();
+        let data_size = reader.read_u16::<LittleEndian>().unwrap();
+        let object_id = reader.read_u32::<LittleEndian>().unwrap();
+        reader.read_u32::<LittleEndian>().unwrap();
+        let token = reader.read_u32::<LittleEndian>().unwrap();
+        DomainInHeader { request_type, num_in_objects, data_size, object_id, token }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = vec![0u8; DomainInHeader::SIZE];
+        out[0x0] = self.request_type as u8;
+        out[0x1] = self.num_in_objects;
+        LittleEndian::write_u16(&mut out[0x2..], self.data_size);
+        LittleEndian::write_u32(&mut out[0x4..], self.object_id);
+        LittleEndian::write_u32(&mut out[0xC..], self.token);
+        out
+    }
+}
+
+/// Where a domain request is routed and the objects it passes along.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DomainRequest {
+    pub request_type: DomainRequestType,
+    pub object_id: u32,
+    pub in_objects: Vec<u32>,
+    pub token: u32,
+}
+
+/// The CMIF layer of a request. `header` is None for `Close` messages and
+/// domain close requests, which carry no command.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CmifRequest {
+    pub command_type: CmifCommandType,
+    pub domain: Option<DomainRequest>,
+    pub header: Option<CmifInHeader>,
+    pub payload: Vec<u8>,
+}
+
+/// CMIF data starts at the first 16-byte boundary of the data words, and
+/// 16 bytes of padding are reserved around it in total.
+fn cmif_data_start(message: &HipcMessage) -> usize {
+    message.data_offset().next_multiple_of(16) - message.data_offset()
+}
+
+fn cmif_data_words(message: &HipcMessage, cmif: &[u8]) -> Vec<u8> {
+    let start = cmif_data_start(message);
+    let mut data = vec![0u8; start];
+    data.extend(cmif);
+    data.resize((0x10 + cmif.len()).next_multiple_of(4), 0);
+    data
+}
+
+impl CmifRequest {
+    pub fn command_id(&self) -> Option<u32> {
+        self.header.map(|h| h.command_id)
+    }
+
+    /// Decodes the data words of `message`. A request to a domain session
+    /// has to be parsed with `is_domain` set. The payload runs to the end of
+    /// the data words, since only the server knows the argument size.
+    pub fn parse(message: &HipcMessage, is_domain: bool) -> Option<CmifRequest> {
+        let command_type = CmifCommandType::from_message_type(message.message_type)?;
+        let mut data = message.data.get(cmif_data_start(message)..).unwrap_or_default();
+        if command_type == CmifCommandType::Close {
+            return Some(CmifRequest { command_type, domain: None, header: None, payload: Vec::new() });
+        }
+
+        let mut domain = None;
+        let is_domain = is_domain && matches!(command_type, CmifCommandType::Request | CmifCommandType::RequestWithContext);
+        if is_domain {
+            let header = DomainInHeader::new(&mut Cursor::new(data.get(..DomainInHeader::SIZE)?));
+            let body = data.get(DomainInHeader::SIZE..DomainInHeader::SIZE + header.data_size as usize)?;
+            let objects = &data[DomainInHeader::SIZE + body.len()..];
+            let in_objects = objects.get(..header.num_in_objects as usize * 4)?
+                .chunks(4)
+                .map(LittleEndian::read_u32)
+                .collect();
+            domain = Some(DomainRequest {
+                request_type: header.request_type,
+                object_id: header.object_id,
+                in_objects,
+                token: header.token,
+            });
+            data = body;
+            if header.request_type != DomainRequestType::SendMessage {
+                return Some(CmifRequest { command_type, domain, header: None, payload: data.to_vec() });
+            }
+        }
+
+        if data.get(..4)? != CmifInHeader::MAGIC {
+            return None;
+        }
+        let header = CmifInHeader::new(&mut Cursor::new(data.get(..CmifInHeader::SIZE)?));
+        Some(CmifRequest { command_type, domain, header: Some(header), payload: data[CmifInHeader::SIZE..].to_vec() })
+    }
+
+    /// Fills in the message type and data words of `message`. Handles and
+    /// descriptors have to be in place first, as they move the data words.
+    pub fn write_to(&self, message: &mut HipcMessage) {
+        message.message_type = self.command_type as u16;
+        if self.command_type == CmifCommandType::Close {
+            message.data.clear();
+            return;
+        }
+        let mut body = Vec::new();
+        if let Some(header) = &self.header {
+            body.extend(header.to_bytes());
+        }
+        body.extend(&self.payload);
+
+        let mut cmif = Vec::new();
+        if let Some(domain) = &self.domain {
+            let header = DomainInHeader {
+                request_type: domain.request_type,
+                num_in_objects: domain.in_objects.len() as u8,
+                data_size: body.len() as u16,
+                object_id: domain.object_id,
+                token: domain.token,
+            };
+            cmif.extend(header.to_bytes());
+        }
+        cmif.extend(body);
+        for object in self.domain.iter().flat_map(|d| &d.in_objects) {
+            cmif.extend(object.to_le_bytes());
+        }
+        message.data = cmif_data_words(message, &cmif);
+    }
+}
+
+/// The CMIF layer of a response. `out_objects` is Some for domain sessions.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CmifResponse {
+    pub header: CmifOutHeader,
+    pub out_objects: Option<Vec<u32>>,
+    pub payload: Vec<u8>,
+}
+
+impl CmifResponse {
+    pub fn new(result: NxResult, payload: Vec<u8>) -> CmifResponse {
+        CmifResponse { header: CmifOutHeader { version: 0, result, token: 0 }, out_objects: None, payload }
+    }
+
+    /// Decodes the data words of `message`. Domain out objects follow the
+    /// return values, so the caller has to know their size; the payload is
+    /// cut to `payload_size` either way.
+    pub fn parse(message: &HipcMessage, is_domain: bool, payload_size: usize) -> Option<CmifResponse> {
+        let mut data = message.data.get(cmif_data_start(message)..)?;
+        let mut num_out_objects = None;
+        if is_domain {
+            num_out_objects = Some(LittleEndian::read_u32(data.get(..4)?) as usize);
+            data = data.get(0x10..)?;
+        }
+        if data.get(..4)? != CmifOutHeader::MAGIC {
+            return None;
+        }
+        let header = CmifOutHeader::new(&mut Cursor::new(data.get(..CmifOutHeader::SIZE)?));
+        // A failed command carries no return values.
+        let payload_size = if header.result.is_success() { payload_size } else { 0 };
+        let payload = data.get(CmifOutHeader::SIZE..CmifOutHeader::SIZE + payload_size)?;
+        let out_objects = match num_out_objects {
+            Some(count) => {
+                let at = CmifOutHeader::SIZE + payload_size;
+                Some(data.get(at..at + count * 4)?.chunks(4).map(LittleEndian::read_u32).collect())
+            }
+            None => None,
+        };
+        Some(CmifResponse { header, out_objects, payload: payload.to_vec() })
+    }
+
+    /// Fills in the message type and data words of `message`, as with
+    /// [`CmifRequest::write_to`].
+    pub fn write_to(&self, message: &mut HipcMessage) {
+        message.message_type = 0;
+        let mut cmif = Vec::new();
+        if let Some(objects) = &self.out_objects {
+            let mut domain_header = [0u8; 0x10];
+            LittleEndian::write_u32(&mut domain_header, objects.len() as u32);
+            cmif.extend(domain_header);
+        }
+        cmif.extend(self.header.to_bytes());
+        cmif.extend(&self.payload);
+        for object in self.out_objects.iter().flatten() {
+            cmif.extend(object.to_le_bytes());
+        }
+        message.data = cmif_data_words(message, &cmif);
+    }
+}
+
+/// The protocol a service speaks on top of HIPC. TIPC drops the CMIF
+/// headers and domains and puts the command ID in the message type.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum IpcProtocol {
+    Cmif,
+    Tipc,
+}
+
+impl IpcProtocol {
+    /// CMIF uses message types 0 to 7, TIPC 15 (close) and up.
+    pub fn of_message(message: &HipcMessage) -> IpcProtocol {
+        if message.message_type >= TipcRequest::CLOSE_MESSAGE_TYPE {
+            IpcProtocol::Tipc
+        } else {
+            IpcProtocol::Cmif
+        }
+    }
+}
+
+/// Which protocol each service's clients are expected to use. Services not
+/// listed speak CMIF.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ServiceProtocols {
+    protocols: HashMap<String, IpcProtocol>,
+}
+
+impl ServiceProtocols {
+    pub fn new() -> ServiceProtocols {
+        ServiceProtocols::default()
+    }
+
+    /// The protocols of a given system version: `sm:` moved to TIPC in 12.0.0.
+    pub fn for_firmware(major: u32) -> ServiceProtocols {
+        let mut protocols = ServiceProtocols::new();
+        if major >= 12 {
+            protocols.set("sm:", IpcProtocol::Tipc);
+        }
+        protocols
+    }
+
+    pub fn set(&mut self, service: &str, protocol: IpcProtocol) {
+        self.protocols.insert(service.to_string(), protocol);
+    }
+
+    pub fn protocol(&self, service: &str) -> IpcProtocol {
+        self.protocols.get(service).copied().unwrap_or(IpcProtocol::Cmif)
+    }
+}
+
+/// A TIPC request: the arguments follow the HIPC header directly.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TipcRequest {
+    /// None for a close request.
+    pub command_id: Option<u32>,
+    pub payload: Vec<u8>,
+}
+
+impl TipcRequest {
+    pub const CLOSE_MESSAGE_TYPE: u16 = 15;
+    const COMMAND_MESSAGE_TYPE_BASE: u16 = 16;
+
+    pub fn parse(message: &HipcMessage) -> Option<TipcRequest> {
+        let command_id = match message.message_type {
+            TipcRequest::CLOSE_MESSAGE_TYPE => None,
+            ty if ty >= TipcRequest::COMMAND_MESSAGE_TYPE_BASE => {
+                Some((ty - TipcRequest::COMMAND_MESSAGE_TYPE_BASE) as u32)
+            }
+            _ => return None,
+        };
+        Some(TipcRequest { command_id, payload: message.data.clone() })
+    }
+
+    pub fn write_to(&self, message: &mut HipcMessage) {
+        message.message_type = match self.command_id {
+            Some(id) => id as u16 + TipcRequest::COMMAND_MESSAGE_TYPE_BASE,
+            None => TipcRequest::CLOSE_MESSAGE_TYPE,
+        };
+        message.data = self.payload.clone();
+    }
+}
+
+/// A TIPC response: the result word, then the return values.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TipcResponse {
+    pub result: NxResult,
+    pub payload: Vec<u8>,
+}
+
+impl TipcResponse {
+    pub fn parse(message: &HipcMessage) -> Option<TipcResponse> {
+        let result = NxResult(LittleEndian::read_u32(message.data.get(..4)?));
+        Some(TipcResponse { result, payload: message.data[4..].to_vec() })
+    }
+
+    pub fn write_to(&self, message: &mut HipcMessage) {
+        message.message_type = 0;
+        message.data = self.result.0.to_le_bytes().to_vec();
+        message.data.extend(&self.payload);
+    }
+}
+
+/// A request in whichever protocol the client used, so a service can
+/// answer both.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum IpcRequest {
+    Cmif(CmifRequest),
+    Tipc(TipcRequest),
+}
+
+impl IpcRequest {
+    /// `is_domain` only applies to CMIF requests.
+    pub fn parse(message: &HipcMessage, is_domain: bool) -> Option<IpcRequest> {
+        match IpcProtocol::of_message(message) {
+            IpcProtocol::Cmif => CmifRequest::parse(message, is_domain).map(IpcRequest::Cmif),
+            IpcProtocol::Tipc => TipcRequest::parse(message).map(IpcRequest::Tipc),
+        }
+    }
+
+    pub fn protocol(&self) -> IpcProtocol {
+        match self {
+            IpcRequest::Cmif(_) => IpcProtocol::Cmif,
+            IpcRequest::Tipc(_) => IpcProtocol::Tipc,
+        }
+    }
+
+    /// None for close requests.
+    pub fn command_id(&self) -> Option<u32> {
+        match self {
+            IpcRequest::Cmif(request) => request.command_id(),
+            IpcRequest::Tipc(request) => request.command_id,
+        }
+    }
+
+    pub fn payload(&self) -> &[u8] {
+        match self {
+            IpcRequest::Cmif(request) => &request.payload,
+            IpcRequest::Tipc(request) => &request.payload,
+        }
+    }
+
+    /// Writes a response in the same protocol as this request. Handles in
+    /// `message` have to be set first.
+    pub fn respond(&self, result: NxResult, payload: Vec<u8>, message: &mut HipcMessage) {
+        match self {
+            IpcRequest::Cmif(request) => {
+                let mut response = CmifResponse::new(result, payload);
+                if request.domain.is_some() {
+                    response.out_objects = Some(Vec::new());
+                }
+                response.write_to(message);
+            }
+            IpcRequest::Tipc(_) => TipcResponse { result, payload }.write_to(message),
+        }
+    }
+}
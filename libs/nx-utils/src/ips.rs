@@ -0,0 +1,193 @@
+use std::borrow::Cow;
+
+use crate::executable::{Executable, ExecutableFormat};
+use crate::nso::NsoHeader;
+use crate::{NroHeader, SegmentBytes, SwitchExecutable};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IpsFormat {
+    /// "PATCH" ... "EOF", with 24-bit offsets.
+    Ips,
+    /// "IPS32" ... "EEOF", with 32-bit offsets.
+    Ips32,
+}
+
+impl IpsFormat {
+    fn offset_size(self) -> usize {
+        match self {
+            IpsFormat::Ips => 3,
+            IpsFormat::Ips32 => 4,
+        }
+    }
+
+    fn footer(self) -> &'static [u8] {
+        match self {
+            IpsFormat::Ips => b"EOF",
+            IpsFormat::Ips32 => b"EEOF",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum IpsData {
+    Bytes(Vec<u8>),
+    /// `len` copies of `value`.
+    Rle { len: u16, value: u8 },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IpsRecord {
+    pub offset: u32,
+    pub data: IpsData,
+}
+
+impl IpsRecord {
+    pub fn len(&self) -> usize {
+        match &self.data {
+            IpsData::Bytes(bytes) => bytes.len(),
+            IpsData::Rle { len, .. } => *len as usize,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// An IPS or IPS32 patch as distributed for ExeFS modules. Offsets are into
+/// the module file as it would look decompressed: for an NSO that means the
+/// 0x100-byte header followed by the memory image, for an NRO the memory
+/// image itself, header included.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IpsPatch {
+    pub format: IpsFormat,
+    /// The build ID the patch is for, taken from its file name. Names may
+    /// give fewer than 0x20 bytes; only those are compared.
+    pub build_id: Option<Vec<u8>>,
+    pub records: Vec<IpsRecord>,
+}
+
+impl IpsPatch {
+    /// Parses a patch. Records are big-endian: an offset, a u16 size and the
+    /// bytes, or a zero size followed by a u16 run length and the byte to
+    /// repeat. Returns None if the patch is malformed.
+    pub fn parse(bytes: &[u8]) -> Option<IpsPatch> {
+        let (format, mut rest) = if let Some(rest) = bytes.strip_prefix(b"IPS32") {
+            (IpsFormat::Ips32, rest)
+        } else {
+            (IpsFormat::Ips, bytes.strip_prefix(b"PATCH")?)
+        };
+
+        let mut records = Vec::new();
+        while !rest.starts_with(format.footer()) {
+            let offset = rest.get(..format.offset_size())?.iter().fold(0u32, |acc, b| acc << 8 | *b as u32);
+            rest = &rest[format.offset_size()..];
+            let size = u16::from_be_bytes([*rest.first()?, *rest.get(1)?]) as usize;
+            rest = &rest[2..];
+            let data = if size == 0 {
+                let len = u16::from_be_bytes([*rest.first()?, *rest.get(1)?]);
+                let value = *rest.get(2)?;
+                rest = &rest[3..];
+                IpsData::Rle { len, value }
+            } else {
+                let bytes = rest.get(..size)?.to_vec();
+                rest = &rest[size..];
+                IpsData::Bytes(bytes)
+            };
+            records.push(IpsRecord { offset, data });
+        }
+
+        Some(IpsPatch { format, build_id: None, records })
+    }
+
+    /// Parses a patch file named after the build ID it applies to, such as
+    /// `8747C99A3C92C6B5….ips`.
+    pub fn read(file_name: &str, bytes: &[u8]) -> Option<IpsPatch> {
+        let stem = file_name.rsplit_once('.').map_or(file_name, |(stem, _)| stem);
+        let mut patch = IpsPatch::parse(bytes)?;
+        patch.build_id = Some(parse_build_id(stem)?);
+        Some(patch)
+    }
+
+    /// True if the patch is not tied to a build ID or its ID is a prefix of
+    /// the module's.
+    pub fn matches(&self, build_id: Option<&[u8; 0x20]>) -> bool {
+        match (&self.build_id, build_id) {
+            (None, _) => true,
+            (Some(wanted), Some(actual)) => actual.starts_with(wanted),
+            (Some(_), None) => false,
+        }
+    }
+
+    /// Writes the records into `image`, where patch offset `shift` is image
+    /// offset zero. Offsets below `protected` are never written, and records
+    /// are cut off at the end of the image, as Atmosphère does.
+    pub fn apply_to(&self, image: &mut [u8], shift: usize, protected: usize) {
+        for record in &self.records {
+            let start = record.offset as usize;
+            for i in 0..record.len() {
+                let offset = start + i;
+                if offset < protected || offset < shift || offset - shift >= image.len() {
+                    continue;
+                }
+                image[offset - shift] = match &record.data {
+                    IpsData::Bytes(bytes) => bytes[i],
+                    IpsData::Rle { value, .. } => *value,
+                };
+            }
+        }
+    }
+}
+
+/// Decodes the hex digits of a patch file name, at most 0x20 bytes.
+fn parse_build_id(hex: &str) -> Option<Vec<u8>> {
+    if hex.is_empty() || !hex.len().is_multiple_of(2) || hex.len() > 0x40 {
+        return None;
+    }
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok()).collect()
+}
+
+impl<'a> SwitchExecutable<'a> {
+    /// Applies an IPS patch and returns the patched executable, or None if
+    /// the patch is for a different build. The patched segments get buffers
+    /// of their own; everything else still borrows from this executable's file.
+    pub fn apply_ips(&self, patch: &IpsPatch) -> Option<SwitchExecutable<'_>> {
+        if !patch.matches(self.build_id().as_ref()) {
+            return None;
+        }
+        let (shift, protected) = match self.format() {
+            ExecutableFormat::Nso => (NsoHeader::SIZE, NsoHeader::SIZE),
+            ExecutableFormat::Nro => (0, NroHeader::SIZE),
+            ExecutableFormat::Kip | ExecutableFormat::Elf => (0, 0),
+        };
+
+        let mut image = self.memory_image();
+        patch.apply_to(&mut image, shift, protected);
+
+        let [text, ro, data, _] = self.segment_offsets();
+        let segment = |original: &SegmentBytes, offset: usize| {
+            let original_bytes = original.get(&self.program);
+            let bytes = &image[offset..][..original_bytes.len()];
+            if bytes == original_bytes {
+                original.clone()
+            } else {
+                SegmentBytes::Owned(bytes.to_vec())
+            }
+        };
+        Some(SwitchExecutable {
+            program: Cow::Borrowed(&self.program),
+            text: segment(&self.text, text),
+            ro: segment(&self.ro, ro),
+            data: segment(&self.data, data),
+            bss: self.bss.clone(),
+            icon: self.icon.clone(),
+            nacp: self.nacp.clone(),
+            romfs: self.romfs.clone(),
+            asset_version: self.asset_version,
+            nro_header: self.nro_header.clone(),
+            nso_header: self.nso_header.clone(),
+            kip_header: self.kip_header.clone(),
+            elf: self.elf.clone(),
+        })
+    }
+}
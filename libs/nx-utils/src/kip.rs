@@ -0,0 +1,161 @@
+use std::borrow::Cow;
+use std::io::{Cursor, Read};
+
+use byteorder::{LittleEndian, ReadBytesExt};
+
+use crate::NroSegmentType::{DATA, RO, TEXT};
+use crate::{blz, NroSegmentType, SegmentBytes, SwitchExecutable};
+
+/// One of the six KIP1 segment headers: text, ro, data, bss and two unused.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct KipSegmentHeader {
+    pub memory_offset: u32,
+    pub size: u32,
+    /// Size in the file, i.e. after compression. Zero for .bss.
+    pub file_size: u32,
+    /// Holds the affinity mask for text and the main thread stack size for ro.
+    pub attribute: u32,
+}
+
+impl KipSegmentHeader {
+    fn new(reader: &mut Cursor<&[u8]>) -> KipSegmentHeader {
+        KipSegmentHeader {
+            memory_offset: reader.read_u32::<LittleEndian>().unwrap(),
+            size: reader.read_u32::<LittleEndian>().unwrap(),
+            file_size: reader.read_u32::<LittleEndian>().unwrap(),
+            attribute: reader.read_u32::<LittleEndian>().unwrap(),
+        }
+    }
+}
+
+/// The 0x100-byte KIP1 header of a kernel initial process. The segment data
+/// follows it back to back, text first.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KipHeader {
+    pub name: String,
+    pub title_id: u64,
+    /// Also documented as the process version.
+    pub process_category: u32,
+    pub main_thread_priority: u8,
+    pub default_core: u8,
+    pub flags: u8,
+    /// Indexed by [`NroSegmentType`], then .bss and two unused entries.
+    pub segments: [KipSegmentHeader; 6],
+    /// Kernel capability descriptors; unused slots are 0xFFFFFFFF.
+    pub capabilities: [u32; 0x20],
+}
+
+impl KipHeader {
+    pub const MAGIC: &'static [u8; 4] = b"KIP1";
+    pub const SIZE: usize = 0x100;
+    pub const BSS: usize = 3;
+
+    pub const TEXT_COMPRESS: u8 = 1 << 0;
+    pub const RO_COMPRESS: u8 = 1 << 1;
+    pub const DATA_COMPRESS: u8 = 1 << 2;
+    pub const IS_64BIT: u8 = 1 << 3;
+    pub const ADDRESS_SPACE_64BIT: u8 = 1 << 4;
+    pub const USE_SECURE_MEMORY: u8 = 1 << 5;
+
+    pub fn new(reader: &mut Cursor<&[u8]>) -> KipHeader {
+        let mut buf: [u8; 4] = [0, 0, 0, 0];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, KipHeader::MAGIC);
+        let mut name = [0u8; 0xC];
+        reader.read_exact(&mut name).unwrap();
+        let name_len = name.iter().position(|b| *b == 0).unwrap_or(name.len());
+        let title_id = reader.read_u64::<LittleEndian>().unwrap();
+        let process_category = reader.read_u32::<LittleEndian>().unwrap();
+        let main_thread_priority = reader.read_u8().unwrap();
+        let default_core = reader.read_u8().unwrap();
+        reader.read_u8().unwrap();
+        let flags = reader.read_u8().unwrap();
+        let segments = std::array::from_fn(|_| KipSegmentHeader::new(reader));
+        let mut capabilities = [0u32; 0x20];
+        reader.read_u32_into::<LittleEndian>(&mut capabilities).unwrap();
+
+        KipHeader {
+            name: String::from_utf8_lossy(&name[..name_len]).into_owned(),
+            title_id,
+            process_category,
+            main_thread_priority,
+            default_core,
+            flags,
+            segments,
+            capabilities,
+        }
+    }
+
+    pub fn is_compressed(&self, segment: NroSegmentType) -> bool {
+        self.flags & (KipHeader::TEXT_COMPRESS << segment as u8) != 0
+    }
+
+    pub fn stack_size(&self) -> u32 {
+        self.segments[RO as usize].attribute
+    }
+
+    pub fn bss_size(&self) -> u32 {
+        self.segments[KipHeader::BSS].size
+    }
+
+    /// The capability descriptors that are in use.
+    pub fn kernel_capabilities(&self) -> impl Iterator<Item = u32> + '_ {
+        self.capabilities.iter().copied().filter(|c| *c != 0xFFFFFFFF)
+    }
+
+    /// Decompresses a segment if needed and pads it to its memory size.
+    /// Segments that are stored as-is are borrowed from `file_bytes`.
+    /// Returns None if the BLZ data is corrupt.
+    pub fn decompress_segment<'b>(&self, file_bytes: &'b [u8], segment: NroSegmentType) -> Option<Cow<'b, [u8]>> {
+        let index = segment as usize;
+        let size = self.segments[index].size as usize;
+        let file_offset = KipHeader::SIZE + self.segments[..index].iter().map(|s| s.file_size as usize).sum::<usize>();
+        let stored = file_bytes.get(file_offset..)?.get(..self.segments[index].file_size as usize)?;
+        let mut bytes = if self.is_compressed(segment) && !stored.is_empty() {
+            blz::decompress(stored)?
+        } else if stored.len() >= size {
+            return Some(Cow::Borrowed(&stored[..size]));
+        } else {
+            stored.to_vec()
+        };
+        bytes.resize(size, 0);
+        Some(Cow::Owned(bytes))
+    }
+}
+
+impl<'a> SwitchExecutable<'a> {
+    pub fn read_kip(file_bytes: impl Into<Cow<'a, [u8]>>) -> SwitchExecutable<'a> {
+        let file_bytes = file_bytes.into();
+        let header = KipHeader::new(&mut Cursor::new(&file_bytes[..]));
+
+        let segment = |index, name| {
+            let bytes = header.decompress_segment(&file_bytes, index).unwrap_or_else(|| panic!("corrupt KIP {} segment", name));
+            SegmentBytes::new(&file_bytes, bytes)
+        };
+        let text = segment(TEXT, ".text");
+        let ro = segment(RO, ".rodata");
+        let data = segment(DATA, ".data");
+        let bss = vec![0; header.bss_size() as usize];
+
+        SwitchExecutable {
+            program: file_bytes,
+            text,
+            ro,
+            data,
+            bss,
+            icon: None,
+            nacp: None,
+            romfs: None,
+            asset_version: 0,
+            nro_header: None,
+            nso_header: None,
+            kip_header: Some(header),
+            elf: None,
+        }
+    }
+
+    /// The KIP1 header, if this executable was read from a KIP.
+    pub fn kip_header(&self) -> Option<&KipHeader> {
+        self.kip_header.as_ref()
+    }
+}
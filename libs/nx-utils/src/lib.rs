@@ -1,74 +1,413 @@
+use std::borrow::Cow;
 use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::ops::Range;
 use std::str::from_utf8;
 
-use byteorder::{LittleEndian, ReadBytesExt};
+use byteorder::{ByteOrder, LittleEndian, ReadBytesExt};
 
+use crate::elf::ElfFile;
+use crate::kip::KipHeader;
+use crate::nso::NsoHeader;
 use crate::NroSegmentType::{DATA, RO, TEXT};
 
+pub mod blz;
+pub mod elf;
+pub mod executable;
+pub mod ipc;
+pub mod ips;
+pub mod kip;
+pub mod loader;
+pub mod lz4;
+pub mod nacp;
+pub mod npdm;
+pub mod nso;
+pub mod pfs0;
+pub mod result;
+pub mod romfs;
+pub mod sha256;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum NroSegmentType {
     TEXT = 0,
     RO = 1,
     DATA = 2,
 }
 
+/// An (offset, size) pair. In the NRO header, segment offsets are relative
+/// to the start of the file and the .rodata extents to the start of .rodata.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub struct NroSegment {
-    offset: usize,
-    size: usize,
+    pub offset: usize,
+    pub size: usize,
 }
 
 impl NroSegment {
-    pub fn new(reader: &mut Cursor<Vec<u8>>) -> NroSegment {
+    pub fn new(reader: &mut Cursor<&[u8]>) -> NroSegment {
         NroSegment {
             offset: reader.read_u32::<LittleEndian>().unwrap() as usize,
             size: reader.read_u32::<LittleEndian>().unwrap() as usize,
         }
     }
+
+    /// Asset entries store their offset and size as u64 rather than u32.
+    fn new_u64(reader: &mut Cursor<&[u8]>) -> NroSegment {
+        NroSegment {
+            offset: reader.read_u64::<LittleEndian>().unwrap() as usize,
+            size: reader.read_u64::<LittleEndian>().unwrap() as usize,
+        }
+    }
+
+    /// The file range this segment covers when its offset is relative to
+    /// `base`, or None if it is empty or does not fit in `file_len` bytes.
+    fn range(&self, base: usize, file_len: usize) -> Option<Range<usize>> {
+        let start = base.checked_add(self.offset)?;
+        let end = start.checked_add(self.size)?;
+        (self.size != 0 && end <= file_len).then_some(start..end)
+    }
 }
 
-pub struct SwitchExecutable {
-    program: Vec<u8>,
-    text: Vec<u8>,
-    ro: Vec<u8>,
-    data: Vec<u8>,
-    bss: Vec<u8>,
+/// The 0x80-byte header that sits at the start of an NRO's .text.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct NroHeader {
+    /// The first word of .text, normally a branch over the header.
+    pub entry_instruction: u32,
+    pub mod0_offset: u32,
+    /// Eight bytes of padding, which homebrew fills with "HOMEBREW".
+    pub padding: [u8; 8],
+    pub version: u32,
+    /// Size of the whole image: the header and all three segments.
+    pub size: u32,
+    pub flags: u32,
+    /// .text, .rodata and .data, indexed by [`NroSegmentType`].
+    pub segments: [NroSegment; 3],
+    pub bss_size: u32,
+    pub build_id: [u8; 0x20],
+    pub dso_handle_offset: u32,
+    pub api_info: NroSegment,
+    pub dynstr: NroSegment,
+    pub dynsym: NroSegment,
 }
 
-impl SwitchExecutable {
-    pub fn read_nro(file_bytes: Vec<u8>) -> SwitchExecutable {
-        let mut reader = Cursor::new(file_bytes.clone());
-        reader.seek(SeekFrom::Current(4)).unwrap();
-        reader.seek(SeekFrom::Current(4)).unwrap(); // mod0: u32
-        reader.seek(SeekFrom::Current(8)).unwrap();
+impl NroHeader {
+    pub const MAGIC: &'static [u8; 4] = b"NRO0";
+    pub const SIZE: usize = 0x80;
+
+    pub fn new(reader: &mut Cursor<&[u8]>) -> NroHeader {
+        let entry_instruction = reader.read_u32::<LittleEndian>().unwrap();
+        let mod0_offset = reader.read_u32::<LittleEndian>().unwrap();
+        let mut padding = [0u8; 8];
+        reader.read_exact(&mut padding).unwrap();
         let mut buf: [u8; 4] = [0, 0, 0, 0];
         reader.read_exact(&mut buf).unwrap();
         let magic = from_utf8(&buf).unwrap();
         assert_eq!(magic, "NRO0");
-        let _version = reader.read_u32::<LittleEndian>().unwrap();
-        let _header_size = reader.read_u32::<LittleEndian>().unwrap();
-        reader.seek(SeekFrom::Current(4)).unwrap();
-        let sectors = [
-            NroSegment::new(&mut reader),
-            NroSegment::new(&mut reader),
-            NroSegment::new(&mut reader)
+        let version = reader.read_u32::<LittleEndian>().unwrap();
+        let size = reader.read_u32::<LittleEndian>().unwrap();
+        let flags = reader.read_u32::<LittleEndian>().unwrap();
+        let segments = [
+            NroSegment::new(reader),
+            NroSegment::new(reader),
+            NroSegment::new(reader)
         ];
         let bss_size = reader.read_u32::<LittleEndian>().unwrap();
         reader.seek(SeekFrom::Current(4)).unwrap();
-        reader.seek(SeekFrom::Current(0x20)).unwrap(); // Build ID
-        reader.seek(SeekFrom::Current(0x20)).unwrap();
+        let mut build_id = [0u8; 0x20];
+        reader.read_exact(&mut build_id).unwrap();
+        let dso_handle_offset = reader.read_u32::<LittleEndian>().unwrap();
+        reader.seek(SeekFrom::Current(4)).unwrap();
+
+        NroHeader {
+            entry_instruction,
+            mod0_offset,
+            padding,
+            version,
+            size,
+            flags,
+            segments,
+            bss_size,
+            build_id,
+            dso_handle_offset,
+            api_info: NroSegment::new(reader),
+            dynstr: NroSegment::new(reader),
+            dynsym: NroSegment::new(reader),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = vec![0u8; NroHeader::SIZE];
+        LittleEndian::write_u32(&mut out[0x00..], self.entry_instruction);
+        LittleEndian::write_u32(&mut out[0x04..], self.mod0_offset);
+        out[0x08..0x10].copy_from_slice(&self.padding);
+        out[0x10..0x14].copy_from_slice(NroHeader::MAGIC);
+        LittleEndian::write_u32(&mut out[0x14..], self.version);
+        LittleEndian::write_u32(&mut out[0x18..], self.size);
+        LittleEndian::write_u32(&mut out[0x1C..], self.flags);
+        let extents = self.segments.iter().chain([&self.api_info, &self.dynstr, &self.dynsym]);
+        let positions = [0x20, 0x28, 0x30, 0x68, 0x70, 0x78];
+        for (segment, at) in extents.zip(positions) {
+            LittleEndian::write_u32(&mut out[at..], segment.offset as u32);
+            LittleEndian::write_u32(&mut out[at + 4..], segment.size as u32);
+        }
+        LittleEndian::write_u32(&mut out[0x38..], self.bss_size);
+        out[0x40..0x60].copy_from_slice(&self.build_id);
+        LittleEndian::write_u32(&mut out[0x60..], self.dso_handle_offset);
+        out
+    }
+}
+
+/// The optional asset section appended to homebrew NROs right after the
+/// executable image. Each entry is an (offset, size) pair relative to the
+/// start of the ASET header.
+pub struct NroAssetHeader {
+    pub version: u32,
+    pub icon: NroSegment,
+    pub nacp: NroSegment,
+    pub romfs: NroSegment,
+}
 
-        // TODO: asset processing?
+impl NroAssetHeader {
+    pub const MAGIC: &'static [u8; 4] = b"ASET";
+    pub const SIZE: usize = 0x38;
 
-        let text = file_bytes[sectors[TEXT as usize].offset..][..sectors[TEXT as usize].size].to_vec();
-        let ro = file_bytes[sectors[RO as usize].offset..][..sectors[RO as usize].size].to_vec();
-        let data = file_bytes[sectors[DATA as usize].offset..][..sectors[DATA as usize].size].to_vec();
-        let bss = file_bytes[sectors[DATA as usize].offset + sectors[DATA as usize].size..][..bss_size as usize].to_vec();
+    pub fn new(reader: &mut Cursor<&[u8]>) -> NroAssetHeader {
+        let mut buf: [u8; 4] = [0, 0, 0, 0];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, NroAssetHeader::MAGIC);
+        let version = reader.read_u32::<LittleEndian>().unwrap();
+        NroAssetHeader {
+            version,
+            icon: NroSegment::new_u64(reader),
+            nacp: NroSegment::new_u64(reader),
+            romfs: NroSegment::new_u64(reader),
+        }
+    }
+}
 
-        return SwitchExecutable {
-            program: file_bytes.clone(),
+/// Where the bytes of a segment or asset live: a range of the file the
+/// executable was read from, or a buffer of their own when they had to be
+/// decompressed or were replaced.
+#[derive(Clone, Debug)]
+enum SegmentBytes {
+    File(Range<usize>),
+    Owned(Vec<u8>),
+}
+
+impl SegmentBytes {
+    /// Keeps bytes borrowed from `program` as a range and takes ownership of
+    /// anything else. Borrowed bytes must lie within `program`.
+    fn new(program: &[u8], bytes: Cow<[u8]>) -> SegmentBytes {
+        match bytes {
+            Cow::Borrowed([]) => SegmentBytes::File(0..0),
+            Cow::Borrowed(slice) => {
+                let start = (slice.as_ptr() as usize).checked_sub(program.as_ptr() as usize)
+                    .filter(|start| start + slice.len() <= program.len())
+                    .expect("segment does not borrow from the file");
+                SegmentBytes::File(start..start + slice.len())
+            }
+            Cow::Owned(bytes) => SegmentBytes::Owned(bytes),
+        }
+    }
+
+    fn get<'s>(&'s self, program: &'s [u8]) -> &'s [u8] {
+        match self {
+            SegmentBytes::File(range) => &program[range.clone()],
+            SegmentBytes::Owned(bytes) => bytes,
+        }
+    }
+}
+
+/// An executable read from any of the supported formats. It borrows the
+/// file it was read from, which may equally be an owned `Vec<u8>`, a slice
+/// or a memory-mapped file; segments that are stored uncompressed are views
+/// into it and only compressed ones get buffers of their own.
+#[derive(Clone)]
+pub struct SwitchExecutable<'a> {
+    program: Cow<'a, [u8]>,
+    text: SegmentBytes,
+    ro: SegmentBytes,
+    data: SegmentBytes,
+    bss: Vec<u8>,
+    icon: Option<SegmentBytes>,
+    nacp: Option<SegmentBytes>,
+    romfs: Option<SegmentBytes>,
+    /// Version of the ASET header, written back by write_nro.
+    asset_version: u32,
+    nro_header: Option<NroHeader>,
+    nso_header: Option<NsoHeader>,
+    kip_header: Option<KipHeader>,
+    elf: Option<ElfFile>,
+}
+
+impl<'a> SwitchExecutable<'a> {
+    pub fn read_nro(file_bytes: impl Into<Cow<'a, [u8]>>) -> SwitchExecutable<'a> {
+        let file_bytes = file_bytes.into();
+        let mut reader = Cursor::new(&file_bytes[..]);
+        let header = NroHeader::new(&mut reader);
+        let image_size = header.size as usize;
+
+        // Homebrew NROs may carry an ASET section directly after the image.
+        let (mut icon, mut nacp, mut romfs, mut asset_version) = (None, None, None, 0);
+        if file_bytes.len() >= image_size + NroAssetHeader::SIZE && &file_bytes[image_size..][..4] == NroAssetHeader::MAGIC {
+            reader.seek(SeekFrom::Start(image_size as u64)).unwrap();
+            let assets = NroAssetHeader::new(&mut reader);
+            asset_version = assets.version;
+            // Assets cut off by a truncated file are dropped.
+            icon = assets.icon.range(image_size, file_bytes.len()).map(SegmentBytes::File);
+            nacp = assets.nacp.range(image_size, file_bytes.len()).map(SegmentBytes::File);
+            romfs = assets.romfs.range(image_size, file_bytes.len()).map(SegmentBytes::File);
+        }
+
+        let segment = |index: NroSegmentType| {
+            let NroSegment { offset, size } = header.segments[index as usize];
+            assert!(offset + size <= file_bytes.len(), "NRO segment out of bounds");
+            SegmentBytes::File(offset..offset + size)
+        };
+        let (text, ro, data) = (segment(TEXT), segment(RO), segment(DATA));
+        // .bss is not stored in the file; whatever follows .data is the asset section.
+        let bss = vec![0; header.bss_size as usize];
+
+        SwitchExecutable {
+            program: file_bytes,
             text,
             ro,
             data,
             bss,
-        };
+            icon,
+            nacp,
+            romfs,
+            asset_version,
+            nro_header: Some(header),
+            nso_header: None,
+            kip_header: None,
+            elf: None,
+        }
+    }
+
+    /// Serializes text, ro and data back into an NRO, followed by an ASET
+    /// section if any asset is present.
+    ///
+    /// The NRO header occupies the first 0x80 bytes of .text and is written
+    /// back from [`NroHeader`], with the image size, segment table and .bss
+    /// size recomputed and each segment starting on a page boundary. Other
+    /// formats keep live code there, so they return None.
+    pub fn write_nro(&self) -> Option<Vec<u8>> {
+        let mut header = self.nro_header.clone()?;
+        if self.text().len() < NroHeader::SIZE {
+            return None;
+        }
+        let align = |offset: usize| (offset + 0xFFF) & !0xFFF;
+        let ro_offset = align(self.text().len());
+        let data_offset = align(ro_offset + self.ro().len());
+        let image_size = data_offset + self.data().len();
+
+        let mut out = Vec::with_capacity(image_size);
+        out.extend_from_slice(self.text());
+        out.resize(ro_offset, 0);
+        out.extend_from_slice(self.ro());
+        out.resize(data_offset, 0);
+        out.extend_from_slice(self.data());
+
+        header.size = image_size as u32;
+        header.segments = [
+            NroSegment { offset: 0, size: self.text().len() },
+            NroSegment { offset: ro_offset, size: self.ro().len() },
+            NroSegment { offset: data_offset, size: self.data().len() },
+        ];
+        header.bss_size = self.bss.len() as u32;
+        out[..NroHeader::SIZE].copy_from_slice(&header.to_bytes());
+
+        if self.icon.is_none() && self.nacp.is_none() && self.romfs.is_none() {
+            return Some(out);
+        }
+
+        // Assets follow the ASET header back to back: icon, NACP, RomFS.
+        let assets = [self.icon(), self.nacp(), self.romfs()];
+        out.extend_from_slice(NroAssetHeader::MAGIC);
+        out.extend_from_slice(&self.asset_version.to_le_bytes());
+        let mut asset_offset = NroAssetHeader::SIZE;
+        for asset in assets {
+            let size = asset.map_or(0, |a| a.len());
+            let offset = if size == 0 { 0 } else { asset_offset };
+            out.extend_from_slice(&(offset as u64).to_le_bytes());
+            out.extend_from_slice(&(size as u64).to_le_bytes());
+            asset_offset += size;
+        }
+        for asset in assets.into_iter().flatten() {
+            out.extend_from_slice(asset);
+        }
+        Some(out)
+    }
+
+    /// The NRO header, if this executable was read from an NRO.
+    pub fn nro_header(&self) -> Option<&NroHeader> {
+        self.nro_header.as_ref()
+    }
+
+    /// The raw bytes the executable was read from.
+    pub fn program(&self) -> &[u8] {
+        &self.program
+    }
+
+    pub fn text(&self) -> &[u8] {
+        self.text.get(&self.program)
+    }
+
+    pub fn ro(&self) -> &[u8] {
+        self.ro.get(&self.program)
+    }
+
+    pub fn data(&self) -> &[u8] {
+        self.data.get(&self.program)
+    }
+
+    pub fn bss(&self) -> &[u8] {
+        &self.bss
+    }
+
+    /// The JPEG icon from the NRO asset section, if present.
+    pub fn icon(&self) -> Option<&[u8]> {
+        self.icon.as_ref().map(|b| b.get(&self.program))
+    }
+
+    /// The raw 0x4000-byte NACP control data from the NRO asset section, if present.
+    pub fn nacp(&self) -> Option<&[u8]> {
+        self.nacp.as_ref().map(|b| b.get(&self.program))
+    }
+
+    /// The RomFS image from the NRO asset section, if present.
+    pub fn romfs(&self) -> Option<&[u8]> {
+        self.romfs.as_ref().map(|b| b.get(&self.program))
+    }
+
+    pub fn set_icon(&mut self, icon: Option<Vec<u8>>) {
+        self.icon = icon.map(SegmentBytes::Owned);
+    }
+
+    pub fn set_nacp(&mut self, nacp: Option<Vec<u8>>) {
+        self.nacp = nacp.map(SegmentBytes::Owned);
+    }
+
+    pub fn set_romfs(&mut self, romfs: Option<Vec<u8>>) {
+        self.romfs = romfs.map(SegmentBytes::Owned);
+    }
+
+    /// Detaches the executable from the buffer it was read from by copying
+    /// the file into memory of its own.
+    pub fn into_owned(self) -> SwitchExecutable<'static> {
+        SwitchExecutable {
+            program: Cow::Owned(self.program.into_owned()),
+            text: self.text,
+            ro: self.ro,
+            data: self.data,
+            bss: self.bss,
+            icon: self.icon,
+            nacp: self.nacp,
+            romfs: self.romfs,
+            asset_version: self.asset_version,
+            nro_header: self.nro_header,
+            nso_header: self.nso_header,
+            kip_header: self.kip_header,
+            elf: self.elf,
+        }
     }
 }
@@ -0,0 +1,399 @@
+use byteorder::{ByteOrder, LittleEndian};
+
+use crate::SwitchExecutable;
+
+pub const PAGE_SIZE: usize = 0x1000;
+
+pub const DT_NULL: i64 = 0;
+pub const DT_NEEDED: i64 = 1;
+pub const DT_PLTRELSZ: i64 = 2;
+pub const DT_HASH: i64 = 4;
+pub const DT_STRTAB: i64 = 5;
+pub const DT_SYMTAB: i64 = 6;
+pub const DT_RELA: i64 = 7;
+pub const DT_RELASZ: i64 = 8;
+pub const DT_STRSZ: i64 = 10;
+pub const DT_SYMENT: i64 = 11;
+pub const DT_JMPREL: i64 = 23;
+
+pub const R_AARCH64_ABS64: u32 = 257;
+pub const R_AARCH64_GLOB_DAT: u32 = 1025;
+pub const R_AARCH64_JUMP_SLOT: u32 = 1026;
+pub const R_AARCH64_RELATIVE: u32 = 1027;
+
+pub(crate) const SYMBOL_SIZE: usize = 0x18;
+const RELA_SIZE: usize = 0x18;
+
+pub(crate) fn page_align(offset: usize) -> usize {
+    (offset + PAGE_SIZE - 1) & !(PAGE_SIZE - 1)
+}
+
+/// The MOD0 header every Switch module points to from offset 4 of .text.
+/// Offsets are converted from MOD0-relative to image-relative.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Mod0Header {
+    pub offset: usize,
+    pub dynamic: usize,
+    pub bss_start: usize,
+    pub bss_end: usize,
+    pub eh_frame_hdr_start: usize,
+    pub eh_frame_hdr_end: usize,
+    pub module_object: usize,
+}
+
+impl Mod0Header {
+    pub const MAGIC: &'static [u8; 4] = b"MOD0";
+}
+
+/// An entry of the dynamic symbol table.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    /// Image-relative address, or zero for imports.
+    pub value: u64,
+    pub size: u64,
+    pub info: u8,
+    pub other: u8,
+    pub shndx: u16,
+}
+
+impl Symbol {
+    pub const STB_LOCAL: u8 = 0;
+    pub const STB_GLOBAL: u8 = 1;
+    pub const STB_WEAK: u8 = 2;
+    pub const STT_FUNC: u8 = 2;
+    pub const STV_DEFAULT: u8 = 0;
+    pub const STV_PROTECTED: u8 = 3;
+
+    /// Decodes an Elf64_Sym whose name is an offset into `strtab`.
+    pub(crate) fn read(raw: &[u8], strtab: &[u8]) -> Symbol {
+        let name = strtab.get(LittleEndian::read_u32(raw) as usize..).unwrap_or_default();
+        let name_len = name.iter().position(|b| *b == 0).unwrap_or(name.len());
+        Symbol {
+            name: String::from_utf8_lossy(&name[..name_len]).into_owned(),
+            info: raw[4],
+            other: raw[5],
+            shndx: LittleEndian::read_u16(&raw[6..]),
+            value: LittleEndian::read_u64(&raw[8..]),
+            size: LittleEndian::read_u64(&raw[16..]),
+        }
+    }
+
+    pub fn binding(&self) -> u8 {
+        self.info >> 4
+    }
+
+    pub fn typ(&self) -> u8 {
+        self.info & 0xF
+    }
+
+    pub fn visibility(&self) -> u8 {
+        self.other & 0x3
+    }
+
+    /// Imports have no section; everything else is defined by this module.
+    pub fn is_defined(&self) -> bool {
+        self.shndx != 0
+    }
+
+    pub fn is_export(&self) -> bool {
+        self.is_defined() && self.binding() != Symbol::STB_LOCAL && !self.name.is_empty()
+    }
+}
+
+/// An Elf64_Rela entry from DT_RELA or DT_JMPREL.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Relocation {
+    /// Image-relative address that is patched.
+    pub offset: u64,
+    pub typ: u32,
+    /// Index into the dynamic symbol table; zero if unused.
+    pub symbol: u32,
+    pub addend: i64,
+}
+
+impl<'a> SwitchExecutable<'a> {
+    /// Image-relative start of text, ro, data and bss. NSOs, KIPs and ELFs
+    /// say where each segment goes; NROs are laid out back to back on page
+    /// boundaries, with .bss directly after .data.
+    pub fn segment_offsets(&self) -> [usize; 4] {
+        if let Some(header) = &self.nso_header {
+            let [text, ro, data] = &header.segments;
+            let bss = data.memory_offset + data.size;
+            return [text.memory_offset, ro.memory_offset, data.memory_offset, bss].map(|o| o as usize);
+        }
+        if let Some(header) = &self.kip_header {
+            return std::array::from_fn(|i| header.segments[i].memory_offset as usize);
+        }
+        if let Some(elf) = &self.elf {
+            return self.elf_segment_offsets(elf);
+        }
+        let ro = page_align(self.text().len());
+        let data = page_align(ro + self.ro().len());
+        [0, ro, data, data + self.data().len()]
+    }
+
+    /// Size of the module in memory, .bss included, rounded up to a page.
+    pub fn image_size(&self) -> usize {
+        page_align(self.segment_offsets()[3] + self.bss.len())
+    }
+
+    /// The module as mapped into memory at offset zero, before relocation.
+    pub fn memory_image(&self) -> Vec<u8> {
+        let mut image = vec![0u8; self.image_size()];
+        let [text, ro, data, _] = self.segment_offsets();
+        image[text..][..self.text().len()].copy_from_slice(self.text());
+        image[ro..][..self.ro().len()].copy_from_slice(self.ro());
+        image[data..][..self.data().len()].copy_from_slice(self.data());
+        image
+    }
+
+    /// The segments at their image-relative offsets. The accessors below read
+    /// through it rather than copying the whole module with memory_image().
+    fn segment_view(&self) -> SegmentView<'_> {
+        let [text, ro, data, _] = self.segment_offsets();
+        SegmentView { segments: [(text, self.text()), (ro, self.ro()), (data, self.data())] }
+    }
+
+    pub fn mod0(&self) -> Option<Mod0Header> {
+        parse_mod0(&self.segment_view())
+    }
+
+    /// The (tag, value) pairs of the dynamic section, up to DT_NULL.
+    pub fn dynamic_entries(&self) -> Vec<(i64, u64)> {
+        let view = self.segment_view();
+        parse_mod0(&view).map_or_else(Vec::new, |mod0| parse_dynamic(&view, mod0.dynamic))
+    }
+
+    pub fn dynamic_symbols(&self) -> Vec<Symbol> {
+        parse_symbols(&self.segment_view(), &self.dynamic_entries())
+    }
+
+    /// All RELA relocations, DT_RELA first and then the PLT ones in DT_JMPREL.
+    pub fn relocations(&self) -> Vec<Relocation> {
+        parse_relocations(&self.segment_view(), &self.dynamic_entries())
+    }
+}
+
+/// Text, ro and data paired with their image-relative offsets.
+struct SegmentView<'s> {
+    segments: [(usize, &'s [u8]); 3],
+}
+
+impl<'s> SegmentView<'s> {
+    /// The bytes from image offset `offset` to the end of the segment that
+    /// holds it; empty if no segment does.
+    fn at(&self, offset: usize) -> &'s [u8] {
+        self.segments.iter()
+            .find(|(start, bytes)| (*start..*start + bytes.len()).contains(&offset))
+            .map_or(&[], |(start, bytes)| &bytes[offset - start..])
+    }
+}
+
+fn parse_mod0(image: &SegmentView) -> Option<Mod0Header> {
+    let offset = LittleEndian::read_u32(image.at(4).get(..4)?) as usize;
+    let raw = image.at(offset).get(..0x1C)?;
+    if &raw[..4] != Mod0Header::MAGIC {
+        return None;
+    }
+    let field = |i: usize| (offset as i64 + LittleEndian::read_i32(&raw[4 * i..]) as i64) as usize;
+    Some(Mod0Header {
+        offset,
+        dynamic: field(1),
+        bss_start: field(2),
+        bss_end: field(3),
+        eh_frame_hdr_start: field(4),
+        eh_frame_hdr_end: field(5),
+        module_object: field(6),
+    })
+}
+
+fn parse_dynamic(image: &SegmentView, offset: usize) -> Vec<(i64, u64)> {
+    image.at(offset).chunks_exact(16)
+        .map(|e| (LittleEndian::read_i64(e), LittleEndian::read_u64(&e[8..])))
+        .take_while(|(tag, _)| *tag != DT_NULL)
+        .collect()
+}
+
+fn dynamic_value(dynamic: &[(i64, u64)], tag: i64) -> Option<usize> {
+    dynamic.iter().find(|(t, _)| *t == tag).map(|(_, v)| *v as usize)
+}
+
+fn parse_symbols(image: &SegmentView, dynamic: &[(i64, u64)]) -> Vec<Symbol> {
+    let (Some(symtab), Some(strtab)) = (dynamic_value(dynamic, DT_SYMTAB), dynamic_value(dynamic, DT_STRTAB)) else {
+        return Vec::new();
+    };
+    let entry_size = dynamic_value(dynamic, DT_SYMENT).unwrap_or(SYMBOL_SIZE);
+    // DT_HASH records the symbol count as nchain. Without it, rely on the
+    // usual layout of .dynstr directly following .dynsym.
+    let count = match dynamic_value(dynamic, DT_HASH) {
+        Some(hash) => image.at(hash + 4).get(..4).map_or(0, LittleEndian::read_u32) as usize,
+        None => strtab.saturating_sub(symtab) / entry_size,
+    };
+
+    // The table ends early if it runs past the end of its segment.
+    (0..count)
+        .map_while(|i| image.at(symtab + i * entry_size).get(..SYMBOL_SIZE))
+        .map(|raw| Symbol::read(raw, image.at(strtab)))
+        .collect()
+}
+
+fn parse_relocations(image: &SegmentView, dynamic: &[(i64, u64)]) -> Vec<Relocation> {
+    let tables = [(DT_RELA, DT_RELASZ), (DT_JMPREL, DT_PLTRELSZ)];
+    tables.iter()
+        .filter_map(|(table, size)| Some((dynamic_value(dynamic, *table)?, dynamic_value(dynamic, *size)?)))
+        // Tables that do not fit in their segment are skipped.
+        .filter_map(|(offset, size)| image.at(offset).get(..size))
+        .flat_map(|table| table.chunks_exact(RELA_SIZE))
+        .map(|raw| {
+            let info = LittleEndian::read_u64(&raw[8..]);
+            Relocation {
+                offset: LittleEndian::read_u64(raw),
+                typ: info as u32,
+                symbol: (info >> 32) as u32,
+                addend: LittleEndian::read_i64(&raw[16..]),
+            }
+        })
+        .collect()
+}
+
+/// A module placed inside a [`ProcessImage`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LoadedModule {
+    pub name: String,
+    pub base: u64,
+    pub size: u64,
+    pub symbols: Vec<Symbol>,
+}
+
+/// An import no loaded module exports.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnresolvedImport {
+    pub module: String,
+    pub symbol: String,
+    /// Absolute address of the slot that was left untouched.
+    pub address: u64,
+}
+
+/// Several modules mapped into one address space and linked against each
+/// other the way rtld does it at process start.
+pub struct ProcessImage {
+    pub base: u64,
+    pub memory: Vec<u8>,
+    pub modules: Vec<LoadedModule>,
+    pub unresolved: Vec<UnresolvedImport>,
+}
+
+impl ProcessImage {
+    /// Default base address of the first module in a 39-bit address space.
+    pub const DEFAULT_BASE: u64 = 0x8000000;
+
+    /// Places `modules`, given in load order (rtld, main, subsdk*, sdk), at
+    /// consecutive page-aligned addresses starting at `base`, then applies
+    /// their relocations.
+    ///
+    /// R_AARCH64_RELATIVE adds the module base. R_AARCH64_GLOB_DAT,
+    /// R_AARCH64_JUMP_SLOT and R_AARCH64_ABS64 are bound to the first module,
+    /// in load order, that exports the symbol; a module's own definition is
+    /// only preferred when it has non-default visibility. Unresolved weak
+    /// imports become zero, other unresolved imports are left alone and
+    /// reported in `unresolved`. Relocations naming a symbol past the end of
+    /// the symbol table or patching memory outside their module are skipped,
+    /// as are relocation and symbol tables that do not fit in their segment.
+    pub fn load(modules: &[(String, &SwitchExecutable)], base: u64) -> ProcessImage {
+        let mut memory = Vec::new();
+        let mut loaded = Vec::new();
+        let mut relocations = Vec::new();
+
+        for (name, exe) in modules {
+            let offset = memory.len();
+            let image = exe.memory_image();
+            let (view, dynamic) = (exe.segment_view(), exe.dynamic_entries());
+            relocations.push(parse_relocations(&view, &dynamic));
+            loaded.push(LoadedModule {
+                name: name.clone(),
+                base: base + offset as u64,
+                size: image.len() as u64,
+                symbols: parse_symbols(&view, &dynamic),
+            });
+            memory.extend_from_slice(&image);
+        }
+
+        let mut process = ProcessImage { base, memory, modules: loaded, unresolved: Vec::new() };
+        for (index, relocations) in relocations.iter().enumerate() {
+            for relocation in relocations {
+                process.apply(index, relocation);
+            }
+        }
+        process
+    }
+
+    fn apply(&mut self, module: usize, relocation: &Relocation) {
+        let module_base = self.modules[module].base;
+        // A slot outside the module would patch its neighbour.
+        if relocation.offset.checked_add(8).is_none_or(|end| end > self.modules[module].size) {
+            return;
+        }
+        let address = module_base + relocation.offset;
+        let value = match relocation.typ {
+            R_AARCH64_RELATIVE => module_base.wrapping_add(relocation.addend as u64),
+            R_AARCH64_GLOB_DAT | R_AARCH64_JUMP_SLOT | R_AARCH64_ABS64 => {
+                let Some(symbol) = self.modules[module].symbols.get(relocation.symbol as usize) else {
+                    return;
+                };
+                let local = symbol.is_defined().then(|| module_base + symbol.value);
+                let target = if local.is_some() && symbol.visibility() != Symbol::STV_DEFAULT {
+                    local
+                } else {
+                    self.resolve(&symbol.name).or(local)
+                };
+                match target {
+                    Some(target) => target.wrapping_add(relocation.addend as u64),
+                    None if symbol.binding() == Symbol::STB_WEAK => 0,
+                    None => {
+                        self.unresolved.push(UnresolvedImport {
+                            module: self.modules[module].name.clone(),
+                            symbol: symbol.name.clone(),
+                            address,
+                        });
+                        return;
+                    }
+                }
+            }
+            _ => return,
+        };
+        self.write_u64(address, value);
+    }
+
+    /// Looks up an exported symbol across all modules, in load order.
+    /// Hidden and internal symbols are not visible to other modules.
+    pub fn resolve(&self, name: &str) -> Option<u64> {
+        self.modules.iter().find_map(|module| {
+            module.symbols.iter()
+                .filter(|s| matches!(s.visibility(), Symbol::STV_DEFAULT | Symbol::STV_PROTECTED))
+                .find(|s| s.is_export() && s.name == name)
+                .map(|s| module.base + s.value)
+        })
+    }
+
+    /// The module whose image contains `address`.
+    pub fn module_at(&self, address: u64) -> Option<&LoadedModule> {
+        self.modules.iter().find(|m| (m.base..m.base + m.size).contains(&address))
+    }
+
+    /// The eight bytes at `address`, or None if they are not all mapped.
+    pub fn read_u64(&self, address: u64) -> Option<u64> {
+        let offset = address.checked_sub(self.base)? as usize;
+        self.memory.get(offset..offset.checked_add(8)?).map(LittleEndian::read_u64)
+    }
+
+    /// Writes to an unmapped address are dropped.
+    fn write_u64(&mut self, address: u64, value: u64) {
+        let Some(offset) = address.checked_sub(self.base).map(|o| o as usize) else {
+            return;
+        };
+        if let Some(bytes) = offset.checked_add(8).and_then(|end| self.memory.get_mut(offset..end)) {
+            LittleEndian::write_u64(bytes, value);
+        }
+    }
+}
@@ -0,0 +1,138 @@
+/// Decoder for raw LZ4 blocks (no frame header), as used by NSO segments.
+///
+/// A block is a series of sequences. Each starts with a token whose high
+/// nibble is the literal length and low nibble the match length minus four;
+/// a nibble of 15 means more length bytes follow, each added until one is
+/// not 255. The literals come next, then a little-endian u16 offset back
+/// into the output. The last sequence has literals only.
+///
+/// Returns None if the block is malformed or does not decompress to exactly
+/// `decompressed_size` bytes.
+pub fn decompress(input: &[u8], decompressed_size: usize) -> Option<Vec<u8>> {
+    let mut out: Vec<u8> = Vec::with_capacity(decompressed_size);
+    let mut pos = 0;
+
+    loop {
+        let token = *input.get(pos)?;
+        pos += 1;
+
+        let literal_len = read_length(input, &mut pos, (token >> 4) as usize)?;
+        out.extend_from_slice(input.get(pos..pos + literal_len)?);
+        pos += literal_len;
+
+        if pos == input.len() {
+            break;
+        }
+
+        let offset = u16::from_le_bytes([*input.get(pos)?, *input.get(pos + 1)?]) as usize;
+        pos += 2;
+        if offset == 0 || offset > out.len() {
+            return None;
+        }
+
+        let match_len = read_length(input, &mut pos, (token & 0xF) as usize)? + 4;
+        if out.len() + match_len > decompressed_size {
+            return None;
+        }
+        // The match may overlap the bytes it produces, so copy one at a time.
+        let start = out.len() - offset;
+        for i in 0..match_len {
+            out.push(out[start + i]);
+        }
+    }
+
+    if out.len() != decompressed_size {
+        return None;
+    }
+    Some(out)
+}
+
+/// Extends a 4-bit length from a token with the following 255-run bytes.
+fn read_length(input: &[u8], pos: &mut usize, nibble: usize) -> Option<usize> {
+    let mut len = nibble;
+    if nibble == 0xF {
+        loop {
+            let b = *input.get(*pos)?;
+            *pos += 1;
+            len += b as usize;
+            if b != 255 {
+                break;
+            }
+        }
+    }
+    Some(len)
+}
+
+/// The last five bytes of a block are always literals.
+const LAST_LITERALS: usize = 5;
+/// The last match must start at least twelve bytes before the end of the block.
+const MF_LIMIT: usize = 12;
+const HASH_BITS: u32 = 16;
+
+/// Greedy LZ4 block compressor. It finds matches through a single hash table
+/// of four-byte sequences, which is far from optimal but produces valid
+/// blocks that any LZ4 decoder, including the system loader, accepts.
+pub fn compress(input: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(input.len() / 2 + 16);
+    let mut table = vec![usize::MAX; 1 << HASH_BITS];
+    let mut anchor = 0;
+    let mut pos = 0;
+
+    if input.len() > MF_LIMIT {
+        let match_limit = input.len() - MF_LIMIT;
+        while pos < match_limit {
+            let sequence = read_u32(input, pos);
+            let hash = (sequence.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize;
+            let candidate = table[hash];
+            table[hash] = pos;
+
+            if candidate == usize::MAX || pos - candidate > 0xFFFF || read_u32(input, candidate) != sequence {
+                pos += 1;
+                continue;
+            }
+
+            let max_len = input.len() - LAST_LITERALS - pos;
+            let mut len = 4;
+            while len < max_len && input[candidate + len] == input[pos + len] {
+                len += 1;
+            }
+
+            write_sequence(&mut out, &input[anchor..pos], (pos - candidate) as u16, len);
+            pos += len;
+            anchor = pos;
+        }
+    }
+
+    // Final sequence: literals only.
+    let literals = &input[anchor..];
+    out.push((literals.len().min(15) as u8) << 4);
+    write_length(&mut out, literals.len());
+    out.extend_from_slice(literals);
+    out
+}
+
+fn read_u32(input: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes([input[pos], input[pos + 1], input[pos + 2], input[pos + 3]])
+}
+
+fn write_sequence(out: &mut Vec<u8>, literals: &[u8], offset: u16, match_len: usize) {
+    let match_code = match_len - 4;
+    out.push(((literals.len().min(15) as u8) << 4) | match_code.min(15) as u8);
+    write_length(out, literals.len());
+    out.extend_from_slice(literals);
+    out.extend_from_slice(&offset.to_le_bytes());
+    write_length(out, match_code);
+}
+
+/// Writes the 255-run continuation of a length whose nibble saturated at 15.
+fn write_length(out: &mut Vec<u8>, len: usize) {
+    if len < 15 {
+        return;
+    }
+    let mut rest = len - 15;
+    while rest >= 255 {
+        out.push(255);
+        rest -= 255;
+    }
+    out.push(rest as u8);
+}
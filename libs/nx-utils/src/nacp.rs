@@ -0,0 +1,343 @@
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+/// Languages in the order their titles appear in the NACP.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Language {
+    AmericanEnglish = 0,
+    BritishEnglish = 1,
+    Japanese = 2,
+    French = 3,
+    German = 4,
+    LatinAmericanSpanish = 5,
+    Spanish = 6,
+    Italian = 7,
+    Dutch = 8,
+    CanadianFrench = 9,
+    Portuguese = 10,
+    Russian = 11,
+    Korean = 12,
+    TraditionalChinese = 13,
+    SimplifiedChinese = 14,
+    BrazilianPortuguese = 15,
+}
+
+impl Language {
+    pub const ALL: [Language; 16] = [
+        Language::AmericanEnglish,
+        Language::BritishEnglish,
+        Language::Japanese,
+        Language::French,
+        Language::German,
+        Language::LatinAmericanSpanish,
+        Language::Spanish,
+        Language::Italian,
+        Language::Dutch,
+        Language::CanadianFrench,
+        Language::Portuguese,
+        Language::Russian,
+        Language::Korean,
+        Language::TraditionalChinese,
+        Language::SimplifiedChinese,
+        Language::BrazilianPortuguese,
+    ];
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct NacpTitle {
+    pub name: String,
+    pub publisher: String,
+}
+
+impl NacpTitle {
+    pub const NAME_SIZE: usize = 0x200;
+    pub const PUBLISHER_SIZE: usize = 0x100;
+
+    pub fn is_empty(&self) -> bool {
+        self.name.is_empty() && self.publisher.is_empty()
+    }
+}
+
+/// Application control property, the 0x4000-byte metadata block shipped in
+/// the NRO asset section and in control NCAs. Fields are named after their
+/// switchbrew counterparts. Everything past the last documented field is
+/// kept verbatim in `trailing` so that reading and writing round-trips.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Nacp {
+    pub titles: [NacpTitle; 16],
+    pub isbn: String,
+    pub startup_user_account: u8,
+    pub user_account_switch_lock: u8,
+    pub add_on_content_registration_type: u8,
+    pub attribute_flag: u32,
+    pub supported_language_flag: u32,
+    pub parental_control_flag: u32,
+    pub screenshot: u8,
+    pub video_capture: u8,
+    pub data_loss_confirmation: u8,
+    pub play_log_policy: u8,
+    pub presence_group_id: u64,
+    pub rating_age: [i8; 0x20],
+    pub display_version: String,
+    pub add_on_content_base_id: u64,
+    pub save_data_owner_id: u64,
+    pub user_account_save_data_size: i64,
+    pub user_account_save_data_journal_size: i64,
+    pub device_save_data_size: i64,
+    pub device_save_data_journal_size: i64,
+    pub bcat_delivery_cache_storage_size: i64,
+    pub application_error_code_category: String,
+    pub local_communication_id: [u64; 8],
+    pub logo_type: u8,
+    pub logo_handling: u8,
+    pub runtime_add_on_content_install: u8,
+    pub runtime_parameter_delivery: u8,
+    pub reserved_30f4: [u8; 2],
+    pub crash_report: u8,
+    pub hdcp: u8,
+    pub seed_for_pseudo_device_id: u64,
+    pub bcat_passphrase: String,
+    pub startup_user_account_option: u8,
+    pub reserved_3142: [u8; 6],
+    pub user_account_save_data_size_max: i64,
+    pub user_account_save_data_journal_size_max: i64,
+    pub device_save_data_size_max: i64,
+    pub device_save_data_journal_size_max: i64,
+    pub temporary_storage_size: i64,
+    pub cache_storage_size: i64,
+    pub cache_storage_journal_size: i64,
+    pub cache_storage_data_and_journal_size_max: i64,
+    pub cache_storage_index_max: u16,
+    pub reserved_318a: [u8; 6],
+    pub play_log_queryable_application_id: [u64; 16],
+    pub play_log_query_capability: u8,
+    pub repair_flag: u8,
+    pub program_index: u8,
+    pub required_network_service_license_on_launch: u8,
+    /// Bytes 0x3214..0x4000: reserved space and fields added by newer firmware.
+    pub trailing: Vec<u8>,
+}
+
+impl Nacp {
+    pub const SIZE: usize = 0x4000;
+    const TRAILING_OFFSET: usize = 0x3214;
+
+    pub fn read(bytes: &[u8]) -> Nacp {
+        assert_eq!(bytes.len(), Nacp::SIZE, "NACP must be 0x4000 bytes");
+        let mut reader = Cursor::new(bytes);
+
+        let titles = std::array::from_fn(|_| NacpTitle {
+            name: read_string(&mut reader, NacpTitle::NAME_SIZE),
+            publisher: read_string(&mut reader, NacpTitle::PUBLISHER_SIZE),
+        });
+        let isbn = read_string(&mut reader, 0x25);
+        let startup_user_account = reader.read_u8().unwrap();
+        let user_account_switch_lock = reader.read_u8().unwrap();
+        let add_on_content_registration_type = reader.read_u8().unwrap();
+        let attribute_flag = reader.read_u32::<LittleEndian>().unwrap();
+        let supported_language_flag = reader.read_u32::<LittleEndian>().unwrap();
+        let parental_control_flag = reader.read_u32::<LittleEndian>().unwrap();
+        let screenshot = reader.read_u8().unwrap();
+        let video_capture = reader.read_u8().unwrap();
+        let data_loss_confirmation = reader.read_u8().unwrap();
+        let play_log_policy = reader.read_u8().unwrap();
+        let presence_group_id = reader.read_u64::<LittleEndian>().unwrap();
+        let mut rating_age = [0i8; 0x20];
+        reader.read_i8_into(&mut rating_age).unwrap();
+        let display_version = read_string(&mut reader, 0x10);
+        let add_on_content_base_id = reader.read_u64::<LittleEndian>().unwrap();
+        let save_data_owner_id = reader.read_u64::<LittleEndian>().unwrap();
+        let user_account_save_data_size = reader.read_i64::<LittleEndian>().unwrap();
+        let user_account_save_data_journal_size = reader.read_i64::<LittleEndian>().unwrap();
+        let device_save_data_size = reader.read_i64::<LittleEndian>().unwrap();
+        let device_save_data_journal_size = reader.read_i64::<LittleEndian>().unwrap();
+        let bcat_delivery_cache_storage_size = reader.read_i64::<LittleEndian>().unwrap();
+        let application_error_code_category = read_string(&mut reader, 8);
+        let mut local_communication_id = [0u64; 8];
+        reader.read_u64_into::<LittleEndian>(&mut local_communication_id).unwrap();
+        let logo_type = reader.read_u8().unwrap();
+        let logo_handling = reader.read_u8().unwrap();
+        let runtime_add_on_content_install = reader.read_u8().unwrap();
+        let runtime_parameter_delivery = reader.read_u8().unwrap();
+        let mut reserved_30f4 = [0u8; 2];
+        reader.read_exact(&mut reserved_30f4).unwrap();
+        let crash_report = reader.read_u8().unwrap();
+        let hdcp = reader.read_u8().unwrap();
+        let seed_for_pseudo_device_id = reader.read_u64::<LittleEndian>().unwrap();
+        let bcat_passphrase = read_string(&mut reader, 0x41);
+        let startup_user_account_option = reader.read_u8().unwrap();
+        let mut reserved_3142 = [0u8; 6];
+        reader.read_exact(&mut reserved_3142).unwrap();
+        let user_account_save_data_size_max = reader.read_i64::<LittleEndian>().unwrap();
+        let user_account_save_data_journal_size_max = reader.read_i64::<LittleEndian>().unwrap();
+        let device_save_data_size_max = reader.read_i64::<LittleEndian>().unwrap();
+        let device_save_data_journal_size_max = reader.read_i64::<LittleEndian>().unwrap();
+        let temporary_storage_size = reader.read_i64::<LittleEndian>().unwrap();
+        let cache_storage_size = reader.read_i64::<LittleEndian>().unwrap();
+        let cache_storage_journal_size = reader.read_i64::<LittleEndian>().unwrap();
+        let cache_storage_data_and_journal_size_max = reader.read_i64::<LittleEndian>().unwrap();
+        let cache_storage_index_max = reader.read_u16::<LittleEndian>().unwrap();
+        let mut reserved_318a = [0u8; 6];
+        reader.read_exact(&mut reserved_318a).unwrap();
+        let mut play_log_queryable_application_id = [0u64; 16];
+        reader.read_u64_into::<LittleEndian>(&mut play_log_queryable_application_id).unwrap();
+        let play_log_query_capability = reader.read_u8().unwrap();
+        let repair_flag = reader.read_u8().unwrap();
+        let program_index = reader.read_u8().unwrap();
+        let required_network_service_license_on_launch = reader.read_u8().unwrap();
+        assert_eq!(reader.position() as usize, Nacp::TRAILING_OFFSET);
+
+        Nacp {
+            titles,
+            isbn,
+            startup_user_account,
+            user_account_switch_lock,
+            add_on_content_registration_type,
+            attribute_flag,
+            supported_language_flag,
+            parental_control_flag,
+            screenshot,
+            video_capture,
+            data_loss_confirmation,
+            play_log_policy,
+            presence_group_id,
+            rating_age,
+            display_version,
+            add_on_content_base_id,
+            save_data_owner_id,
+            user_account_save_data_size,
+            user_account_save_data_journal_size,
+            device_save_data_size,
+            device_save_data_journal_size,
+            bcat_delivery_cache_storage_size,
+            application_error_code_category,
+            local_communication_id,
+            logo_type,
+            logo_handling,
+            runtime_add_on_content_install,
+            runtime_parameter_delivery,
+            reserved_30f4,
+            crash_report,
+            hdcp,
+            seed_for_pseudo_device_id,
+            bcat_passphrase,
+            startup_user_account_option,
+            reserved_3142,
+            user_account_save_data_size_max,
+            user_account_save_data_journal_size_max,
+            device_save_data_size_max,
+            device_save_data_journal_size_max,
+            temporary_storage_size,
+            cache_storage_size,
+            cache_storage_journal_size,
+            cache_storage_data_and_journal_size_max,
+            cache_storage_index_max,
+            reserved_318a,
+            play_log_queryable_application_id,
+            play_log_query_capability,
+            repair_flag,
+            program_index,
+            required_network_service_license_on_launch,
+            trailing: bytes[Nacp::TRAILING_OFFSET..].to_vec(),
+        }
+    }
+
+    /// Serializes back into the 0x4000-byte on-disk format. Panics if a string
+    /// does not fit into its fixed-size field.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Cursor::new(vec![0u8; Nacp::SIZE]);
+
+        for title in &self.titles {
+            write_string(&mut writer, &title.name, NacpTitle::NAME_SIZE);
+            write_string(&mut writer, &title.publisher, NacpTitle::PUBLISHER_SIZE);
+        }
+        write_string(&mut writer, &self.isbn, 0x25);
+        writer.write_u8(self.startup_user_account).unwrap();
+        writer.write_u8(self.user_account_switch_lock).unwrap();
+        writer.write_u8(self.add_on_content_registration_type).unwrap();
+        writer.write_u32::<LittleEndian>(self.attribute_flag).unwrap();
+        writer.write_u32::<LittleEndian>(self.supported_language_flag).unwrap();
+        writer.write_u32::<LittleEndian>(self.parental_control_flag).unwrap();
+        writer.write_u8(self.screenshot).unwrap();
+        writer.write_u8(self.video_capture).unwrap();
+        writer.write_u8(self.data_loss_confirmation).unwrap();
+        writer.write_u8(self.play_log_policy).unwrap();
+        writer.write_u64::<LittleEndian>(self.presence_group_id).unwrap();
+        for age in self.rating_age {
+            writer.write_i8(age).unwrap();
+        }
+        write_string(&mut writer, &self.display_version, 0x10);
+        writer.write_u64::<LittleEndian>(self.add_on_content_base_id).unwrap();
+        writer.write_u64::<LittleEndian>(self.save_data_owner_id).unwrap();
+        writer.write_i64::<LittleEndian>(self.user_account_save_data_size).unwrap();
+        writer.write_i64::<LittleEndian>(self.user_account_save_data_journal_size).unwrap();
+        writer.write_i64::<LittleEndian>(self.device_save_data_size).unwrap();
+        writer.write_i64::<LittleEndian>(self.device_save_data_journal_size).unwrap();
+        writer.write_i64::<LittleEndian>(self.bcat_delivery_cache_storage_size).unwrap();
+        write_string(&mut writer, &self.application_error_code_category, 8);
+        for id in self.local_communication_id {
+            writer.write_u64::<LittleEndian>(id).unwrap();
+        }
+        writer.write_u8(self.logo_type).unwrap();
+        writer.write_u8(self.logo_handling).unwrap();
+        writer.write_u8(self.runtime_add_on_content_install).unwrap();
+        writer.write_u8(self.runtime_parameter_delivery).unwrap();
+        writer.write_all(&self.reserved_30f4).unwrap();
+        writer.write_u8(self.crash_report).unwrap();
+        writer.write_u8(self.hdcp).unwrap();
+        writer.write_u64::<LittleEndian>(self.seed_for_pseudo_device_id).unwrap();
+        write_string(&mut writer, &self.bcat_passphrase, 0x41);
+        writer.write_u8(self.startup_user_account_option).unwrap();
+        writer.write_all(&self.reserved_3142).unwrap();
+        writer.write_i64::<LittleEndian>(self.user_account_save_data_size_max).unwrap();
+        writer.write_i64::<LittleEndian>(self.user_account_save_data_journal_size_max).unwrap();
+        writer.write_i64::<LittleEndian>(self.device_save_data_size_max).unwrap();
+        writer.write_i64::<LittleEndian>(self.device_save_data_journal_size_max).unwrap();
+        writer.write_i64::<LittleEndian>(self.temporary_storage_size).unwrap();
+        writer.write_i64::<LittleEndian>(self.cache_storage_size).unwrap();
+        writer.write_i64::<LittleEndian>(self.cache_storage_journal_size).unwrap();
+        writer.write_i64::<LittleEndian>(self.cache_storage_data_and_journal_size_max).unwrap();
+        writer.write_u16::<LittleEndian>(self.cache_storage_index_max).unwrap();
+        writer.write_all(&self.reserved_318a).unwrap();
+        for id in self.play_log_queryable_application_id {
+            writer.write_u64::<LittleEndian>(id).unwrap();
+        }
+        writer.write_u8(self.play_log_query_capability).unwrap();
+        writer.write_u8(self.repair_flag).unwrap();
+        writer.write_u8(self.program_index).unwrap();
+        writer.write_u8(self.required_network_service_license_on_launch).unwrap();
+        assert_eq!(self.trailing.len(), Nacp::SIZE - Nacp::TRAILING_OFFSET);
+        writer.write_all(&self.trailing).unwrap();
+
+        writer.into_inner()
+    }
+
+    pub fn title(&self, language: Language) -> &NacpTitle {
+        &self.titles[language as usize]
+    }
+
+    pub fn title_mut(&mut self, language: Language) -> &mut NacpTitle {
+        &mut self.titles[language as usize]
+    }
+
+    /// The title shown by the system when the console language has no entry:
+    /// the first non-empty one, in language order.
+    pub fn default_title(&self) -> Option<&NacpTitle> {
+        self.titles.iter().find(|t| !t.is_empty())
+    }
+}
+
+/// Reads a NUL-padded UTF-8 string occupying exactly `size` bytes.
+fn read_string(reader: &mut Cursor<&[u8]>, size: usize) -> String {
+    let mut buf = vec![0u8; size];
+    reader.read_exact(&mut buf).unwrap();
+    let len = buf.iter().position(|b| *b == 0).unwrap_or(size);
+    String::from_utf8_lossy(&buf[..len]).into_owned()
+}
+
+fn write_string(writer: &mut Cursor<Vec<u8>>, s: &str, size: usize) {
+    assert!(s.len() <= size, "string {:?} does not fit into {:#x} bytes", s, size);
+    writer.write_all(s.as_bytes()).unwrap();
+    writer.seek(SeekFrom::Current((size - s.len()) as i64)).unwrap();
+}
@@ -0,0 +1,286 @@
+use byteorder::{ByteOrder, LittleEndian};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AddressSpaceType {
+    AddressSpace32Bit,
+    /// 36-bit address space, used by early 64-bit titles.
+    AddressSpace64BitOld,
+    AddressSpace32BitNoReserved,
+    /// 39-bit address space.
+    AddressSpace64Bit,
+    /// One of the reserved values 4-7.
+    Unknown(u8),
+}
+
+/// The META header at the start of `main.npdm`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NpdmMeta {
+    pub flags: u8,
+    pub main_thread_priority: u8,
+    pub main_thread_core: u8,
+    pub system_resource_size: u32,
+    pub version: u32,
+    pub main_thread_stack_size: u32,
+    pub name: String,
+    pub product_code: String,
+}
+
+impl NpdmMeta {
+    pub const MAGIC: &'static [u8; 4] = b"META";
+
+    pub fn is_64bit(&self) -> bool {
+        self.flags & 1 != 0
+    }
+
+    pub fn address_space_type(&self) -> AddressSpaceType {
+        match (self.flags >> 1) & 0b111 {
+            0 => AddressSpaceType::AddressSpace32Bit,
+            1 => AddressSpaceType::AddressSpace64BitOld,
+            2 => AddressSpaceType::AddressSpace32BitNoReserved,
+            3 => AddressSpaceType::AddressSpace64Bit,
+            other => AddressSpaceType::Unknown(other),
+        }
+    }
+}
+
+/// One entry of a service access control list.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ServiceAccess {
+    /// Service name, possibly ending in a `*` wildcard.
+    pub name: String,
+    /// True if the title may register (host) the service rather than connect to it.
+    pub is_server: bool,
+}
+
+/// A decoded kernel capability descriptor. The type of a descriptor is given
+/// by the number of consecutive set bits counted from bit 0.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KernelCapability {
+    ThreadInfo { lowest_priority: u8, highest_priority: u8, min_core: u8, max_core: u8 },
+    /// SVCs `index * 24 + bit` for every set bit of `mask` are allowed.
+    EnableSystemCalls { index: u8, mask: u32 },
+    MapRange { address: u64, size: u64, read_only: bool, is_io: bool },
+    MapIoPage { address: u64 },
+    MapRegion(u32),
+    InterruptPair { interrupts: [u16; 2] },
+    ProgramType(u8),
+    KernelVersion { major: u16, minor: u8 },
+    HandleTableSize(u16),
+    MiscFlags { enable_debug: bool, force_debug: bool },
+    Unknown(u32),
+}
+
+impl KernelCapability {
+    /// Decodes a list of descriptors. Unused 0xFFFFFFFF entries are skipped.
+    /// MapRange takes two descriptors; the second is consumed with the first,
+    /// and parsing stops at a MapRange whose second half is missing.
+    pub fn parse_all(descriptors: &[u32]) -> Vec<KernelCapability> {
+        let mut caps = Vec::new();
+        let mut iter = descriptors.iter().copied();
+        while let Some(d) = iter.next() {
+            let cap = match (!d).trailing_zeros() {
+                3 => KernelCapability::ThreadInfo {
+                    lowest_priority: ((d >> 4) & 0x3F) as u8,
+                    highest_priority: ((d >> 10) & 0x3F) as u8,
+                    min_core: ((d >> 16) & 0xFF) as u8,
+                    max_core: ((d >> 24) & 0xFF) as u8,
+                },
+                4 => KernelCapability::EnableSystemCalls {
+                    index: ((d >> 29) & 0b111) as u8,
+                    mask: (d >> 5) & 0xFFFFFF,
+                },
+                6 => {
+                    let Some(size) = iter.next() else { break };
+                    KernelCapability::MapRange {
+                        address: (((d >> 7) & 0xFFFFFF) as u64) << 12,
+                        size: (((size >> 7) & 0xFFFFF) as u64) << 12,
+                        read_only: d >> 31 != 0,
+                        is_io: size >> 31 == 0,
+                    }
+                }
+                7 => KernelCapability::MapIoPage { address: ((d >> 8) as u64) << 12 },
+                10 => KernelCapability::MapRegion(d),
+                11 => KernelCapability::InterruptPair {
+                    interrupts: [((d >> 12) & 0x3FF) as u16, ((d >> 22) & 0x3FF) as u16],
+                },
+                13 => KernelCapability::ProgramType(((d >> 14) & 0b111) as u8),
+                14 => KernelCapability::KernelVersion {
+                    major: (d >> 19) as u16,
+                    minor: ((d >> 15) & 0xF) as u8,
+                },
+                15 => KernelCapability::HandleTableSize(((d >> 16) & 0x3FF) as u16),
+                16 => KernelCapability::MiscFlags {
+                    enable_debug: d & (1 << 17) != 0,
+                    force_debug: d & (1 << 18) != 0,
+                },
+                32 => continue,
+                _ => KernelCapability::Unknown(d),
+            };
+            caps.push(cap);
+        }
+        caps
+    }
+}
+
+/// Bit `n % 64` of word `n / 64` is set if SVC `n` may be called. The
+/// 3-bit index and 24-bit mask of EnableSystemCalls cover SVCs 0 to 0xBF.
+pub fn allowed_svcs(caps: &[KernelCapability]) -> [u64; 3] {
+    let mut svcs = [0u64; 3];
+    for cap in caps {
+        if let KernelCapability::EnableSystemCalls { index, mask } = cap {
+            for bit in (0..24).filter(|bit| mask & (1 << bit) != 0) {
+                let svc = *index as usize * 24 + bit;
+                svcs[svc / 64] |= 1 << (svc % 64);
+            }
+        }
+    }
+    svcs
+}
+
+/// The access control info (ACI0) the title actually runs with.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Aci0 {
+    pub program_id: u64,
+    /// The raw filesystem access header.
+    pub fs_access: Vec<u8>,
+    pub services: Vec<ServiceAccess>,
+    pub kernel_capabilities: Vec<KernelCapability>,
+}
+
+impl Aci0 {
+    pub const MAGIC: &'static [u8; 4] = b"ACI0";
+
+    pub fn read(bytes: &[u8]) -> Aci0 {
+        assert_eq!(&bytes[..4], Aci0::MAGIC);
+        Aci0 {
+            program_id: LittleEndian::read_u64(&bytes[0x10..]),
+            fs_access: sub_section(bytes, 0x20).to_vec(),
+            services: read_services(sub_section(bytes, 0x28)),
+            kernel_capabilities: read_kernel_capabilities(sub_section(bytes, 0x30)),
+        }
+    }
+}
+
+/// The signed access control descriptor (ACID): the upper bound of what
+/// the ACI0 may request.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Acid {
+    pub signature: Vec<u8>,
+    pub public_key: Vec<u8>,
+    pub flags: u32,
+    pub program_id_min: u64,
+    pub program_id_max: u64,
+    /// The raw filesystem access control.
+    pub fs_access: Vec<u8>,
+    pub services: Vec<ServiceAccess>,
+    pub kernel_capabilities: Vec<KernelCapability>,
+}
+
+impl Acid {
+    pub const MAGIC: &'static [u8; 4] = b"ACID";
+
+    pub fn read(bytes: &[u8]) -> Acid {
+        assert_eq!(&bytes[0x200..0x204], Acid::MAGIC);
+        Acid {
+            signature: bytes[..0x100].to_vec(),
+            public_key: bytes[0x100..0x200].to_vec(),
+            flags: LittleEndian::read_u32(&bytes[0x20C..]),
+            program_id_min: LittleEndian::read_u64(&bytes[0x210..]),
+            program_id_max: LittleEndian::read_u64(&bytes[0x218..]),
+            fs_access: sub_section(bytes, 0x220).to_vec(),
+            services: read_services(sub_section(bytes, 0x228)),
+            kernel_capabilities: read_kernel_capabilities(sub_section(bytes, 0x230)),
+        }
+    }
+
+    pub fn is_production(&self) -> bool {
+        self.flags & 1 != 0
+    }
+}
+
+/// Program metadata from an ExeFS `main.npdm`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Npdm {
+    pub meta: NpdmMeta,
+    pub aci0: Aci0,
+    pub acid: Acid,
+}
+
+impl Npdm {
+    pub fn read(bytes: &[u8]) -> Npdm {
+        assert_eq!(&bytes[..4], NpdmMeta::MAGIC);
+        let meta = NpdmMeta {
+            flags: bytes[0x0C],
+            main_thread_priority: bytes[0x0E],
+            main_thread_core: bytes[0x0F],
+            system_resource_size: LittleEndian::read_u32(&bytes[0x14..]),
+            version: LittleEndian::read_u32(&bytes[0x18..]),
+            main_thread_stack_size: LittleEndian::read_u32(&bytes[0x1C..]),
+            name: read_string(&bytes[0x20..0x30]),
+            product_code: read_string(&bytes[0x30..0x40]),
+        };
+
+        Npdm {
+            meta,
+            aci0: Aci0::read(sub_section(bytes, 0x70)),
+            acid: Acid::read(sub_section(bytes, 0x78)),
+        }
+    }
+
+    pub fn title_id(&self) -> u64 {
+        self.aci0.program_id
+    }
+
+    /// Bit `n % 64` of word `n / 64` is set if SVC `n` may be called.
+    pub fn allowed_svcs(&self) -> [u64; 3] {
+        allowed_svcs(&self.aci0.kernel_capabilities)
+    }
+
+    pub fn is_svc_allowed(&self, svc: u8) -> bool {
+        let svc = svc as usize;
+        svc < 0xC0 && self.allowed_svcs()[svc / 64] & (1 << (svc % 64)) != 0
+    }
+
+    /// Services the title may connect to or host, from its ACI0.
+    pub fn services(&self) -> &[ServiceAccess] {
+        &self.aci0.services
+    }
+}
+
+/// Resolves an (offset, size) pair stored at `at`, relative to `bytes`.
+fn sub_section(bytes: &[u8], at: usize) -> &[u8] {
+    let offset = LittleEndian::read_u32(&bytes[at..]) as usize;
+    let size = LittleEndian::read_u32(&bytes[at + 4..]) as usize;
+    &bytes[offset..][..size]
+}
+
+fn read_string(bytes: &[u8]) -> String {
+    let len = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..len]).into_owned()
+}
+
+/// Each entry is a control byte (bit 7: server, bits 0..2: name length - 1)
+/// followed by the name. A truncated entry ends the list.
+fn read_services(mut bytes: &[u8]) -> Vec<ServiceAccess> {
+    let mut services = Vec::new();
+    while let Some((&control, rest)) = bytes.split_first() {
+        if control == 0 {
+            break;
+        }
+        let len = (control & 0b111) as usize + 1;
+        let Some((name, rest)) = rest.split_at_checked(len) else {
+            break;
+        };
+        services.push(ServiceAccess {
+            name: String::from_utf8_lossy(name).into_owned(),
+            is_server: control & 0x80 != 0,
+        });
+        bytes = rest;
+    }
+    services
+}
+
+fn read_kernel_capabilities(bytes: &[u8]) -> Vec<KernelCapability> {
+    let descriptors: Vec<u32> = bytes.chunks_exact(4).map(LittleEndian::read_u32).collect();
+    KernelCapability::parse_all(&descriptors)
+}
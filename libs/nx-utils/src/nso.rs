@@ -0,0 +1,361 @@
+use std::borrow::Cow;
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::executable::Executable;
+use crate::NroSegmentType::{DATA, RO, TEXT};
+use crate::sha256::sha256;
+use crate::{lz4, NroSegmentType, SegmentBytes, SwitchExecutable};
+
+/// Where a segment lives in the file and in memory. `size` is the
+/// decompressed size; the size in the file is in [`NsoHeader::file_sizes`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct NsoSegmentHeader {
+    pub file_offset: u32,
+    pub memory_offset: u32,
+    pub size: u32,
+}
+
+impl NsoSegmentHeader {
+    fn new(reader: &mut Cursor<&[u8]>) -> NsoSegmentHeader {
+        NsoSegmentHeader {
+            file_offset: reader.read_u32::<LittleEndian>().unwrap(),
+            memory_offset: reader.read_u32::<LittleEndian>().unwrap(),
+            size: reader.read_u32::<LittleEndian>().unwrap(),
+        }
+    }
+}
+
+/// An (offset, size) pair relative to the start of .rodata.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct NsoRoExtent {
+    pub offset: u32,
+    pub size: u32,
+}
+
+impl NsoRoExtent {
+    fn new(reader: &mut Cursor<&[u8]>) -> NsoRoExtent {
+        NsoRoExtent {
+            offset: reader.read_u32::<LittleEndian>().unwrap(),
+            size: reader.read_u32::<LittleEndian>().unwrap(),
+        }
+    }
+}
+
+/// The 0x100-byte NSO0 header. Segment arrays are indexed by [`NroSegmentType`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct NsoHeader {
+    pub version: u32,
+    pub flags: u32,
+    pub segments: [NsoSegmentHeader; 3],
+    pub module_name_offset: u32,
+    pub module_name_size: u32,
+    pub bss_size: u32,
+    pub build_id: [u8; 0x20],
+    /// Segment sizes in the file, i.e. after compression.
+    pub file_sizes: [u32; 3],
+    pub api_info: NsoRoExtent,
+    pub dynstr: NsoRoExtent,
+    pub dynsym: NsoRoExtent,
+    /// SHA-256 of each decompressed segment.
+    pub hashes: [[u8; 0x20]; 3],
+    /// The module name stored at `module_name_offset`, usually a single NUL.
+    pub module_name: Vec<u8>,
+}
+
+impl NsoHeader {
+    pub const MAGIC: &'static [u8; 4] = b"NSO0";
+    pub const SIZE: usize = 0x100;
+
+    pub const TEXT_COMPRESS: u32 = 1 << 0;
+    pub const RO_COMPRESS: u32 = 1 << 1;
+    pub const DATA_COMPRESS: u32 = 1 << 2;
+    pub const TEXT_HASH: u32 = 1 << 3;
+    pub const RO_HASH: u32 = 1 << 4;
+    pub const DATA_HASH: u32 = 1 << 5;
+
+    pub fn new(reader: &mut Cursor<&[u8]>) -> NsoHeader {
+        let mut buf: [u8; 4] = [0, 0, 0, 0];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, NsoHeader::MAGIC);
+        let version = reader.read_u32::<LittleEndian>().unwrap();
+        reader.seek(SeekFrom::Current(4)).unwrap();
+        let flags = reader.read_u32::<LittleEndian>().unwrap();
+        let text = NsoSegmentHeader::new(reader);
+        let module_name_offset = reader.read_u32::<LittleEndian>().unwrap();
+        let ro = NsoSegmentHeader::new(reader);
+        let module_name_size = reader.read_u32::<LittleEndian>().unwrap();
+        let data = NsoSegmentHeader::new(reader);
+        let bss_size = reader.read_u32::<LittleEndian>().unwrap();
+        let mut build_id = [0u8; 0x20];
+        reader.read_exact(&mut build_id).unwrap();
+        let mut file_sizes = [0u32; 3];
+        reader.read_u32_into::<LittleEndian>(&mut file_sizes).unwrap();
+        reader.seek(SeekFrom::Current(0x1C)).unwrap();
+        let api_info = NsoRoExtent::new(reader);
+        let dynstr = NsoRoExtent::new(reader);
+        let dynsym = NsoRoExtent::new(reader);
+        let mut hashes = [[0u8; 0x20]; 3];
+        for hash in hashes.iter_mut() {
+            reader.read_exact(hash).unwrap();
+        }
+
+        let mut module_name = vec![0u8; module_name_size as usize];
+        reader.seek(SeekFrom::Start(module_name_offset as u64)).unwrap();
+        reader.read_exact(&mut module_name).unwrap();
+
+        NsoHeader {
+            version,
+            flags,
+            segments: [text, ro, data],
+            module_name_offset,
+            module_name_size,
+            bss_size,
+            build_id,
+            file_sizes,
+            api_info,
+            dynstr,
+            dynsym,
+            hashes,
+            module_name,
+        }
+    }
+
+    /// Serializes the fixed 0x100-byte header; the module name is not included.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Cursor::new(Vec::with_capacity(NsoHeader::SIZE));
+        writer.write_all(NsoHeader::MAGIC).unwrap();
+        writer.write_u32::<LittleEndian>(self.version).unwrap();
+        writer.write_u32::<LittleEndian>(0).unwrap();
+        writer.write_u32::<LittleEndian>(self.flags).unwrap();
+        let extras = [self.module_name_offset, self.module_name_size, self.bss_size];
+        for (segment, extra) in self.segments.iter().zip(extras) {
+            writer.write_u32::<LittleEndian>(segment.file_offset).unwrap();
+            writer.write_u32::<LittleEndian>(segment.memory_offset).unwrap();
+            writer.write_u32::<LittleEndian>(segment.size).unwrap();
+            writer.write_u32::<LittleEndian>(extra).unwrap();
+        }
+        writer.write_all(&self.build_id).unwrap();
+        for size in self.file_sizes {
+            writer.write_u32::<LittleEndian>(size).unwrap();
+        }
+        writer.write_all(&[0; 0x1C]).unwrap();
+        for extent in [&self.api_info, &self.dynstr, &self.dynsym] {
+            writer.write_u32::<LittleEndian>(extent.offset).unwrap();
+            writer.write_u32::<LittleEndian>(extent.size).unwrap();
+        }
+        for hash in &self.hashes {
+            writer.write_all(hash).unwrap();
+        }
+        assert_eq!(writer.get_ref().len(), NsoHeader::SIZE);
+        writer.into_inner()
+    }
+
+    pub fn is_compressed(&self, segment: NroSegmentType) -> bool {
+        self.flags & (NsoHeader::TEXT_COMPRESS << segment as u32) != 0
+    }
+
+    /// Whether the loader is asked to check the segment against its hash.
+    pub fn check_hash(&self, segment: NroSegmentType) -> bool {
+        self.flags & (NsoHeader::TEXT_HASH << segment as u32) != 0
+    }
+
+    /// The segment exactly as stored in the file, possibly compressed.
+    pub fn file_segment<'a>(&self, file_bytes: &'a [u8], segment: NroSegmentType) -> &'a [u8] {
+        let index = segment as usize;
+        &file_bytes[self.segments[index].file_offset as usize..][..self.file_sizes[index] as usize]
+    }
+
+    /// Decompresses a segment if needed; uncompressed segments are borrowed
+    /// from `file_bytes`. Returns None if the LZ4 data is corrupt.
+    pub fn decompress_segment<'b>(&self, file_bytes: &'b [u8], segment: NroSegmentType) -> Option<Cow<'b, [u8]>> {
+        let size = self.segments[segment as usize].size as usize;
+        let compressed = self.is_compressed(segment);
+        let stored = self.file_segment(file_bytes, segment);
+        if compressed {
+            lz4::decompress(stored, size).map(Cow::Owned)
+        } else {
+            stored.get(..size).map(Cow::Borrowed)
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SegmentIntegrity {
+    /// The decompressed segment matches its SHA-256.
+    Valid,
+    /// The segment decompressed, but to different contents than were hashed.
+    HashMismatch,
+    /// The compressed data is corrupt or does not fit inside the file.
+    Corrupt,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NsoSegmentReport {
+    pub segment: NroSegmentType,
+    pub status: SegmentIntegrity,
+    /// Whether the header flags ask the loader to check this segment's hash.
+    pub hash_check_requested: bool,
+    pub expected: [u8; 0x20],
+    /// None if the segment could not be decompressed.
+    pub actual: Option<[u8; 0x20]>,
+}
+
+/// Result of checking every NSO segment against its header hash. All three
+/// segments are always checked; `hash_check_requested` records what the
+/// header flags ask for so callers can mimic the loader if they want to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NsoIntegrityReport {
+    pub segments: [NsoSegmentReport; 3],
+}
+
+impl NsoIntegrityReport {
+    /// True if every segment decompressed and matches its hash.
+    pub fn is_valid(&self) -> bool {
+        self.segments.iter().all(|s| s.status == SegmentIntegrity::Valid)
+    }
+
+    /// True if the loader would accept the file: every segment decompresses,
+    /// and those flagged for checking match their hash.
+    pub fn is_loadable(&self) -> bool {
+        self.segments.iter().all(|s| match s.status {
+            SegmentIntegrity::Valid => true,
+            SegmentIntegrity::HashMismatch => !s.hash_check_requested,
+            SegmentIntegrity::Corrupt => false,
+        })
+    }
+}
+
+impl NsoHeader {
+    /// Decompresses and hashes each segment of `file_bytes`, the file this
+    /// header was read from. Never panics on corrupt segment data.
+    pub fn verify(&self, file_bytes: &[u8]) -> NsoIntegrityReport {
+        let segments = [TEXT, RO, DATA].map(|segment| {
+            let index = segment as usize;
+            let file_offset = self.segments[index].file_offset as usize;
+            let in_bounds = file_offset.checked_add(self.file_sizes[index] as usize)
+                .is_some_and(|end| end <= file_bytes.len());
+            let actual = if in_bounds {
+                self.decompress_segment(file_bytes, segment).map(|bytes| sha256(&bytes))
+            } else {
+                None
+            };
+            let status = match actual {
+                None => SegmentIntegrity::Corrupt,
+                Some(hash) if hash == self.hashes[index] => SegmentIntegrity::Valid,
+                Some(_) => SegmentIntegrity::HashMismatch,
+            };
+            NsoSegmentReport {
+                segment,
+                status,
+                hash_check_requested: self.check_hash(segment),
+                expected: self.hashes[index],
+                actual,
+            }
+        });
+        NsoIntegrityReport { segments }
+    }
+}
+
+/// Checks an NSO file before loading it, so corrupted or tampered dumps can
+/// be rejected without [`SwitchExecutable::read_nso`] panicking halfway.
+pub fn verify_nso(file_bytes: &[u8]) -> NsoIntegrityReport {
+    let header = NsoHeader::new(&mut Cursor::new(file_bytes));
+    header.verify(file_bytes)
+}
+
+impl<'a> SwitchExecutable<'a> {
+    pub fn read_nso(file_bytes: impl Into<Cow<'a, [u8]>>) -> SwitchExecutable<'a> {
+        let file_bytes = file_bytes.into();
+        let header = NsoHeader::new(&mut Cursor::new(&file_bytes[..]));
+
+        let segment = |index, name| {
+            let bytes = header.decompress_segment(&file_bytes, index).unwrap_or_else(|| panic!("corrupt NSO {} segment", name));
+            SegmentBytes::new(&file_bytes, bytes)
+        };
+        let text = segment(TEXT, ".text");
+        let ro = segment(RO, ".rodata");
+        let data = segment(DATA, ".data");
+        let bss = vec![0; header.bss_size as usize];
+
+        SwitchExecutable {
+            program: file_bytes,
+            text,
+            ro,
+            data,
+            bss,
+            icon: None,
+            nacp: None,
+            romfs: None,
+            asset_version: 0,
+            nro_header: None,
+            nso_header: Some(header),
+            kip_header: None,
+            elf: None,
+        }
+    }
+
+    /// The NSO header, if this executable was read from an NSO.
+    pub fn nso_header(&self) -> Option<&NsoHeader> {
+        self.nso_header.as_ref()
+    }
+
+    /// Serializes text, ro and data into an NSO, optionally LZ4-compressing
+    /// every segment. Segments are placed at consecutive page-aligned memory
+    /// offsets and their hashes are recomputed. The module name and .rodata
+    /// extents are carried over when this executable came from an NSO; the
+    /// build ID is kept from whatever format it came from.
+    pub fn write_nso(&self, compress: bool) -> Vec<u8> {
+        let original = self.nso_header.clone().unwrap_or_default();
+        let module_name = if original.module_name.is_empty() { vec![0] } else { original.module_name };
+
+        let segments = [self.text(), self.ro(), self.data()];
+        let stored: Vec<Vec<u8>> = segments.iter()
+            .map(|s| if compress { lz4::compress(s) } else { s.to_vec() })
+            .collect();
+
+        let mut header = NsoHeader {
+            version: original.version,
+            flags: NsoHeader::TEXT_HASH | NsoHeader::RO_HASH | NsoHeader::DATA_HASH,
+            module_name_offset: NsoHeader::SIZE as u32,
+            module_name_size: module_name.len() as u32,
+            bss_size: self.bss.len() as u32,
+            build_id: Executable::build_id(self).unwrap_or_default(),
+            api_info: original.api_info,
+            dynstr: original.dynstr,
+            dynsym: original.dynsym,
+            module_name,
+            ..NsoHeader::default()
+        };
+        if compress {
+            header.flags |= NsoHeader::TEXT_COMPRESS | NsoHeader::RO_COMPRESS | NsoHeader::DATA_COMPRESS;
+        }
+
+        let mut file_offset = NsoHeader::SIZE + header.module_name.len();
+        let mut memory_offset = 0;
+        for (index, segment) in segments.iter().enumerate() {
+            header.segments[index] = NsoSegmentHeader {
+                file_offset: file_offset as u32,
+                memory_offset: memory_offset as u32,
+                size: segment.len() as u32,
+            };
+            header.file_sizes[index] = stored[index].len() as u32;
+            header.hashes[index] = sha256(segment);
+            file_offset += stored[index].len();
+            memory_offset = (memory_offset + segment.len() + 0xFFF) & !0xFFF;
+        }
+
+        let mut out = header.to_bytes();
+        out.extend_from_slice(&header.module_name);
+        for segment in stored {
+            out.extend_from_slice(&segment);
+        }
+        out
+    }
+
+    /// Checks the loaded segments against the NSO header hashes.
+    pub fn verify_nso(&self) -> Option<NsoIntegrityReport> {
+        Some(self.nso_header()?.verify(&self.program))
+    }
+}
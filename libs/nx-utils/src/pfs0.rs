@@ -0,0 +1,95 @@
+use byteorder::{ByteOrder, LittleEndian};
+
+use crate::nso::NsoHeader;
+use crate::SwitchExecutable;
+
+/// A file inside a PFS0. `offset` is relative to the start of the data region.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pfs0Entry {
+    pub name: String,
+    pub offset: u64,
+    pub size: u64,
+}
+
+/// Read-only view of a PFS0 ("partition filesystem") container, the format
+/// of ExeFS sections and NSP files. Entries are parsed up front; their
+/// contents are handed out as sub-slices of the borrowed bytes.
+pub struct Pfs0<'a> {
+    bytes: &'a [u8],
+    entries: Vec<Pfs0Entry>,
+    data_offset: usize,
+}
+
+impl<'a> Pfs0<'a> {
+    pub const MAGIC: &'static [u8; 4] = b"PFS0";
+    const ENTRY_SIZE: usize = 0x18;
+
+    pub fn new(bytes: &'a [u8]) -> Pfs0<'a> {
+        assert_eq!(&bytes[..4], Pfs0::MAGIC);
+        let file_count = LittleEndian::read_u32(&bytes[4..]) as usize;
+        let string_table_size = LittleEndian::read_u32(&bytes[8..]) as usize;
+        let string_table_offset = 0x10 + file_count * Pfs0::ENTRY_SIZE;
+        let string_table = &bytes[string_table_offset..][..string_table_size];
+
+        let entries = (0..file_count).map(|i| {
+            let raw = &bytes[0x10 + i * Pfs0::ENTRY_SIZE..];
+            // Names past the string table come out empty, invalid UTF-8 lossy.
+            let name = string_table.get(LittleEndian::read_u32(&raw[0x10..]) as usize..).unwrap_or_default();
+            let name_len = name.iter().position(|b| *b == 0).unwrap_or(name.len());
+            Pfs0Entry {
+                name: String::from_utf8_lossy(&name[..name_len]).into_owned(),
+                offset: LittleEndian::read_u64(&raw[0x00..]),
+                size: LittleEndian::read_u64(&raw[0x08..]),
+            }
+        }).collect();
+
+        Pfs0 {
+            bytes,
+            entries,
+            data_offset: string_table_offset + string_table_size,
+        }
+    }
+
+    pub fn entries(&self) -> &[Pfs0Entry] {
+        &self.entries
+    }
+
+    /// Returns the contents of the file called `name`.
+    pub fn get(&self, name: &str) -> Option<&'a [u8]> {
+        self.entries.iter().find(|e| e.name == name).map(|e| self.data(e))
+    }
+
+    pub fn data(&self, entry: &Pfs0Entry) -> &'a [u8] {
+        &self.bytes[self.data_offset + entry.offset as usize..][..entry.size as usize]
+    }
+}
+
+/// Position of an ExeFS module in the loader's order: rtld, main, subsdk0
+/// through subsdk9, then sdk. Anything else sorts last.
+pub fn exefs_load_order(name: &str) -> usize {
+    match name {
+        "rtld" => 0,
+        "main" => 1,
+        "sdk" => 12,
+        _ => match name.strip_prefix("subsdk").and_then(|n| n.parse::<usize>().ok()) {
+            Some(n) if n < 10 => 2 + n,
+            _ => usize::MAX,
+        },
+    }
+}
+
+impl<'a> SwitchExecutable<'a> {
+    /// Loads every NSO in an ExeFS (or any PFS0), in the loader's module
+    /// order. Other files such as `main.npdm` are skipped.
+    /// The modules borrow from `file_bytes`.
+    pub fn read_exefs(file_bytes: &'a [u8]) -> Vec<(String, SwitchExecutable<'a>)> {
+        let pfs0 = Pfs0::new(file_bytes);
+        let mut modules: Vec<&Pfs0Entry> = pfs0.entries().iter()
+            .filter(|e| pfs0.data(e).starts_with(NsoHeader::MAGIC))
+            .collect();
+        modules.sort_by_key(|e| (exefs_load_order(&e.name), e.name.clone()));
+        modules.into_iter()
+            .map(|e| (e.name.clone(), SwitchExecutable::read_nso(pfs0.data(e))))
+            .collect()
+    }
+}
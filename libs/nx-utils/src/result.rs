@@ -0,0 +1,272 @@
+use std::fmt;
+
+/// A Horizon result code, as returned by every SVC and IPC call. Bits 0..9
+/// hold the module and bits 9..22 the description; zero is success.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct NxResult(pub u32);
+
+impl NxResult {
+    pub const SUCCESS: NxResult = NxResult(0);
+
+    const MODULE_BITS: u32 = 9;
+    const DESCRIPTION_BITS: u32 = 13;
+
+    pub const fn new(module: u32, description: u32) -> NxResult {
+        let module = module & ((1 << NxResult::MODULE_BITS) - 1);
+        let description = description & ((1 << NxResult::DESCRIPTION_BITS) - 1);
+        NxResult(module | description << NxResult::MODULE_BITS)
+    }
+
+    pub const fn module(self) -> u32 {
+        self.0 & ((1 << NxResult::MODULE_BITS) - 1)
+    }
+
+    pub const fn description(self) -> u32 {
+        (self.0 >> NxResult::MODULE_BITS) & ((1 << NxResult::DESCRIPTION_BITS) - 1)
+    }
+
+    pub const fn is_success(self) -> bool {
+        self.0 == 0
+    }
+
+    pub const fn is_failure(self) -> bool {
+        !self.is_success()
+    }
+
+    pub fn into_result(self) -> Result<(), NxResult> {
+        if self.is_success() { Ok(()) } else { Err(self) }
+    }
+
+    pub fn module_name(self) -> Option<&'static str> {
+        MODULES.iter().find(|(module, _)| *module == self.module()).map(|(_, name)| *name)
+    }
+
+    pub fn description_name(self) -> Option<&'static str> {
+        DESCRIPTIONS.iter()
+            .find(|(module, description, _)| *module == self.module() && *description == self.description())
+            .map(|(_, _, name)| *name)
+    }
+}
+
+impl From<u32> for NxResult {
+    fn from(raw: u32) -> NxResult {
+        NxResult(raw)
+    }
+}
+
+/// The usual `2MMM-DDDD` form, followed by the names when they are known,
+/// e.g. `2001-0114 (Kernel: InvalidHandle)`.
+impl fmt::Display for NxResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:04}", 2000 + self.module(), self.description())?;
+        if self.is_success() {
+            return write!(f, " (Success)");
+        }
+        match (self.module_name(), self.description_name()) {
+            (Some(module), Some(description)) => write!(f, " ({}: {})", module, description),
+            (Some(module), None) => write!(f, " ({})", module),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl std::error::Error for NxResult {}
+
+pub const MODULES: &[(u32, &str)] = &[
+    (1, "Kernel"),
+    (2, "FS"),
+    (3, "OS"),
+    (4, "HTCS"),
+    (5, "NCM"),
+    (6, "DD"),
+    (8, "LR"),
+    (9, "LDR"),
+    (10, "SF"),
+    (11, "HIPC"),
+    (13, "DMNT"),
+    (15, "PM"),
+    (16, "NS"),
+    (18, "HTC"),
+    (20, "KVDB"),
+    (21, "SM"),
+    (22, "RO"),
+    (24, "SDMMC"),
+    (25, "OVLN"),
+    (26, "SPL"),
+    (100, "ETHC"),
+    (101, "I2C"),
+    (102, "GPIO"),
+    (103, "UART"),
+    (105, "Settings"),
+    (107, "WLAN"),
+    (110, "NIFM"),
+    (114, "VI"),
+    (115, "NFP"),
+    (116, "Time"),
+    (117, "FGM"),
+    (118, "OE"),
+    (120, "PCIe"),
+    (121, "Friends"),
+    (122, "BCAT"),
+    (123, "SSL"),
+    (124, "Account"),
+    (125, "News"),
+    (126, "Mii"),
+    (127, "NFC"),
+    (128, "AM"),
+    (129, "PlayReport"),
+    (133, "PCV"),
+    (134, "USBPD"),
+    (135, "BPC"),
+    (136, "PSM"),
+    (137, "NIM"),
+    (138, "PSC"),
+    (139, "TC"),
+    (140, "USB"),
+    (141, "NSD"),
+    (142, "PCTL"),
+    (143, "BTM"),
+    (144, "LA"),
+    (145, "ETicket"),
+    (147, "ERPT"),
+    (148, "APM"),
+    (150, "Profiler"),
+    (168, "UserlandAssert"),
+    (202, "HID"),
+    (203, "LDN"),
+    (205, "Irsensor"),
+    (206, "Capture"),
+    (345, "Libnx"),
+    (346, "HomebrewAbi"),
+    (347, "HomebrewLoader"),
+    (348, "LibnxNvidia"),
+    (349, "LibnxBinder"),
+];
+
+pub const DESCRIPTIONS: &[(u32, u32, &str)] = &[
+    (1, 7, "OutOfSessions"),
+    (1, 14, "InvalidArgument"),
+    (1, 33, "NotImplemented"),
+    (1, 54, "StopProcessingException"),
+    (1, 57, "NoSynchronizationObject"),
+    (1, 59, "TerminationRequested"),
+    (1, 70, "NoEvent"),
+    (1, 101, "InvalidSize"),
+    (1, 102, "InvalidAddress"),
+    (1, 103, "OutOfResource"),
+    (1, 104, "OutOfMemory"),
+    (1, 105, "OutOfHandles"),
+    (1, 106, "InvalidCurrentMemory"),
+    (1, 108, "InvalidNewMemoryPermission"),
+    (1, 110, "InvalidMemoryRegion"),
+    (1, 112, "InvalidPriority"),
+    (1, 113, "InvalidCoreId"),
+    (1, 114, "InvalidHandle"),
+    (1, 115, "InvalidPointer"),
+    (1, 116, "InvalidCombination"),
+    (1, 117, "TimedOut"),
+    (1, 118, "Cancelled"),
+    (1, 119, "OutOfRange"),
+    (1, 120, "InvalidEnumValue"),
+    (1, 121, "NotFound"),
+    (1, 122, "Busy"),
+    (1, 123, "SessionClosed"),
+    (1, 124, "NotHandled"),
+    (1, 125, "InvalidState"),
+    (1, 126, "ReservedUsed"),
+    (1, 127, "NotSupported"),
+    (1, 128, "Debug"),
+    (1, 129, "NoThread"),
+    (1, 130, "UnknownThread"),
+    (1, 131, "PortClosed"),
+    (1, 132, "LimitReached"),
+    (1, 133, "InvalidMemoryPool"),
+    (1, 258, "ReceiveListBroken"),
+    (1, 259, "OutOfAddressSpace"),
+    (1, 260, "MessageTooLarge"),
+    (1, 517, "InvalidProcessId"),
+    (1, 518, "InvalidThreadId"),
+    (1, 519, "InvalidId"),
+    (1, 520, "ProcessTerminated"),
+    (2, 1, "PathNotFound"),
+    (2, 2, "PathAlreadyExists"),
+    (2, 7, "TargetLocked"),
+    (2, 8, "DirectoryNotEmpty"),
+    (10, 202, "InvalidHeaderSize"),
+    (10, 211, "InvalidInHeader"),
+    (10, 221, "UnknownCommandId"),
+    (10, 232, "InvalidOutRawSize"),
+    (10, 235, "InvalidNumInObjects"),
+    (10, 236, "InvalidNumOutObjects"),
+    (10, 239, "InvalidInObject"),
+    (10, 261, "TargetNotFound"),
+    (10, 301, "OutOfDomainEntries"),
+    (11, 102, "OutOfSessionMemory"),
+    (11, 131, "OutOfSessions"),
+    (11, 141, "PointerBufferTooSmall"),
+    (11, 200, "OutOfDomains"),
+    (11, 301, "SessionClosed"),
+    (11, 402, "InvalidRequestSize"),
+    (11, 403, "UnknownCommandType"),
+    (11, 420, "InvalidCmifRequest"),
+    (11, 491, "TargetNotDomain"),
+    (11, 492, "DomainObjectNotFound"),
+    (21, 1, "OutOfProcesses"),
+    (21, 2, "InvalidClient"),
+    (21, 3, "OutOfSessions"),
+    (21, 4, "AlreadyRegistered"),
+    (21, 5, "OutOfServices"),
+    (21, 6, "InvalidServiceName"),
+    (21, 7, "NotRegistered"),
+    (21, 8, "NotAllowed"),
+    (21, 9, "TooLargeAccessControl"),
+    (345, 1, "BadReloc"),
+    (345, 2, "OutOfMemory"),
+    (345, 3, "AlreadyMapped"),
+    (345, 4, "BadGetInfo_Stack"),
+    (345, 5, "BadGetInfo_Heap"),
+    (345, 6, "BadQueryMemory"),
+    (345, 7, "AlreadyInitialized"),
+    (345, 8, "NotInitialized"),
+    (345, 9, "NotFound"),
+    (345, 10, "IoError"),
+    (345, 11, "BadInput"),
+    (345, 12, "BadReent"),
+    (345, 13, "BufferProducerError"),
+    (345, 14, "HandleTooEarly"),
+    (345, 15, "HeapAllocFailed"),
+    (345, 16, "TooManyOverrides"),
+    (345, 17, "ParcelError"),
+    (345, 18, "BadGfxInit"),
+    (345, 19, "BadGfxEventWait"),
+    (345, 20, "BadGfxQueueBuffer"),
+    (345, 21, "BadGfxDequeueBuffer"),
+    (345, 22, "AppletCmdidNotFound"),
+    (345, 23, "BadAppletReceiveMessage"),
+    (345, 24, "BadAppletNotifyRunning"),
+    (345, 25, "BadAppletGetCurrentFocusState"),
+    (345, 26, "BadAppletGetOperationMode"),
+    (345, 27, "BadAppletGetPerformanceMode"),
+    (345, 28, "BadUsbCommsRead"),
+    (345, 29, "BadUsbCommsWrite"),
+    (345, 30, "InitFail_SM"),
+    (345, 31, "InitFail_AM"),
+    (345, 32, "InitFail_HID"),
+    (345, 33, "InitFail_FS"),
+    (345, 34, "BadGetInfo_Rng"),
+    (345, 35, "JitUnavailable"),
+    (345, 36, "WeirdKernel"),
+    (345, 37, "IncompatSysVer"),
+    (345, 38, "InitFail_Time"),
+    (345, 39, "TooManyDevOpTabs"),
+    (345, 40, "DomainMessageUnknownType"),
+    (345, 41, "DomainMessageTooManyObjectIds"),
+    (345, 42, "AppletFailedToInitialize"),
+    (345, 43, "ApmFailedToInitialize"),
+    (345, 44, "NvinfoFailedToInitialize"),
+    (345, 45, "NvbufFailedToInitialize"),
+    (345, 46, "LibAppletBadExit"),
+    (345, 47, "InvalidCmifOutHeader"),
+    (345, 48, "ShouldNotHappen"),
+    (345, 49, "TimedOut"),
+];
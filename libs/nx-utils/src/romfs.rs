@@ -0,0 +1,592 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use byteorder::{ByteOrder, LittleEndian};
+
+/// Marks the end of a sibling or hash chain, or the absence of a child.
+pub const ROMFS_ENTRY_EMPTY: u32 = 0xFFFFFFFF;
+
+/// The level 3 RomFS header. All offsets are relative to the start of the image.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RomFsHeader {
+    pub header_size: u64,
+    pub dir_hash_table_offset: u64,
+    pub dir_hash_table_size: u64,
+    pub dir_meta_table_offset: u64,
+    pub dir_meta_table_size: u64,
+    pub file_hash_table_offset: u64,
+    pub file_hash_table_size: u64,
+    pub file_meta_table_offset: u64,
+    pub file_meta_table_size: u64,
+    pub file_data_offset: u64,
+}
+
+impl RomFsHeader {
+    pub const SIZE: usize = 0x50;
+
+    pub fn read(bytes: &[u8]) -> RomFsHeader {
+        assert!(bytes.len() >= RomFsHeader::SIZE, "RomFS image is smaller than its header");
+        let field = |i: usize| LittleEndian::read_u64(&bytes[i * 8..]);
+        let header = RomFsHeader {
+            header_size: field(0),
+            dir_hash_table_offset: field(1),
+            dir_hash_table_size: field(2),
+            dir_meta_table_offset: field(3),
+            dir_meta_table_size: field(4),
+            file_hash_table_offset: field(5),
+            file_hash_table_size: field(6),
+            file_meta_table_offset: field(7),
+            file_meta_table_size: field(8),
+            file_data_offset: field(9),
+        };
+        assert_eq!(header.header_size, RomFsHeader::SIZE as u64);
+        header
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        [
+            self.header_size,
+            self.dir_hash_table_offset,
+            self.dir_hash_table_size,
+            self.dir_meta_table_offset,
+            self.dir_meta_table_size,
+            self.file_hash_table_offset,
+            self.file_hash_table_size,
+            self.file_meta_table_offset,
+            self.file_meta_table_size,
+            self.file_data_offset,
+        ].iter().flat_map(|f| f.to_le_bytes()).collect()
+    }
+}
+
+/// Hash used to place directory and file entries into the hash tables.
+/// `parent` is the metadata offset of the containing directory.
+pub fn romfs_path_hash(parent: u32, name: &[u8]) -> u32 {
+    let mut hash = parent ^ 123456789;
+    for c in name {
+        hash = hash.rotate_right(5) ^ *c as u32;
+    }
+    hash
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RomFsEntryKind {
+    Directory,
+    File,
+}
+
+/// A directory or file inside a RomFS. `offset` is the position of its
+/// entry in the directory or file metadata table. Names that are not valid
+/// UTF-8 have the offending bytes replaced with U+FFFD.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RomFsEntry<'a> {
+    pub name: Cow<'a, str>,
+    pub kind: RomFsEntryKind,
+    pub offset: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RomFsMetadata {
+    pub kind: RomFsEntryKind,
+    /// Size of the file data; zero for directories.
+    pub len: u64,
+}
+
+impl RomFsMetadata {
+    pub fn is_dir(&self) -> bool {
+        self.kind == RomFsEntryKind::Directory
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.kind == RomFsEntryKind::File
+    }
+}
+
+/// Directory metadata entry as stored in the image. The name follows the
+/// fixed part and is padded to a multiple of four bytes.
+struct DirEntry<'a> {
+    parent: u32,
+    sibling: u32,
+    child_dir: u32,
+    child_file: u32,
+    hash_next: u32,
+    name: Cow<'a, str>,
+}
+
+/// File metadata entry as stored in the image.
+struct FileEntry<'a> {
+    parent: u32,
+    sibling: u32,
+    data_offset: u64,
+    data_size: u64,
+    hash_next: u32,
+    name: Cow<'a, str>,
+}
+
+impl DirEntry<'_> {
+    /// Size of the fixed part that precedes the name.
+    const MIN_SIZE: usize = 0x18;
+}
+
+impl FileEntry<'_> {
+    const MIN_SIZE: usize = 0x20;
+}
+
+/// The first entry offset in the bucket for `name`, or None if the hash
+/// table has no buckets.
+fn hash_bucket(table: &[u8], parent: u32, name: &str) -> Option<u32> {
+    let buckets = table.len() / 4;
+    if buckets == 0 {
+        return None;
+    }
+    let bucket = romfs_path_hash(parent, name.as_bytes()) as usize % buckets;
+    Some(LittleEndian::read_u32(&table[bucket * 4..]))
+}
+
+/// Read-only view of a RomFS image, either a standalone `.romfs` file or the
+/// one embedded in an NRO asset section. Nothing is copied: entries are
+/// decoded from the borrowed bytes on each lookup, and file contents are
+/// handed out as sub-slices.
+pub struct RomFs<'a> {
+    bytes: &'a [u8],
+    header: RomFsHeader,
+}
+
+impl<'a> RomFs<'a> {
+    pub fn new(bytes: &'a [u8]) -> RomFs<'a> {
+        RomFs {
+            header: RomFsHeader::read(bytes),
+            bytes,
+        }
+    }
+
+    pub fn header(&self) -> &RomFsHeader {
+        &self.header
+    }
+
+    /// Lists the direct children of a directory, subdirectories first.
+    pub fn read_dir(&self, path: &str) -> Option<ReadDir<'a, '_>> {
+        let (kind, offset) = self.lookup(path)?;
+        if kind != RomFsEntryKind::Directory {
+            return None;
+        }
+        let dir = self.dir_entry(offset);
+        Some(ReadDir {
+            romfs: self,
+            next_dir: dir.child_dir,
+            next_file: dir.child_file,
+        })
+    }
+
+    /// Returns the contents of the file at `path`.
+    pub fn open(&self, path: &str) -> Option<&'a [u8]> {
+        let (kind, offset) = self.lookup(path)?;
+        if kind != RomFsEntryKind::File {
+            return None;
+        }
+        Some(self.file_data(offset))
+    }
+
+    pub fn metadata(&self, path: &str) -> Option<RomFsMetadata> {
+        let (kind, offset) = self.lookup(path)?;
+        let len = match kind {
+            RomFsEntryKind::Directory => 0,
+            RomFsEntryKind::File => self.file_entry(offset).data_size,
+        };
+        Some(RomFsMetadata { kind, len })
+    }
+
+    /// Iterates over every entry below the root, depth first, yielding the
+    /// full path (starting with `/`) alongside each entry.
+    pub fn walk(&self) -> Walk<'a, '_> {
+        Walk {
+            romfs: self,
+            stack: vec![(String::new(), self.read_dir("/").unwrap())],
+        }
+    }
+
+    /// Returns the contents of the file whose metadata entry sits at `offset`.
+    pub fn file_data(&self, offset: u32) -> &'a [u8] {
+        let file = self.file_entry(offset);
+        let start = (self.header.file_data_offset + file.data_offset) as usize;
+        &self.bytes[start..][..file.data_size as usize]
+    }
+
+    /// Resolves a `/`-separated path, following the hash chains for each
+    /// component. Empty components and a leading `romfs:` are ignored.
+    fn lookup(&self, path: &str) -> Option<(RomFsEntryKind, u32)> {
+        let path = path.strip_prefix("romfs:").unwrap_or(path);
+        let mut components = path.split('/').filter(|c| !c.is_empty()).peekable();
+        let mut dir = 0u32; // the root directory is always the first entry
+
+        while let Some(name) = components.next() {
+            if let Some(child) = self.find_dir(dir, name) {
+                dir = child;
+            } else if components.peek().is_none() {
+                return self.find_file(dir, name).map(|file| (RomFsEntryKind::File, file));
+            } else {
+                return None;
+            }
+        }
+        Some((RomFsEntryKind::Directory, dir))
+    }
+
+    fn find_dir(&self, parent: u32, name: &str) -> Option<u32> {
+        let table = self.table(self.header.dir_hash_table_offset, self.header.dir_hash_table_size);
+        let mut offset = hash_bucket(table, parent, name)?;
+        // A chain visits each entry at most once; a longer one is a cycle.
+        for _ in 0..=self.header.dir_meta_table_size as usize / DirEntry::MIN_SIZE {
+            if offset == ROMFS_ENTRY_EMPTY {
+                return None;
+            }
+            let entry = self.dir_entry(offset);
+            if entry.parent == parent && entry.name == name {
+                return Some(offset);
+            }
+            offset = entry.hash_next;
+        }
+        None
+    }
+
+    fn find_file(&self, parent: u32, name: &str) -> Option<u32> {
+        let table = self.table(self.header.file_hash_table_offset, self.header.file_hash_table_size);
+        let mut offset = hash_bucket(table, parent, name)?;
+        for _ in 0..=self.header.file_meta_table_size as usize / FileEntry::MIN_SIZE {
+            if offset == ROMFS_ENTRY_EMPTY {
+                return None;
+            }
+            let entry = self.file_entry(offset);
+            if entry.parent == parent && entry.name == name {
+                return Some(offset);
+            }
+            offset = entry.hash_next;
+        }
+        None
+    }
+
+    fn table(&self, offset: u64, size: u64) -> &'a [u8] {
+        &self.bytes[offset as usize..][..size as usize]
+    }
+
+    fn dir_entry(&self, offset: u32) -> DirEntry<'a> {
+        let meta = self.table(self.header.dir_meta_table_offset, self.header.dir_meta_table_size);
+        let raw = &meta[offset as usize..];
+        let name_size = LittleEndian::read_u32(&raw[0x14..]) as usize;
+        DirEntry {
+            parent: LittleEndian::read_u32(&raw[0x00..]),
+            sibling: LittleEndian::read_u32(&raw[0x04..]),
+            child_dir: LittleEndian::read_u32(&raw[0x08..]),
+            child_file: LittleEndian::read_u32(&raw[0x0C..]),
+            hash_next: LittleEndian::read_u32(&raw[0x10..]),
+            name: String::from_utf8_lossy(&raw[0x18..][..name_size]),
+        }
+    }
+
+    fn file_entry(&self, offset: u32) -> FileEntry<'a> {
+        let meta = self.table(self.header.file_meta_table_offset, self.header.file_meta_table_size);
+        let raw = &meta[offset as usize..];
+        let name_size = LittleEndian::read_u32(&raw[0x1C..]) as usize;
+        FileEntry {
+            parent: LittleEndian::read_u32(&raw[0x00..]),
+            sibling: LittleEndian::read_u32(&raw[0x04..]),
+            data_offset: LittleEndian::read_u64(&raw[0x08..]),
+            data_size: LittleEndian::read_u64(&raw[0x10..]),
+            hash_next: LittleEndian::read_u32(&raw[0x18..]),
+            name: String::from_utf8_lossy(&raw[0x20..][..name_size]),
+        }
+    }
+}
+
+/// Iterator over the children of one directory, see [`RomFs::read_dir`].
+pub struct ReadDir<'a, 'r> {
+    romfs: &'r RomFs<'a>,
+    next_dir: u32,
+    next_file: u32,
+}
+
+impl<'a, 'r> Iterator for ReadDir<'a, 'r> {
+    type Item = RomFsEntry<'a>;
+
+    fn next(&mut self) -> Option<RomFsEntry<'a>> {
+        if self.next_dir != ROMFS_ENTRY_EMPTY {
+            let offset = self.next_dir;
+            let dir = self.romfs.dir_entry(offset);
+            self.next_dir = dir.sibling;
+            return Some(RomFsEntry { name: dir.name, kind: RomFsEntryKind::Directory, offset });
+        }
+        if self.next_file != ROMFS_ENTRY_EMPTY {
+            let offset = self.next_file;
+            let file = self.romfs.file_entry(offset);
+            self.next_file = file.sibling;
+            return Some(RomFsEntry { name: file.name, kind: RomFsEntryKind::File, offset });
+        }
+        None
+    }
+}
+
+/// Depth-first iterator over a whole RomFS, see [`RomFs::walk`].
+pub struct Walk<'a, 'r> {
+    romfs: &'r RomFs<'a>,
+    stack: Vec<(String, ReadDir<'a, 'r>)>,
+}
+
+impl<'a, 'r> Iterator for Walk<'a, 'r> {
+    type Item = (String, RomFsEntry<'a>);
+
+    fn next(&mut self) -> Option<(String, RomFsEntry<'a>)> {
+        loop {
+            let (prefix, dir) = self.stack.last_mut()?;
+            let Some(entry) = dir.next() else {
+                self.stack.pop();
+                continue;
+            };
+            let path = format!("{}/{}", prefix, entry.name);
+            if entry.kind == RomFsEntryKind::Directory {
+                let dir = self.romfs.dir_entry(entry.offset);
+                self.stack.push((path.clone(), ReadDir {
+                    romfs: self.romfs,
+                    next_dir: dir.child_dir,
+                    next_file: dir.child_file,
+                }));
+            }
+            return Some((path, entry));
+        }
+    }
+}
+
+/// Number of hash buckets Nintendo's tooling allocates for `count` entries.
+fn romfs_hash_table_count(count: usize) -> usize {
+    if count < 3 {
+        return 3;
+    }
+    if count < 19 {
+        return count | 1;
+    }
+    let mut count = count;
+    while [2, 3, 5, 7, 11, 13, 17].iter().any(|p| count.is_multiple_of(*p)) {
+        count += 1;
+    }
+    count
+}
+
+fn align_up(value: usize, align: usize) -> usize {
+    (value + align - 1) & !(align - 1)
+}
+
+#[derive(Default)]
+struct BuilderDir {
+    dirs: BTreeMap<String, BuilderDir>,
+    files: BTreeMap<String, Vec<u8>>,
+}
+
+/// Builds a RomFS image from an in-memory tree, typically populated from a
+/// host directory with [`RomFsBuilder::from_dir`]. Children are sorted by
+/// name, so the same input always yields the same image.
+///
+/// The layout follows Nintendo's: the header padded to 0x200, the file data
+/// (each file aligned to 0x10), then the directory hash and metadata tables
+/// followed by the file hash and metadata tables.
+#[derive(Default)]
+pub struct RomFsBuilder {
+    root: BuilderDir,
+}
+
+impl RomFsBuilder {
+    pub const FILE_DATA_OFFSET: usize = 0x200;
+
+    pub fn new() -> RomFsBuilder {
+        RomFsBuilder::default()
+    }
+
+    /// Recursively adds every directory and file below `path`. Symbolic links
+    /// are followed and packed as the directory or file they point to; a link
+    /// to a directory that contains it becomes an empty directory.
+    pub fn from_dir(path: &Path) -> io::Result<RomFsBuilder> {
+        let mut builder = RomFsBuilder::new();
+        add_host_dir(&mut builder.root, path, &mut Vec::new())?;
+        Ok(builder)
+    }
+
+    /// Adds an empty directory, creating missing parents.
+    pub fn add_dir(&mut self, path: &str) {
+        self.dir_mut(path);
+    }
+
+    /// Adds or replaces a file, creating missing parent directories.
+    pub fn add_file(&mut self, path: &str, data: Vec<u8>) {
+        let (parent, name) = path.trim_end_matches('/').rsplit_once('/').unwrap_or(("", path));
+        assert!(!name.is_empty(), "RomFS file path {:?} has no file name", path);
+        self.dir_mut(parent).files.insert(name.to_string(), data);
+    }
+
+    fn dir_mut(&mut self, path: &str) -> &mut BuilderDir {
+        let path = path.strip_prefix("romfs:").unwrap_or(path);
+        let mut dir = &mut self.root;
+        for name in path.split('/').filter(|c| !c.is_empty()) {
+            dir = dir.dirs.entry(name.to_string()).or_default();
+        }
+        dir
+    }
+
+    pub fn build(&self) -> Vec<u8> {
+        // Lay out directories breadth first so that siblings are adjacent.
+        // Each entry is (parent index, name, directory).
+        let mut dirs: Vec<(usize, &str, &BuilderDir)> = vec![(0, "", &self.root)];
+        let mut i = 0;
+        while i < dirs.len() {
+            let dir = dirs[i].2;
+            for (name, child) in &dir.dirs {
+                dirs.push((i, name, child));
+            }
+            i += 1;
+        }
+
+        let mut dir_offsets = Vec::with_capacity(dirs.len());
+        let mut dir_meta_size = 0;
+        for (_, name, _) in &dirs {
+            dir_offsets.push(dir_meta_size as u32);
+            dir_meta_size += 0x18 + align_up(name.len(), 4);
+        }
+
+        // Files are grouped by directory, in directory order.
+        // Each entry is (parent index, name, contents).
+        let mut files: Vec<(usize, &str, &[u8])> = Vec::new();
+        for (index, (_, _, dir)) in dirs.iter().enumerate() {
+            for (name, data) in &dir.files {
+                files.push((index, name, data));
+            }
+        }
+
+        let mut file_offsets = Vec::with_capacity(files.len());
+        let mut file_data_offsets = Vec::with_capacity(files.len());
+        let mut file_meta_size = 0;
+        let mut file_data_size = 0;
+        for (_, name, data) in &files {
+            file_offsets.push(file_meta_size as u32);
+            file_meta_size += 0x20 + align_up(name.len(), 4);
+            file_data_size = align_up(file_data_size, 0x10);
+            file_data_offsets.push(file_data_size as u64);
+            file_data_size += data.len();
+        }
+
+        let dir_buckets = romfs_hash_table_count(dirs.len());
+        let file_buckets = romfs_hash_table_count(files.len());
+        let mut dir_hash_table = vec![ROMFS_ENTRY_EMPTY; dir_buckets];
+        let mut file_hash_table = vec![ROMFS_ENTRY_EMPTY; file_buckets];
+
+        // Children of a directory are contiguous in both lists, so the first
+        // one seen for each parent is the head of its sibling chain.
+        let mut first_child = vec![ROMFS_ENTRY_EMPTY; dirs.len()];
+        for (index, (parent, _, _)) in dirs.iter().enumerate().skip(1).rev() {
+            first_child[*parent] = dir_offsets[index];
+        }
+        let mut first_file = vec![ROMFS_ENTRY_EMPTY; dirs.len()];
+        for (index, (parent, _, _)) in files.iter().enumerate().rev() {
+            first_file[*parent] = file_offsets[index];
+        }
+
+        let mut dir_meta = Vec::with_capacity(dir_meta_size);
+        for (index, (parent, name, _)) in dirs.iter().enumerate() {
+            let parent_offset = dir_offsets[*parent];
+            let next_sibling = dirs.get(index + 1).filter(|d| index != 0 && d.0 == *parent);
+            let bucket = romfs_path_hash(parent_offset, name.as_bytes()) as usize % dir_buckets;
+
+            push_u32(&mut dir_meta, parent_offset);
+            push_u32(&mut dir_meta, next_sibling.map_or(ROMFS_ENTRY_EMPTY, |_| dir_offsets[index + 1]));
+            push_u32(&mut dir_meta, first_child[index]);
+            push_u32(&mut dir_meta, first_file[index]);
+            push_u32(&mut dir_meta, dir_hash_table[bucket]);
+            push_u32(&mut dir_meta, name.len() as u32);
+            push_name(&mut dir_meta, name);
+            dir_hash_table[bucket] = dir_offsets[index];
+        }
+
+        let mut file_meta = Vec::with_capacity(file_meta_size);
+        for (index, (parent, name, data)) in files.iter().enumerate() {
+            let parent_offset = dir_offsets[*parent];
+            let next_sibling = files.get(index + 1).filter(|f| f.0 == *parent);
+            let bucket = romfs_path_hash(parent_offset, name.as_bytes()) as usize % file_buckets;
+
+            push_u32(&mut file_meta, parent_offset);
+            push_u32(&mut file_meta, next_sibling.map_or(ROMFS_ENTRY_EMPTY, |_| file_offsets[index + 1]));
+            file_meta.extend_from_slice(&file_data_offsets[index].to_le_bytes());
+            file_meta.extend_from_slice(&(data.len() as u64).to_le_bytes());
+            push_u32(&mut file_meta, file_hash_table[bucket]);
+            push_u32(&mut file_meta, name.len() as u32);
+            push_name(&mut file_meta, name);
+            file_hash_table[bucket] = file_offsets[index];
+        }
+
+        let dir_hash_table_offset = align_up(RomFsBuilder::FILE_DATA_OFFSET + file_data_size, 4);
+        let dir_meta_table_offset = dir_hash_table_offset + dir_buckets * 4;
+        let file_hash_table_offset = dir_meta_table_offset + dir_meta.len();
+        let file_meta_table_offset = file_hash_table_offset + file_buckets * 4;
+        let header = RomFsHeader {
+            header_size: RomFsHeader::SIZE as u64,
+            dir_hash_table_offset: dir_hash_table_offset as u64,
+            dir_hash_table_size: (dir_buckets * 4) as u64,
+            dir_meta_table_offset: dir_meta_table_offset as u64,
+            dir_meta_table_size: dir_meta.len() as u64,
+            file_hash_table_offset: file_hash_table_offset as u64,
+            file_hash_table_size: (file_buckets * 4) as u64,
+            file_meta_table_offset: file_meta_table_offset as u64,
+            file_meta_table_size: file_meta.len() as u64,
+            file_data_offset: RomFsBuilder::FILE_DATA_OFFSET as u64,
+        };
+
+        let mut image = header.to_bytes();
+        image.resize(RomFsBuilder::FILE_DATA_OFFSET, 0);
+        for (index, (_, _, data)) in files.iter().enumerate() {
+            image.resize(RomFsBuilder::FILE_DATA_OFFSET + file_data_offsets[index] as usize, 0);
+            image.extend_from_slice(data);
+        }
+        image.resize(dir_hash_table_offset, 0);
+        dir_hash_table.iter().for_each(|b| push_u32(&mut image, *b));
+        image.extend_from_slice(&dir_meta);
+        file_hash_table.iter().for_each(|b| push_u32(&mut image, *b));
+        image.extend_from_slice(&file_meta);
+        image
+    }
+}
+
+/// `ancestors` holds the canonical paths of the directories being added
+/// above `path`, so that a symlink back to one of them is skipped rather
+/// than followed forever.
+fn add_host_dir(dir: &mut BuilderDir, path: &Path, ancestors: &mut Vec<PathBuf>) -> io::Result<()> {
+    let canonical = fs::canonicalize(path)?;
+    if ancestors.contains(&canonical) {
+        return Ok(());
+    }
+    ancestors.push(canonical);
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let name = entry.file_name().into_string().map_err(|name| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("RomFS name {:?} is not valid UTF-8", name))
+        })?;
+        // `file_type` does not follow symlinks; resolve them to what they point at.
+        let mut file_type = entry.file_type()?;
+        if file_type.is_symlink() {
+            file_type = fs::metadata(entry.path())?.file_type();
+        }
+        if file_type.is_dir() {
+            add_host_dir(dir.dirs.entry(name).or_default(), &entry.path(), ancestors)?;
+        } else {
+            dir.files.insert(name, fs::read(entry.path())?);
+        }
+    }
+    ancestors.pop();
+    Ok(())
+}
+
+fn push_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+/// Names are not NUL-terminated, only padded to the next multiple of four.
+fn push_name(out: &mut Vec<u8>, name: &str) {
+    out.extend_from_slice(name.as_bytes());
+    out.resize(out.len() + align_up(name.len(), 4) - name.len(), 0);
+}
@@ -4,7 +4,30 @@ pub fn main() {
 
 #[cfg(test)]
 mod tests {
-    use nx_utils::SwitchExecutable;
+    use a2ir::aarch64_reader::{decode, Op};
+    use a2ir::builder::FunctionBuilder;
+    use a2ir::ir::{BinaryOp, Block, BlockCall, CastOp, Flag, FloatCC, Function, InstKind, IntCC, MemoryModel, MemoryOrder, Module, Reg, ScalarType, TrapKind, Type, Value,
+                   ValueDef};
+    use a2ir::lifter::{lift_function, FlagsMode};
+    use a2ir::verifier::{dominators, verify};
+    use a2ir::convertProgram;
+    use nx_utils::elf::{ElfFile, SHN_ABS, SHT_SYMTAB};
+    use nx_utils::executable::{Executable, ExecutableFormat, Permissions};
+    use nx_utils::ipc::{BufferDescriptor, BufferMode, CmifCommandType, CmifInHeader, CmifRequest, CmifResponse, DomainRequest,
+                        DomainRequestType, HipcHeader, HipcMessage, IpcProtocol, IpcRequest, RecvListEntry,
+                        ServiceProtocols, StaticDescriptor, TipcRequest, TipcResponse};
+    use nx_utils::ips::{IpsData, IpsFormat, IpsPatch};
+    use nx_utils::kip::KipHeader;
+    use nx_utils::loader::{ProcessImage, R_AARCH64_ABS64, R_AARCH64_GLOB_DAT, R_AARCH64_JUMP_SLOT, R_AARCH64_RELATIVE};
+    use nx_utils::{blz, lz4};
+    use nx_utils::nacp::{Language, Nacp};
+    use nx_utils::npdm::{AddressSpaceType, KernelCapability, Npdm};
+    use nx_utils::pfs0::Pfs0;
+    use nx_utils::result::NxResult;
+    use nx_utils::romfs::{RomFs, RomFsBuilder};
+    use nx_utils::sha256::sha256;
+    use nx_utils::{NroHeader, NroSegment, SwitchExecutable};
+    use std::io::Cursor;
     use super::*;
 
     #[test]
@@ -13,4 +36,1227 @@ mod tests {
         let nro = SwitchExecutable::read_nro(bytes);
         println!("e {}", 1);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn read_nro_assets() {
+        let bytes = include_bytes!("../test/hello-world.nro").to_vec();
+        let nro = SwitchExecutable::read_nro(bytes);
+        assert_eq!(nro.icon().unwrap().len(), 6285);
+        assert_eq!(&nro.icon().unwrap()[..2], &[0xFF, 0xD8]); // JPEG SOI
+        assert_eq!(nro.nacp().unwrap().len(), 0x4000);
+        assert!(nro.romfs().is_none());
+        assert!(nro.bss().iter().all(|b| *b == 0));
+
+        // A truncated file keeps the assets that still fit.
+        let bytes = include_bytes!("../test/hello-world.nro");
+        let truncated = SwitchExecutable::read_nro(&bytes[..bytes.len() - 0x100]);
+        assert_eq!(truncated.icon().unwrap().len(), 6285);
+        assert!(truncated.nacp().is_none());
+        let image_size = SwitchExecutable::read_nro(&bytes[..]).nro_header().unwrap().size as usize;
+        let cut_in_aset = SwitchExecutable::read_nro(&bytes[..image_size + 0x10]);
+        assert!(cut_in_aset.icon().is_none());
+    }
+
+    #[test]
+    fn nacp_round_trip() {
+        let bytes = include_bytes!("../test/hello-world.nro").to_vec();
+        let nro = SwitchExecutable::read_nro(bytes);
+        let mut nacp = Nacp::read(nro.nacp().unwrap());
+        assert_eq!(nacp.title(Language::AmericanEnglish).name, "hello-world");
+        assert_eq!(nacp.title(Language::AmericanEnglish).publisher, "Unspecified Author");
+        assert_eq!(nacp.display_version, "1.0.0");
+        assert_eq!(nacp.to_bytes(), nro.nacp().unwrap());
+
+        nacp.display_version = "1.0.1".to_string();
+        assert_eq!(Nacp::read(&nacp.to_bytes()).display_version, "1.0.1");
+    }
+
+    #[test]
+    fn romfs_build_and_read() {
+        let mut builder = RomFsBuilder::new();
+        builder.add_file("/readme.txt", b"hello".to_vec());
+        builder.add_file("/data/levels/1.bin", vec![1; 0x21]);
+        builder.add_file("/data/levels/2.bin", vec![2; 3]);
+        builder.add_dir("/empty");
+        let image = builder.build();
+        let romfs = RomFs::new(&image);
+
+        assert_eq!(romfs.open("romfs:/readme.txt").unwrap(), b"hello");
+        assert_eq!(romfs.open("/data/levels/2.bin").unwrap(), &[2; 3]);
+        assert_eq!(romfs.metadata("/data/levels/1.bin").unwrap().len, 0x21);
+        assert!(romfs.metadata("/empty").unwrap().is_dir());
+        assert!(romfs.open("/data/levels/3.bin").is_none());
+
+        let names: Vec<_> = romfs.read_dir("/data/levels").unwrap().map(|e| e.name).collect();
+        assert_eq!(names, ["1.bin", "2.bin"]);
+        let paths: Vec<_> = romfs.walk().map(|(path, _)| path).collect();
+        assert_eq!(paths, ["/data", "/data/levels", "/data/levels/1.bin", "/data/levels/2.bin", "/empty", "/readme.txt"]);
+        assert_eq!(image, builder.build());
+    }
+
+    #[test]
+    fn romfs_from_host_dir() {
+        let root = std::env::temp_dir().join(format!("romfs-from-dir-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("data/nested")).unwrap();
+        std::fs::write(root.join("data/nested/level.bin"), [7; 0x13]).unwrap();
+        std::fs::write(root.join("empty.txt"), b"").unwrap();
+        std::fs::write(root.join("データ.txt"), b"jp").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(root.join("data"), root.join("link")).unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&root, root.join("data/loop")).unwrap();
+
+        let image = RomFsBuilder::from_dir(&root).unwrap().build();
+        std::fs::remove_dir_all(&root).unwrap();
+        let romfs = RomFs::new(&image);
+
+        assert_eq!(romfs.open("/data/nested/level.bin").unwrap(), &[7; 0x13]);
+        assert_eq!(romfs.open("/empty.txt").unwrap(), b"");
+        assert!(romfs.metadata("/empty.txt").unwrap().is_file());
+        assert_eq!(romfs.open("/データ.txt").unwrap(), b"jp");
+        #[cfg(unix)]
+        assert_eq!(romfs.open("/link/nested/level.bin").unwrap(), &[7; 0x13]);
+        #[cfg(unix)]
+        assert!(romfs.read_dir("/data/loop").unwrap().next().is_none());
+    }
+
+    #[test]
+    fn romfs_non_utf8_name() {
+        let mut builder = RomFsBuilder::new();
+        builder.add_file("/a.bin", b"data".to_vec());
+        let mut image = builder.build();
+        let header = RomFs::new(&image).header().clone();
+        let name = header.file_meta_table_offset as usize + 0x20;
+        assert_eq!(&image[name..][..5], b"a.bin");
+        image[name] = 0xFF;
+
+        let romfs = RomFs::new(&image);
+        let entry = romfs.read_dir("/").unwrap().next().unwrap();
+        assert_eq!(entry.name, "\u{FFFD}.bin");
+        assert_eq!(romfs.file_data(entry.offset), b"data");
+
+        // An empty hash table or a cyclic chain finds nothing.
+        let mut image = builder.build();
+        image[0x30..0x38].fill(0); // file_hash_table_size
+        assert!(RomFs::new(&image).open("/a.bin").is_none());
+        let mut image = builder.build();
+        let file = header.file_meta_table_offset as usize;
+        image[file..file + 4].copy_from_slice(&5u32.to_le_bytes()); // parent
+        image[file + 0x18..file + 0x1C].copy_from_slice(&0u32.to_le_bytes()); // hash_next
+        assert!(RomFs::new(&image).open("/a.bin").is_none());
+    }
+
+    #[test]
+    fn lz4_overlapping_match() {
+        // "abc", then a 15-byte match at distance 3, then the literals "end".
+        let block = [0x3B, b'a', b'b', b'c', 0x03, 0x00, 0x30, b'e', b'n', b'd'];
+        let out = lz4::decompress(&block, 21).unwrap();
+        assert_eq!(out, b"abcabcabcabcabcabcend");
+        assert!(lz4::decompress(&block, 20).is_none());
+        assert!(lz4::decompress(&block[..5], 21).is_none());
+    }
+
+    #[test]
+    fn sha256_known_digests() {
+        let hex = |d: [u8; 32]| d.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+        assert_eq!(hex(sha256(b"")), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+        assert_eq!(hex(sha256(b"abc")), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        assert_eq!(
+            hex(sha256(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq")),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+    }
+
+    #[test]
+    fn nso_write_and_read_back() {
+        let bytes = include_bytes!("../test/hello-world.nro").to_vec();
+        let nro = SwitchExecutable::read_nro(bytes);
+
+        for compress in [false, true] {
+            let nso_bytes = nro.write_nso(compress);
+            let nso = SwitchExecutable::read_nso(nso_bytes.clone());
+            assert_eq!(nso.text(), nro.text());
+            assert_eq!(nso.ro(), nro.ro());
+            assert_eq!(nso.data(), nro.data());
+            assert_eq!(nso.bss().len(), nro.bss().len());
+            assert!(nso.verify_nso().unwrap().is_valid());
+            assert_eq!(nso.write_nso(compress), nso_bytes);
+        }
+
+        let compressed = nro.write_nso(true);
+        assert!(compressed.len() < nro.write_nso(false).len());
+        let mut tampered = compressed.clone();
+        let last = tampered.len() - 1;
+        tampered[last] ^= 0xFF;
+        let report = nx_utils::nso::verify_nso(&tampered);
+        assert!(!report.is_loadable());
+    }
+
+    #[test]
+    fn lz4_round_trip() {
+        let input: Vec<u8> = (0..5000u32).map(|i| (i * i / 7 % 251) as u8).chain([0; 300]).collect();
+        let compressed = lz4::compress(&input);
+        assert!(compressed.len() < input.len());
+        assert_eq!(lz4::decompress(&compressed, input.len()).unwrap(), input);
+        assert_eq!(lz4::decompress(&lz4::compress(b""), 0).unwrap(), b"");
+    }
+
+    #[test]
+    fn nro_round_trip() {
+        let bytes = include_bytes!("../test/hello-world.nro").to_vec();
+        let mut nro = SwitchExecutable::read_nro(bytes.clone());
+        assert_eq!(nro.write_nro().unwrap(), bytes);
+
+        let romfs = RomFsBuilder::new().build();
+        nro.set_romfs(Some(romfs.clone()));
+        let repacked = SwitchExecutable::read_nro(nro.write_nro().unwrap());
+        assert_eq!(repacked.romfs().unwrap(), romfs);
+        assert_eq!(repacked.icon(), nro.icon());
+        assert_eq!(repacked.nacp(), nro.nacp());
+        assert_eq!(repacked.text(), nro.text());
+    }
+
+    #[test]
+    fn nso_to_nro() {
+        // Only an NRO has a header to write back; elsewhere .text[..0x80] is code.
+        let nro = SwitchExecutable::read_nro(include_bytes!("../test/hello-world.nro").as_slice());
+        let nso = SwitchExecutable::read_nso(nro.write_nso(true));
+        assert!(nso.write_nro().is_none());
+
+        // The ASET version is written back as read.
+        let mut bytes = include_bytes!("../test/hello-world.nro").to_vec();
+        let aset = nro.nro_header().unwrap().size as usize;
+        bytes[aset + 4..aset + 8].copy_from_slice(&1u32.to_le_bytes());
+        assert_eq!(SwitchExecutable::read_nro(bytes.as_slice()).write_nro().unwrap(), bytes);
+    }
+
+    #[test]
+    fn kip_with_blz_text() {
+        // "abcdef" * 8: six literals, then back references of 6, 12, 18 and 6
+        // bytes at distances equal to their sizes, read from the end of the
+        // stream backwards.
+        let mut text = vec![0x03, 0x30, 0x0F, 0xF0, 0xC0, 0x09, 0x90, 0x03, 0x30, b'a', b'b', b'c', b'd', b'e', b'f', 0x03];
+        text.extend_from_slice(&28u32.to_le_bytes()); // compressed size
+        text.extend_from_slice(&12u32.to_le_bytes()); // stream start, from the end
+        text.extend_from_slice(&20u32.to_le_bytes()); // added by decompression
+        assert_eq!(blz::decompress(&text).unwrap(), b"abcdef".repeat(8));
+
+        // An overlapping reference copies front to back, reading stale bytes.
+        let mut overlap = vec![0x00, 0xF0, b'a', b'b', b'c', 0x10];
+        overlap.extend_from_slice(&18u32.to_le_bytes());
+        overlap.extend_from_slice(&12u32.to_le_bytes());
+        overlap.extend_from_slice(&3u32.to_le_bytes());
+        let decompressed = blz::decompress(&overlap).unwrap();
+        assert_eq!(decompressed[..3], [b'b', b'c', 0x10]);
+        assert_eq!(decompressed[15..], *b"abcabc");
+        let mut huge = text.clone();
+        huge[24..].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(blz::decompress(&huge).is_none());
+
+        let ro = b"rodata".to_vec();
+        let mut kip = Vec::new();
+        kip.extend_from_slice(b"KIP1");
+        kip.extend_from_slice(b"TestProc\0\0\0\0");
+        kip.extend_from_slice(&0x0100000000000042u64.to_le_bytes());
+        kip.extend_from_slice(&1u32.to_le_bytes());
+        kip.extend_from_slice(&[44, 3, 0, KipHeader::TEXT_COMPRESS | KipHeader::IS_64BIT]);
+        let segments: [[u32; 4]; 6] = [
+            [0, 48, text.len() as u32, 0],
+            [0x1000, 6, ro.len() as u32, 0x4000],
+            [0x2000, 0, 0, 0],
+            [0x2000, 0x1000, 0, 0],
+            [0; 4],
+            [0; 4],
+        ];
+        for field in segments.iter().flatten() {
+            kip.extend_from_slice(&field.to_le_bytes());
+        }
+        kip.extend_from_slice(&0x0000_0007u32.to_le_bytes());
+        kip.resize(KipHeader::SIZE, 0xFF);
+        kip.extend_from_slice(&text);
+        kip.extend_from_slice(&ro);
+
+        let exe = SwitchExecutable::read_kip(kip);
+        let header = exe.kip_header().unwrap();
+        assert_eq!(header.name, "TestProc");
+        assert_eq!(header.title_id, 0x0100000000000042);
+        assert_eq!(header.main_thread_priority, 44);
+        assert_eq!(header.stack_size(), 0x4000);
+        assert_eq!(header.kernel_capabilities().collect::<Vec<_>>(), [7]);
+        assert_eq!(exe.text(), b"abcdef".repeat(8));
+        assert_eq!(exe.ro(), b"rodata");
+        assert_eq!(exe.bss().len(), 0x1000);
+    }
+
+    #[test]
+    fn exefs_modules_in_load_order() {
+        let nro = SwitchExecutable::read_nro(include_bytes!("../test/hello-world.nro").to_vec());
+        let nso = nro.write_nso(true);
+        let files: [(&str, &[u8]); 3] = [("main.npdm", b"META"), ("sdk", &nso), ("main", &nso)];
+
+        let mut strings = Vec::new();
+        let mut entries = Vec::new();
+        let mut data = Vec::new();
+        for (name, contents) in files {
+            entries.extend_from_slice(&(data.len() as u64).to_le_bytes());
+            entries.extend_from_slice(&(contents.len() as u64).to_le_bytes());
+            entries.extend_from_slice(&(strings.len() as u32).to_le_bytes());
+            entries.extend_from_slice(&0u32.to_le_bytes());
+            strings.extend_from_slice(name.as_bytes());
+            strings.push(0);
+            data.extend_from_slice(contents);
+        }
+        let mut exefs = b"PFS0".to_vec();
+        exefs.extend_from_slice(&(files.len() as u32).to_le_bytes());
+        exefs.extend_from_slice(&(strings.len() as u32).to_le_bytes());
+        exefs.extend_from_slice(&0u32.to_le_bytes());
+        exefs.extend(entries.into_iter().chain(strings).chain(data));
+
+        let pfs0 = Pfs0::new(&exefs);
+        assert_eq!(pfs0.entries().len(), 3);
+        assert_eq!(pfs0.get("main.npdm").unwrap(), b"META");
+        assert!(pfs0.get("rtld").is_none());
+
+        let mut malformed = exefs.clone();
+        malformed[0x58] = 0xFF;
+        malformed[0x50..0x54].copy_from_slice(&0x1000u32.to_le_bytes());
+        let names: Vec<_> = Pfs0::new(&malformed).entries().iter().map(|e| e.name.clone()).collect();
+        assert_eq!(names, ["\u{FFFD}ain.npdm", "sdk", ""]);
+
+        let modules = SwitchExecutable::read_exefs(&exefs);
+        let names: Vec<_> = modules.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["main", "sdk"]);
+        assert_eq!(modules[0].1.text(), nro.text());
+    }
+
+    #[test]
+    fn npdm_meta_and_access_control() {
+        // ThreadInfo, SVCs 0x01 and 0x26, handle table size 512, then padding.
+        let caps: [u32; 5] = [
+            3 << 24 | 24 << 10 | 63 << 4 | 0b0111,
+            (1 << 1) << 5 | 0b01111,
+            1 << 29 | (1 << 14) << 5 | 0b01111,
+            512 << 16 | 0x7FFF,
+            0xFFFFFFFF,
+        ];
+        // The last two entries have a non-UTF-8 name and a truncated one.
+        let sac: &[u8] = b"\x02fsp\x84ldr:x\x03sm:*\x01\xFFx\x07ab";
+        let section = |magic: &[u8], header_size: usize, table_at: usize| {
+            let mut out = vec![0u8; header_size];
+            if magic == b"ACID" {
+                out[0x200..0x204].copy_from_slice(magic);
+            } else {
+                out[..4].copy_from_slice(magic);
+            }
+            out[table_at + 8..table_at + 12].copy_from_slice(&(header_size as u32).to_le_bytes());
+            out[table_at + 12..table_at + 16].copy_from_slice(&(sac.len() as u32).to_le_bytes());
+            out.extend_from_slice(sac);
+            let kc_offset = out.len() as u32;
+            out.extend(caps.iter().flat_map(|c| c.to_le_bytes()));
+            out[table_at + 16..table_at + 20].copy_from_slice(&kc_offset.to_le_bytes());
+            out[table_at + 20..table_at + 24].copy_from_slice(&(caps.len() as u32 * 4).to_le_bytes());
+            out
+        };
+        let mut aci0 = section(b"ACI0", 0x40, 0x20);
+        aci0[0x10..0x18].copy_from_slice(&0x0100000000001000u64.to_le_bytes());
+        let acid = section(b"ACID", 0x240, 0x220);
+
+        let mut npdm = vec![0u8; 0x80];
+        npdm[..4].copy_from_slice(b"META");
+        npdm[0x0C] = 1 | (3 << 1);
+        npdm[0x0E] = 49;
+        npdm[0x0F] = 0;
+        npdm[0x14..0x18].copy_from_slice(&0x1000u32.to_le_bytes());
+        npdm[0x1C..0x20].copy_from_slice(&0x100000u32.to_le_bytes());
+        npdm[0x20..0x27].copy_from_slice(b"Example");
+        let at = |npdm: &mut Vec<u8>, field: usize, section: &[u8]| {
+            let offset = npdm.len() as u32;
+            npdm[field..field + 4].copy_from_slice(&offset.to_le_bytes());
+            npdm[field + 4..field + 8].copy_from_slice(&(section.len() as u32).to_le_bytes());
+            npdm.extend_from_slice(section);
+        };
+        at(&mut npdm, 0x70, &aci0);
+        at(&mut npdm, 0x78, &acid);
+
+        let npdm = Npdm::read(&npdm);
+        assert_eq!(npdm.meta.name, "Example");
+        assert!(npdm.meta.is_64bit());
+        assert_eq!(npdm.meta.address_space_type(), AddressSpaceType::AddressSpace64Bit);
+        assert_eq!(npdm.meta.main_thread_priority, 49);
+        assert_eq!(npdm.meta.main_thread_stack_size, 0x100000);
+        assert_eq!(npdm.meta.system_resource_size, 0x1000);
+        assert_eq!(npdm.title_id(), 0x0100000000001000);
+        assert_eq!(npdm.allowed_svcs(), [(1 << 0x01) | (1 << 0x26), 0, 0]);
+        assert!(npdm.is_svc_allowed(0x26) && !npdm.is_svc_allowed(0x27));
+        let services: Vec<_> = npdm.services().iter().map(|s| (s.name.as_str(), s.is_server)).collect();
+        assert_eq!(services, [("fsp", false), ("ldr:x", true), ("sm:*", false), ("\u{FFFD}x", false)]);
+        assert_eq!(npdm.aci0.kernel_capabilities[0], KernelCapability::ThreadInfo {
+            lowest_priority: 63,
+            highest_priority: 24,
+            min_core: 0,
+            max_core: 3,
+        });
+        assert_eq!(npdm.aci0.kernel_capabilities[3], KernelCapability::HandleTableSize(512));
+        assert_eq!(npdm.acid.kernel_capabilities, npdm.aci0.kernel_capabilities);
+
+        let mut meta = npdm.meta.clone();
+        meta.flags = 5 << 1;
+        assert_eq!(meta.address_space_type(), AddressSpaceType::Unknown(5));
+        // A MapRange cut off at the end of the list is dropped.
+        let caps = KernelCapability::parse_all(&[0x7FFF | (512 << 16), 0x3F]);
+        assert_eq!(caps, [KernelCapability::HandleTableSize(512)]);
+        let svcs = nx_utils::npdm::allowed_svcs(&[KernelCapability::EnableSystemCalls { index: 7, mask: 1 << 23 }]);
+        assert_eq!(svcs, [0, 0, 1 << 63]);
+    }
+
+    /// A one-page NRO whose .text holds MOD0, the dynamic section, .dynsym,
+    /// .dynstr and .rela.dyn. Symbols with a value of zero are imports.
+    fn linked_nro(symbols: &[(&str, u8, u64)], relocations: &[(u64, u32, u64, i64)]) -> Vec<u8> {
+        let mut nro = vec![0u8; 0x1000];
+        nro[4..8].copy_from_slice(&0x80u32.to_le_bytes());
+        nro[0x10..0x14].copy_from_slice(b"NRO0");
+        nro[0x18..0x1C].copy_from_slice(&0x1000u32.to_le_bytes());
+        nro[0x24..0x28].copy_from_slice(&0x1000u32.to_le_bytes());
+        nro[0x28..0x2C].copy_from_slice(&0x1000u32.to_le_bytes());
+        nro[0x30..0x34].copy_from_slice(&0x1000u32.to_le_bytes());
+        nro[0x80..0x84].copy_from_slice(b"MOD0");
+        nro[0x84..0x88].copy_from_slice(&0x80u32.to_le_bytes());
+
+        let dynamic = [(6u64, 0x200u64), (5, 0x300), (7, 0x400), (8, relocations.len() as u64 * 0x18), (0, 0)];
+        for (i, (tag, value)) in dynamic.iter().enumerate() {
+            nro[0x100 + i * 16..][..8].copy_from_slice(&tag.to_le_bytes());
+            nro[0x108 + i * 16..][..8].copy_from_slice(&value.to_le_bytes());
+        }
+        let mut name_offset = 1;
+        for (i, (name, info, value)) in symbols.iter().enumerate() {
+            let sym = &mut nro[0x218 + i * 0x18..];
+            sym[..4].copy_from_slice(&(name_offset as u32).to_le_bytes());
+            sym[4] = *info;
+            sym[6..8].copy_from_slice(&((*value != 0) as u16).to_le_bytes());
+            sym[8..16].copy_from_slice(&value.to_le_bytes());
+            nro[0x300 + name_offset..][..name.len()].copy_from_slice(name.as_bytes());
+            name_offset += name.len() + 1;
+        }
+        for (i, (offset, typ, symbol, addend)) in relocations.iter().enumerate() {
+            let rela = &mut nro[0x400 + i * 0x18..];
+            rela[..8].copy_from_slice(&offset.to_le_bytes());
+            rela[8..16].copy_from_slice(&(symbol << 32 | *typ as u64).to_le_bytes());
+            rela[16..24].copy_from_slice(&addend.to_le_bytes());
+        }
+        nro
+    }
+
+    #[test]
+    fn process_image_links_modules() {
+        const GLOBAL_FUNC: u8 = 0x12;
+        const WEAK_FUNC: u8 = 0x22;
+        let main = SwitchExecutable::read_nro(linked_nro(
+            &[("nnMain", GLOBAL_FUNC, 0x900), ("sdk_init", GLOBAL_FUNC, 0), ("optional", WEAK_FUNC, 0), ("missing", GLOBAL_FUNC, 0)],
+            &[
+                (0x800, R_AARCH64_JUMP_SLOT, 2, 0),
+                (0x808, R_AARCH64_GLOB_DAT, 3, 0),
+                (0x810, R_AARCH64_GLOB_DAT, 4, 0),
+                (0x818, R_AARCH64_RELATIVE, 0, 0x900),
+            ],
+        ));
+        let sdk = SwitchExecutable::read_nro(linked_nro(
+            &[("sdk_init", GLOBAL_FUNC, 0xA00), ("nnMain", GLOBAL_FUNC, 0)],
+            &[(0x800, R_AARCH64_ABS64, 2, 8)],
+        ));
+        assert_eq!(main.dynamic_symbols()[1].name, "nnMain");
+        assert_eq!(main.relocations().len(), 4);
+
+        let nro = SwitchExecutable::read_nro(include_bytes!("../test/hello-world.nro").to_vec());
+        let modules = [("main".to_string(), &main), ("hello".to_string(), &nro), ("sdk".to_string(), &sdk)];
+        let process = ProcessImage::load(&modules, ProcessImage::DEFAULT_BASE);
+        let bases: Vec<_> = process.modules.iter().map(|m| m.base).collect();
+        assert_eq!(bases, [0x8000000, 0x8001000, 0x8044000]);
+        assert_eq!(process.module_at(0x8044800).unwrap().name, "sdk");
+
+        assert_eq!(process.resolve("nnMain"), Some(0x8000900));
+        assert_eq!(process.resolve("sdk_init"), Some(0x8044A00));
+        assert_eq!(process.read_u64(0x8000800), Some(0x8044A00));
+        assert_eq!(process.read_u64(0x8000808), Some(0));
+        assert_eq!(process.read_u64(0x8000818), Some(0x8000900));
+        assert_eq!(process.read_u64(0x8044800), Some(0x8000908));
+        assert_eq!(process.unresolved.len(), 1);
+        assert_eq!((process.unresolved[0].symbol.as_str(), process.unresolved[0].address), ("missing", 0x8000810));
+        // The homebrew NRO only carries RELATIVE relocations.
+        assert_eq!(process.read_u64(0x8001000 + 0x3b000), Some(0x8001000 + 0x250));
+    }
+
+    #[test]
+    fn process_image_binds_in_load_order() {
+        const GLOBAL_FUNC: u8 = 0x12;
+        const STV_HIDDEN: u8 = 2;
+        let main = linked_nro(
+            &[("shared", GLOBAL_FUNC, 0x900), ("private", GLOBAL_FUNC, 0x980), ("hidden", GLOBAL_FUNC, 0)],
+            &[
+                (0x800, R_AARCH64_GLOB_DAT, 1, 0),
+                (0x808, R_AARCH64_GLOB_DAT, 2, 0),
+                (0x810, R_AARCH64_GLOB_DAT, 3, 0),
+                (0x1800, R_AARCH64_RELATIVE, 0, 0),
+            ],
+        );
+        let mut sdk = linked_nro(
+            &[("shared", GLOBAL_FUNC, 0xA00), ("private", GLOBAL_FUNC, 0xA80), ("hidden", GLOBAL_FUNC, 0xB00)],
+            &[(0x800, R_AARCH64_GLOB_DAT, 1, 0), (0x808, R_AARCH64_GLOB_DAT, 2, 0), (0x810, R_AARCH64_GLOB_DAT, 0x20, 0)],
+        );
+        sdk[0x218 + 0x18 + 5] = STV_HIDDEN;
+        sdk[0x218 + 2 * 0x18 + 5] = STV_HIDDEN;
+        let (main, sdk) = (SwitchExecutable::read_nro(main), SwitchExecutable::read_nro(sdk));
+
+        let modules = [("main".to_string(), &main), ("sdk".to_string(), &sdk)];
+        let process = ProcessImage::load(&modules, ProcessImage::DEFAULT_BASE);
+        // Both modules bind `shared` to main, the first to export it.
+        assert_eq!(process.read_u64(0x8000800), Some(0x8000900));
+        assert_eq!(process.read_u64(0x8001800), Some(0x8000900));
+        // sdk's hidden `private` stays local.
+        assert_eq!(process.read_u64(0x8000808), Some(0x8000980));
+        assert_eq!(process.read_u64(0x8001808), Some(0x8001A80));
+        // A symbol index past the table is skipped.
+        assert_eq!(process.read_u64(0x8001810), Some(0));
+        // sdk's hidden `hidden` does not satisfy main's import.
+        assert_eq!(process.read_u64(0x8000810), Some(0));
+        assert_eq!(process.unresolved.len(), 1);
+        assert_eq!((process.unresolved[0].symbol.as_str(), process.unresolved[0].address), ("hidden", 0x8000810));
+        // main's relocation past its own end leaves sdk alone.
+        assert_eq!(process.read_u64(0x8001800), Some(0x8000900));
+        assert_eq!(process.read_u64(0x8002000), None);
+
+        // A DT_RELASZ running past the segment drops the table.
+        let mut broken = linked_nro(&[], &[(0x800, R_AARCH64_RELATIVE, 0, 0)]);
+        broken[0x138..0x140].copy_from_slice(&0x10000u64.to_le_bytes());
+        assert!(SwitchExecutable::read_nro(broken).relocations().is_empty());
+    }
+
+    #[test]
+    fn read_elf_segments_and_symbols() {
+        let nro = SwitchExecutable::read_nro(include_bytes!("../test/hello-world.nro").to_vec());
+        let segments = [(5u32, 0u64, nro.text(), 0usize), (4, 0x2c000, nro.ro(), 0), (6, 0x3b000, nro.data(), nro.bss().len())];
+
+        let mut elf = vec![0u8; 0x1000];
+        elf[..6].copy_from_slice(b"\x7FELF\x02\x01");
+        elf[0x12..0x14].copy_from_slice(&183u16.to_le_bytes());
+        elf[0x18..0x20].copy_from_slice(&0x80u64.to_le_bytes());
+        elf[0x20..0x28].copy_from_slice(&0x40u64.to_le_bytes());
+        elf[0x36..0x38].copy_from_slice(&0x38u16.to_le_bytes());
+        elf[0x38..0x3A].copy_from_slice(&3u16.to_le_bytes());
+        elf[0x3A..0x3C].copy_from_slice(&0x40u16.to_le_bytes());
+        for (i, (flags, vaddr, contents, bss)) in segments.iter().enumerate() {
+            let phdr = &mut elf[0x40 + i * 0x38..];
+            phdr[..4].copy_from_slice(&1u32.to_le_bytes());
+            phdr[4..8].copy_from_slice(&flags.to_le_bytes());
+            phdr[8..16].copy_from_slice(&(0x1000 + vaddr).to_le_bytes());
+            phdr[16..24].copy_from_slice(&vaddr.to_le_bytes());
+            phdr[32..40].copy_from_slice(&(contents.len() as u64).to_le_bytes());
+            phdr[40..48].copy_from_slice(&((contents.len() + bss) as u64).to_le_bytes());
+        }
+        for (_, _, contents, _) in segments {
+            elf.extend_from_slice(contents);
+        }
+
+        // .symtab with one function, .strtab, .debug_info and .shstrtab.
+        let mut symtab = vec![0u8; 0x30];
+        symtab[0x18..0x1C].copy_from_slice(&1u32.to_le_bytes());
+        symtab[0x1C] = 0x12;
+        symtab[0x1E..0x20].copy_from_slice(&1u16.to_le_bytes());
+        symtab[0x20..0x28].copy_from_slice(&0x80u64.to_le_bytes());
+        symtab[0x28..0x30].copy_from_slice(&0x40u64.to_le_bytes());
+        let sections: [(&str, u32, u32, &[u8]); 4] = [
+            (".symtab", SHT_SYMTAB, 2, &symtab),
+            (".strtab", 3, 0, b"\0__nx_start\0"),
+            (".debug_info", 1, 0, b"DWARF"),
+            (".shstrtab", 3, 0, b"\0.symtab\0.strtab\0.debug_info\0.shstrtab\0"),
+        ];
+        let mut headers = vec![0u8; 0x40];
+        let mut name_offset = 1;
+        for (name, typ, link, contents) in sections {
+            let mut header = [0u8; 0x40];
+            header[..4].copy_from_slice(&(name_offset as u32).to_le_bytes());
+            header[4..8].copy_from_slice(&typ.to_le_bytes());
+            header[0x18..0x20].copy_from_slice(&(elf.len() as u64).to_le_bytes());
+            header[0x20..0x28].copy_from_slice(&(contents.len() as u64).to_le_bytes());
+            header[0x28..0x2C].copy_from_slice(&link.to_le_bytes());
+            headers.extend_from_slice(&header);
+            elf.extend_from_slice(contents);
+            name_offset += name.len() + 1;
+        }
+        let shoff = elf.len() as u64;
+        elf[0x28..0x30].copy_from_slice(&shoff.to_le_bytes());
+        elf[0x3C..0x3E].copy_from_slice(&5u16.to_le_bytes());
+        elf[0x3E..0x40].copy_from_slice(&4u16.to_le_bytes());
+        elf.extend(headers);
+
+        let exe = SwitchExecutable::read_elf(elf);
+        assert_eq!(exe.text(), nro.text());
+        assert_eq!(exe.ro(), nro.ro());
+        assert_eq!(exe.data(), nro.data());
+        assert_eq!(exe.bss().len(), nro.bss().len());
+        assert_eq!(exe.dynamic_symbols(), nro.dynamic_symbols());
+        assert_eq!(exe.relocations(), nro.relocations());
+
+        let info: &ElfFile = exe.elf().unwrap();
+        assert_eq!(info.entry, 0x80);
+        assert_eq!(info.section_headers.len(), 5);
+        assert_eq!(info.section(".strtab").unwrap().link, 0);
+        assert_eq!(info.symbols.len(), 2);
+        assert_eq!((info.symbols[1].name.as_str(), info.symbols[1].value, info.symbols[1].size), ("__nx_start", 0x80, 0x40));
+        assert_eq!(exe.dwarf_section(".debug_info"), Some(&b"DWARF"[..]));
+        assert!(exe.dwarf_section(".debug_line").is_none());
+
+        // Writing it back keeps the entry point and an SHN_ABS index.
+        let mut elf = exe.program().to_vec();
+        let at = elf.windows(symtab.len()).position(|w| w == symtab).unwrap();
+        elf[at + 0x1E..at + 0x20].copy_from_slice(&SHN_ABS.to_le_bytes());
+        let written = SwitchExecutable::read_elf(SwitchExecutable::read_elf(elf).write_elf());
+        let info = written.elf().unwrap();
+        assert_eq!(info.entry, 0x80);
+        assert_eq!(info.symbols.iter().find(|s| s.name == "__nx_start").unwrap().shndx, SHN_ABS);
+    }
+
+    #[test]
+    fn write_elf_round_trip() {
+        let nro = SwitchExecutable::read_nro(include_bytes!("../test/hello-world.nro").to_vec());
+        let elf = SwitchExecutable::read_elf(nro.write_elf());
+        assert_eq!(elf.text(), nro.text());
+        assert_eq!(elf.ro(), nro.ro());
+        assert_eq!(elf.data(), nro.data());
+        assert_eq!(elf.bss().len(), nro.bss().len());
+        assert_eq!(elf.relocations(), nro.relocations());
+
+        let info = elf.elf().unwrap();
+        let names: Vec<_> = info.section_headers.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["", ".text", ".rodata", ".data", ".bss", ".dynstr", ".dynamic", ".note.gnu.build-id", ".symtab", ".strtab", ".shstrtab"]);
+        assert_eq!(info.section(".dynamic").unwrap().addr, 0x37e10);
+        assert_eq!(info.program_headers.len(), 5);
+        assert_eq!(info.program_headers[2].memsz, 0x8000);
+        assert_eq!(info.build_id.as_deref(), Some(&nro.text()[0x40..0x54]));
+
+        let linked = SwitchExecutable::read_nro(linked_nro(&[("nnMain", 0x12, 0x900), ("sdk_init", 0x12, 0)], &[]));
+        let exported = SwitchExecutable::read_elf(linked.write_elf());
+        let symbols = &exported.elf().unwrap().symbols;
+        let main = symbols.iter().find(|s| s.name == "nnMain").unwrap();
+        assert_eq!((main.value, main.shndx), (0x900, 1));
+        assert!(!symbols.iter().find(|s| s.name == "sdk_init").unwrap().is_defined());
+        assert_eq!(exported.write_elf(), linked.write_elf());
+    }
+
+    #[test]
+    fn executable_trait_across_formats() {
+        let nro = SwitchExecutable::read_nro(include_bytes!("../test/hello-world.nro").to_vec());
+        let nso = SwitchExecutable::read_nso(nro.write_nso(true));
+        let elf = SwitchExecutable::read_elf(nro.write_elf());
+        let formats: [&dyn Executable; 3] = [&nro, &nso, &elf];
+
+        let mut build_id = [0u8; 0x20];
+        build_id[..0x14].copy_from_slice(&nro.text()[0x40..0x54]);
+        for exe in formats {
+            let segments = exe.segments();
+            let layout: Vec<_> = segments.iter().map(|s| (s.name, s.vaddr, s.memory_size, s.permissions)).collect();
+            assert_eq!(layout, [
+                (".text", 0, 0x2c000, Permissions::RX),
+                (".rodata", 0x2c000, 0xf000, Permissions::R),
+                (".data", 0x3b000, 0x4000, Permissions::RW),
+                (".bss", 0x3f000, 0x4000, Permissions::RW),
+            ]);
+            assert_eq!(segments[0].data, nro.text());
+            assert_eq!(exe.entry_point(), 0);
+            assert_eq!(exe.module_name().as_deref(), Some("hello-world"));
+            assert_eq!(exe.symbols().len(), 3);
+            assert_eq!(exe.relocations().len(), 401);
+            a2ir::convertProgram(exe);
+            assert_eq!(exe.build_id(), Some(build_id));
+        }
+        assert_eq!(nso.format(), ExecutableFormat::Nso);
+    }
+
+    #[test]
+    fn borrowed_parsing_is_zero_copy() {
+        let file: &[u8] = include_bytes!("../test/hello-world.nro");
+        let within = |bytes: &[u8], file: &[u8]| file.as_ptr_range().contains(&bytes.as_ptr());
+
+        let nro = SwitchExecutable::read_nro(file);
+        assert!(within(nro.text(), file) && within(nro.ro(), file) && within(nro.data(), file));
+        assert!(within(nro.icon().unwrap(), file));
+        assert_eq!(nro.program().as_ptr(), file.as_ptr());
+
+        let plain = nro.write_nso(false);
+        let compressed = nro.write_nso(true);
+        let plain_nso = SwitchExecutable::read_nso(&plain[..]);
+        let compressed_nso = SwitchExecutable::read_nso(&compressed[..]);
+        assert!(within(plain_nso.text(), &plain));
+        assert!(!within(compressed_nso.text(), &compressed));
+        assert_eq!(plain_nso.text(), compressed_nso.text());
+
+        let elf = nro.write_elf();
+        let borrowed_elf = SwitchExecutable::read_elf(&elf);
+        assert!(within(borrowed_elf.data(), &elf));
+
+        // An owned copy outlives the buffer it was read from.
+        let owned = {
+            let buffer = file.to_vec();
+            SwitchExecutable::read_nro(&buffer).into_owned()
+        };
+        assert_eq!(owned.text(), nro.text());
+        assert_eq!(owned.nacp(), nro.nacp());
+    }
+
+    #[test]
+    fn nro_header_fields() {
+        let file: &[u8] = include_bytes!("../test/hello-world.nro");
+        let nro = SwitchExecutable::read_nro(file);
+        let header = nro.nro_header().unwrap();
+        assert_eq!(header.mod0_offset, 0x118);
+        assert_eq!(&header.padding, b"HOMEBREW");
+        assert_eq!((header.version, header.size, header.flags), (0, 0x3f000, 0));
+        let segments: Vec<_> = header.segments.iter().map(|s| (s.offset, s.size)).collect();
+        assert_eq!(segments, [(0, 0x2c000), (0x2c000, 0xf000), (0x3b000, 0x4000)]);
+        assert_eq!(header.bss_size, 0x4000);
+        assert_eq!(header.build_id[..4], [0x87, 0x47, 0xc9, 0x9a]);
+        assert_eq!(header.api_info, NroSegment::default());
+        assert_eq!(header.to_bytes(), &file[..NroHeader::SIZE]);
+        assert_eq!(nro.build_id(), Some(header.build_id));
+        assert!(nro.nso_header().is_none());
+
+        let mut changed = header.clone();
+        changed.version = 1;
+        changed.dynsym = NroSegment { offset: 0xbf20, size: 0x48 };
+        let reread = NroHeader::new(&mut Cursor::new(&changed.to_bytes()[..]));
+        assert_eq!(reread, changed);
+    }
+
+    #[test]
+    fn ips_patches_by_build_id() {
+        let nro = SwitchExecutable::read_nro(include_bytes!("../test/hello-world.nro").to_vec());
+        let mut ips = b"PATCH".to_vec();
+        ips.extend_from_slice(&[0x00, 0x10, 0x00, 0x00, 0x04, 0xAA, 0xBB, 0xCC, 0xDD]);
+        ips.extend_from_slice(&[0x03, 0xB0, 0x00, 0x00, 0x00, 0x00, 0x08, 0xEE]);
+        ips.extend_from_slice(&[0x00, 0x00, 0x00, 0x00, 0x01, 0xFF]);
+        ips.extend_from_slice(b"EOF");
+
+        let patch = IpsPatch::read("8747C99A3C92C6B589F791BFB5EAA17A37E1CA3B.ips", &ips).unwrap();
+        assert_eq!(patch.format, IpsFormat::Ips);
+        assert_eq!(patch.records.len(), 3);
+        assert_eq!(patch.records[1].data, IpsData::Rle { len: 8, value: 0xEE });
+
+        let patched = nro.apply_ips(&patch).unwrap();
+        assert_eq!(patched.text()[0x1000..0x1004], [0xAA, 0xBB, 0xCC, 0xDD]);
+        assert_eq!(patched.data()[..8], [0xEE; 8]);
+        assert_eq!(patched.data()[8..], nro.data()[8..]);
+        // The NRO header is protected.
+        assert_eq!(patched.text()[0], nro.text()[0]);
+        assert_eq!(patched.nacp(), nro.nacp());
+        // The untouched .rodata is still read from the original file.
+        assert_eq!(patched.program().as_ptr(), nro.program().as_ptr());
+        assert!(nro.program().as_ptr_range().contains(&patched.ro().as_ptr()));
+
+        let other = IpsPatch::read("0123456789ABCDEF.ips", &ips).unwrap();
+        assert!(nro.apply_ips(&other).is_none());
+        assert!(IpsPatch::parse(b"PATCH\x00\x00\x10\x00\x04\xAA").is_none());
+
+        // IPS32 offsets into an NSO count the 0x100-byte NSO header.
+        let nso = SwitchExecutable::read_nso(nro.write_nso(true));
+        let mut ips32 = b"IPS32".to_vec();
+        ips32.extend_from_slice(&[0x00, 0x02, 0xC1, 0x00, 0x00, 0x02, 0x12, 0x34]);
+        ips32.extend_from_slice(b"EEOF");
+        let patch32 = IpsPatch::read("8747C99A3C92C6B5.ips32", &ips32).unwrap();
+        assert_eq!(patch32.format, IpsFormat::Ips32);
+        let patched_nso = nso.apply_ips(&patch32).unwrap();
+        assert_eq!(patched_nso.ro()[..2], [0x12, 0x34]);
+        assert_eq!(patched_nso.ro()[2..], nso.ro()[2..]);
+        assert_eq!(patched_nso.text(), nso.text());
+    }
+
+    #[test]
+    fn nx_result_codes() {
+        let invalid_handle = NxResult(0xE401);
+        assert_eq!((invalid_handle.module(), invalid_handle.description()), (1, 114));
+        assert_eq!(invalid_handle, NxResult::new(1, 114));
+        assert_eq!(invalid_handle.to_string(), "2001-0114 (Kernel: InvalidHandle)");
+        assert_eq!(NxResult::new(21, 7).to_string(), "2021-0007 (SM: NotRegistered)");
+        assert_eq!(NxResult::new(345, 9999).to_string(), "2345-1807 (Libnx)");
+        assert_eq!(NxResult::new(500, 1).to_string(), "2500-0001");
+        assert_eq!(NxResult::SUCCESS.to_string(), "2000-0000 (Success)");
+        assert_eq!(NxResult::new(0x1FF, 0x1FFF).0, 0x3FFFFF);
+        assert!(NxResult::SUCCESS.into_result().is_ok());
+        assert_eq!(NxResult::from(0xE401).into_result(), Err(invalid_handle));
+    }
+
+    #[test]
+    fn hipc_cmif_messages() {
+        // The smallest CMIF request: command 1 with no arguments.
+        let mut message = HipcMessage::default();
+        let request = CmifRequest {
+            command_type: CmifCommandType::Request,
+            domain: None,
+            header: Some(CmifInHeader { version: 0, command_id: 1, token: 0 }),
+            payload: Vec::new(),
+        };
+        request.write_to(&mut message);
+        let bytes = message.to_bytes();
+        assert_eq!(bytes.len(), 0x28);
+        assert_eq!(&bytes[..8], &[4, 0, 0, 0, 8, 0, 0, 0]);
+        assert_eq!(&bytes[0x10..0x14], b"SFCI");
+        let parsed = CmifRequest::parse(&HipcMessage::parse(&bytes).unwrap(), false).unwrap();
+        assert_eq!(parsed.header, request.header);
+        // Without a domain header the trailing alignment padding stays in the payload.
+        assert_eq!(parsed.payload, [0; 8]);
+
+        // Every descriptor kind, a PID and handles, sent to a domain object.
+        let mut message = HipcMessage {
+            pid: Some(0x51),
+            copy_handles: vec![0xd000],
+            move_handles: vec![0xd001, 0xd002],
+            send_statics: vec![StaticDescriptor { index: 3, address: 0x7f_1234_5678, size: 0x40 }],
+            send_buffers: vec![BufferDescriptor { address: 0x3f_8000_1000, size: 0x1_0000_0200, mode: BufferMode::NonSecure }],
+            recv_buffers: vec![BufferDescriptor { address: 0x2000, size: 0x100, mode: BufferMode::Normal }],
+            exch_buffers: vec![BufferDescriptor { address: 0x3000, size: 0x80, mode: BufferMode::NonDevice }],
+            recv_list: vec![RecvListEntry { address: 0xab_cdef_0000, size: 0x1000 }],
+            ..Default::default()
+        };
+        let request = CmifRequest {
+            command_type: CmifCommandType::Request,
+            domain: Some(DomainRequest {
+                request_type: DomainRequestType::SendMessage,
+                object_id: 0xf001,
+                in_objects: vec![0xf002],
+                token: 0,
+            }),
+            header: Some(CmifInHeader { version: 0, command_id: 17, token: 0 }),
+            payload: 0x1122334455667788u64.to_le_bytes().to_vec(),
+        };
+        request.write_to(&mut message);
+        let bytes = message.to_bytes();
+        let header = HipcHeader::new(&mut Cursor::new(&bytes[..]));
+        assert_eq!(header.recv_static_mode, 3);
+        assert!(header.has_special_header);
+        let parsed = HipcMessage::parse(&bytes).unwrap();
+        assert_eq!(parsed, message);
+        assert_eq!(parsed.to_bytes(), bytes);
+        let parsed_request = CmifRequest::parse(&parsed, true).unwrap();
+        assert_eq!(parsed_request.command_id(), Some(17));
+        assert_eq!(parsed_request, request);
+        assert!(HipcMessage::parse(&bytes[..bytes.len() - 4]).is_none());
+
+        // A domain response returning a new object.
+        let mut response = CmifResponse::new(NxResult::SUCCESS, 7u32.to_le_bytes().to_vec());
+        response.out_objects = Some(vec![0xf003]);
+        let mut message = HipcMessage { move_handles: vec![0xd003], ..Default::default() };
+        response.write_to(&mut message);
+        let parsed = HipcMessage::parse(&message.to_bytes()).unwrap();
+        assert_eq!(CmifResponse::parse(&parsed, true, 4).unwrap(), response);
+
+        let failure = CmifResponse::new(NxResult::new(21, 7), Vec::new());
+        let mut message = HipcMessage::default();
+        failure.write_to(&mut message);
+        let parsed = CmifResponse::parse(&HipcMessage::parse(&message.to_bytes()).unwrap(), false, 8).unwrap();
+        assert_eq!(parsed.header.result.to_string(), "2021-0007 (SM: NotRegistered)");
+    }
+
+    #[test]
+    fn tipc_and_cmif_protocol_selection() {
+        let protocols = ServiceProtocols::for_firmware(12);
+        assert_eq!(protocols.protocol("sm:"), IpcProtocol::Tipc);
+        assert_eq!(protocols.protocol("fsp-srv"), IpcProtocol::Cmif);
+        assert_eq!(ServiceProtocols::for_firmware(11).protocol("sm:"), IpcProtocol::Cmif);
+
+        // sm:GetServiceHandle("fsp-srv") as newer libnx sends it.
+        let mut name = [0u8; 8];
+        name[..7].copy_from_slice(b"fsp-srv");
+        let mut message = HipcMessage::default();
+        TipcRequest { command_id: Some(1), payload: name.to_vec() }.write_to(&mut message);
+        let bytes = message.to_bytes();
+        assert_eq!(&bytes[..8], &[17, 0, 0, 0, 2, 0, 0, 0]);
+        assert_eq!(&bytes[8..], &name);
+
+        let request = IpcRequest::parse(&HipcMessage::parse(&bytes).unwrap(), false).unwrap();
+        assert_eq!(request.protocol(), IpcProtocol::Tipc);
+        assert_eq!((request.command_id(), request.payload()), (Some(1), &name[..]));
+        let mut reply = HipcMessage { move_handles: vec![0xd005], ..Default::default() };
+        request.respond(NxResult::SUCCESS, Vec::new(), &mut reply);
+        let reply = HipcMessage::parse(&reply.to_bytes()).unwrap();
+        assert_eq!(reply.move_handles, [0xd005]);
+        assert_eq!(TipcResponse::parse(&reply).unwrap(), TipcResponse { result: NxResult::SUCCESS, payload: Vec::new() });
+
+        // The same command from older libnx over CMIF gets a CMIF reply.
+        let mut message = HipcMessage::default();
+        let cmif = CmifRequest {
+            command_type: CmifCommandType::Request,
+            domain: None,
+            header: Some(CmifInHeader { version: 0, command_id: 1, token: 0 }),
+            payload: name.to_vec(),
+        };
+        cmif.write_to(&mut message);
+        let request = IpcRequest::parse(&message, false).unwrap();
+        assert_eq!((request.protocol(), request.command_id()), (IpcProtocol::Cmif, Some(1)));
+        let mut reply = HipcMessage::default();
+        request.respond(NxResult::new(21, 7), Vec::new(), &mut reply);
+        let response = CmifResponse::parse(&reply, false, 0).unwrap();
+        assert_eq!(response.header.result, NxResult::new(21, 7));
+
+        let mut close = HipcMessage::default();
+        TipcRequest { command_id: None, payload: Vec::new() }.write_to(&mut close);
+        assert_eq!(close.message_type, TipcRequest::CLOSE_MESSAGE_TYPE);
+        assert_eq!(IpcRequest::parse(&close, false).unwrap().command_id(), None);
+    }
+
+    #[test]
+    fn ir_build_and_verify() {
+        // x0 = sum of 1..=x1, as a loop carrying the counter in a block parameter.
+        let mut func = Function::new("sum", 0x1000);
+        let mut b = FunctionBuilder::new(&mut func);
+        let entry = b.create_block();
+        let header = b.create_block();
+        let exit = b.create_block();
+        let counter = b.append_block_param(header, Type::I64);
+        let sum = b.append_block_param(header, Type::I64);
+
+        b.switch_to_block(entry);
+        let n = b.read_reg(Reg::X(1));
+        let zero = b.iconst(Type::I64, 0);
+        b.jump(header, vec![n, zero]);
+
+        b.switch_to_block(header);
+        let one = b.iconst(Type::I64, 1);
+        let next_sum = b.add(sum, counter);
+        let next_counter = b.sub(counter, one);
+        let done = b.icmp(IntCC::Eq, next_counter, zero);
+        b.branch(done, BlockCall { block: exit, args: vec![] }, BlockCall { block: header, args: vec![next_counter, next_sum] });
+
+        b.switch_to_block(exit);
+        b.write_reg(Reg::X(0), next_sum);
+        let addr = b.read_reg(Reg::Sp);
+        let byte = b.trunc(next_sum, Type::I8);
+        b.store(addr, byte, MemoryOrder::Release);
+        let lr = b.read_reg(Reg::X(30));
+        b.indirect_branch(lr);
+
+        assert_eq!(verify(&func), Ok(()));
+        assert_eq!(func.value_type(done), Type::I1);
+        assert_eq!(dominators(&func)[&exit], header);
+        let text = func.to_string();
+        assert!(text.contains("block1(v0: i64, v1: i64):"), "{}", text);
+        assert!(text.contains("v5: i64 = add v1, v0"), "{}", text);
+        assert!(text.contains("store release [v8], v9"), "{}", text);
+        assert_eq!(Type::vector(ScalarType::F32, 4).to_string(), "f32x4");
+        assert_eq!(Type::vector(ScalarType::I16, 8).bits(), 128);
+
+        // A missing terminator and mismatched types are reported first.
+        let mut func = Function::new("broken", 0x2000);
+        let mut b = FunctionBuilder::new(&mut func);
+        let entry = b.create_block();
+        let left = b.create_block();
+        let right = b.create_block();
+        let join = b.create_block();
+        b.switch_to_block(entry);
+        let w = b.iconst(Type::I32, 1);
+        let x = b.iconst(Type::I64, 2);
+        b.add(w, x);
+        let cond = b.bool(true);
+        b.branch(cond, BlockCall { block: left, args: vec![] }, BlockCall { block: right, args: vec![] });
+        b.switch_to_block(left);
+        let only_left = b.iconst(Type::I64, 3);
+        b.jump(join, vec![]);
+        b.switch_to_block(right);
+        b.jump(join, vec![]);
+        b.switch_to_block(join);
+        b.write_reg(Reg::X(0), only_left);
+        let errors = verify(&func).unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].to_string(), "block0, inst2: Add of i32 and i64");
+        assert_eq!(errors[1].to_string(), "block3: block does not end in a terminator");
+
+        // Once those are fixed, the value defined on one path only is caught.
+        let mut func = Function::new("fixed", 0x2000);
+        let mut b = FunctionBuilder::new(&mut func);
+        let [entry, left, right, join] = [(); 4].map(|_| b.create_block());
+        b.switch_to_block(entry);
+        let cond = b.bool(true);
+        b.branch(cond, BlockCall { block: left, args: vec![] }, BlockCall { block: right, args: vec![] });
+        b.switch_to_block(left);
+        let only_left = b.iconst(Type::I64, 3);
+        b.jump(join, vec![]);
+        b.switch_to_block(right);
+        b.jump(join, vec![]);
+        b.switch_to_block(join);
+        b.write_reg(Reg::X(0), only_left);
+        b.ret();
+        let errors = verify(&func).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].block, join);
+        assert_eq!(errors[0].message, "v1 does not dominate its use");
+    }
+
+    /// The instruction defining `value`.
+    fn def(func: &Function, value: Value) -> &InstKind {
+        match func.value_def(value) {
+            ValueDef::Inst(inst) => &func.inst(inst).kind,
+            param => panic!("{} is {:?}", value, param),
+        }
+    }
+
+    /// Every value `block` writes to `reg`, in program order.
+    fn reg_writes(func: &Function, block: Block, reg: Reg) -> Vec<Value> {
+        func.block_insts(block)
+            .iter()
+            .filter_map(|inst| match func.inst(*inst).kind {
+                InstKind::WriteReg { reg: dest, value } if dest == reg => Some(value),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The last value `block` writes to `reg`.
+    fn written(func: &Function, block: Block, reg: Reg) -> Value {
+        *reg_writes(func, block, reg).last().unwrap_or_else(|| panic!("{} does not write {}", block, reg))
+    }
+
+    /// The operation and operand definitions of the binary instruction `value`.
+    fn binary(func: &Function, value: Value) -> (BinaryOp, &InstKind, &InstKind) {
+        match *def(func, value) {
+            InstKind::Binary { op, lhs, rhs } => (op, def(func, lhs), def(func, rhs)),
+            ref other => panic!("{} is {:?}", value, other),
+        }
+    }
+
+    /// The operation and operand of the cast `value`.
+    fn cast(func: &Function, value: Value) -> (CastOp, Value) {
+        match *def(func, value) {
+            InstKind::Cast { op, arg, .. } => (op, arg),
+            ref other => panic!("{} is {:?}", value, other),
+        }
+    }
+
+    /// The comparison and operand definitions of the integer compare `value`.
+    fn icmp(func: &Function, value: Value) -> (IntCC, &InstKind, &InstKind) {
+        match *def(func, value) {
+            InstKind::Icmp { cond, lhs, rhs } => (cond, def(func, lhs), def(func, rhs)),
+            ref other => panic!("{} is {:?}", value, other),
+        }
+    }
+
+    /// The type, address and ordering of the load `value`.
+    fn load(func: &Function, value: Value) -> (Type, Value, MemoryOrder) {
+        match *def(func, value) {
+            InstKind::Load { ty, addr, order } => (ty, addr, order),
+            ref other => panic!("{} is {:?}", value, other),
+        }
+    }
+
+    /// The condition of the branch ending `block`.
+    fn branch_cond(func: &Function, block: Block) -> Value {
+        match func.terminator(block) {
+            Some(InstKind::Branch { cond, .. }) => *cond,
+            other => panic!("{} ends in {:?}", block, other),
+        }
+    }
+
+    #[test]
+    fn lift_data_processing() {
+        let code: Vec<u8> = [
+            0xD28000A0u32, // mov x0, #5
+            0x11000401,    // add w1, w0, #1
+            0x910043FF,    // add sp, sp, #0x10
+            0xAA0103E2,    // mov x2, x1
+            0xD3442C43,    // ubfx x3, x2, #4, #8
+            0xB0000004,    // adrp x4, #0x1000
+            0x8B214BE5,    // add x5, sp, w1, uxtw #2
+            0x1A9F17E6,    // cset w6, eq
+            0x9B010807,    // madd x7, x0, x1, x2
+            0x93407C08,    // sxtw x8, w0
+            0x92400C29,    // and x9, x1, #0xf
+            0xB260002A,    // orr x10, x1, #0x100000000
+            0xD200F02B,    // eor x11, x1, #0x5555555555555555
+            0xF241003F,    // tst x1, #0x8000000000000000
+            0x00000000,    // udf #0
+        ]
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .collect();
+        assert_eq!(decode(0xD3442C43).op, Op::A64_UBFX);
+        assert_eq!(decode(0x1A9F17E6).op, Op::A64_CSET);
+        // Reserved and unallocated logical immediates.
+        assert_eq!(decode(0x12400000).op, Op::A64_UNKNOWN); // N=1 with sf=0
+        assert_eq!(decode(0x9200FC00).op, Op::A64_UNKNOWN); // N=0, imms=0b111111
+        assert_eq!(decode(0x9240FC00).op, Op::A64_UNKNOWN); // all-ones element
+
+        let func = lift_function("slice", &code, 0x8000123, FlagsMode::Faithful);
+        assert_eq!(verify(&func), Ok(()));
+        let text = func.to_string();
+        let entry = func.entry_block().unwrap();
+        let read = |reg| InstKind::ReadReg { reg };
+        let imm = |bits| InstKind::Const { ty: Type::I64, bits };
+        // W results are zero-extended into the X register.
+        let (op, w1) = cast(&func, written(&func, entry, Reg::X(1)));
+        assert_eq!((op, func.value_type(w1)), (CastOp::Zext, Type::I32), "{}", text);
+        assert_eq!(binary(&func, w1).0, BinaryOp::Add, "{}", text);
+        // Register 31 is SP for ADD (immediate) and ZR for ORR.
+        assert_eq!(binary(&func, written(&func, entry, Reg::Sp)), (BinaryOp::Add, &read(Reg::Sp), &imm(0x10)), "{}", text);
+        assert_eq!(def(&func, written(&func, entry, Reg::X(2))), &read(Reg::X(1)), "{}", text);
+        assert_eq!(def(&func, written(&func, entry, Reg::X(4))), &imm(0x8001000), "{}", text);
+        let (_, w6) = cast(&func, written(&func, entry, Reg::X(6)));
+        assert_eq!(def(&func, cast(&func, w6).1), &read(Reg::Flag(Flag::Z)), "{}", text);
+        let (op, w8) = cast(&func, written(&func, entry, Reg::X(8)));
+        assert_eq!((op, func.value_type(w8)), (CastOp::Sext, Type::I32), "{}", text);
+        // 64-bit logical immediates, including elements narrower than 64 bits.
+        for (rd, op, bits) in [(9, BinaryOp::And, 0xf), (10, BinaryOp::Or, 0x1_0000_0000), (11, BinaryOp::Xor, 0x5555_5555_5555_5555)] {
+            assert_eq!(binary(&func, written(&func, entry, Reg::X(rd))), (op, &read(Reg::X(1)), &imm(bits)), "{}", text);
+        }
+        let InstKind::Icmp { cond: IntCC::Eq, lhs, .. } = *def(&func, written(&func, entry, Reg::Flag(Flag::Z))) else { panic!("{}", text) };
+        assert_eq!(binary(&func, lhs), (BinaryOp::And, &read(Reg::X(1)), &imm(0x8000_0000_0000_0000)), "{}", text);
+        let last = func.terminator(func.entry_block().unwrap()).unwrap();
+        assert_eq!(last, &InstKind::Trap { kind: TrapKind::Undefined });
+
+        // The slice from NRO bytes: the entry point lifts up to its first
+        // unsupported instruction and exits there.
+        let nro = SwitchExecutable::read_nro(include_bytes!("../test/hello-world.nro").to_vec());
+        let module = convertProgram(&nro);
+        let entry = module.function_at(ProcessImage::DEFAULT_BASE + nro.entry_point()).unwrap();
+        assert_eq!(verify(entry), Ok(()));
+    }
+
+    #[test]
+    fn lift_flags() {
+        let code: Vec<u8> = [
+            0xF100141Fu32, // cmp x0, #5
+            0x5400004B,    // b.lt +8
+            0x9A830041,    // csel x1, x2, x3, eq
+            0xFA43C804,    // ccmp x0, #3, #4, gt
+            0x1A9FC7E4,    // cset w4, le
+            0x7200001F,    // tst w0, #1
+            0x54000041,    // b.ne +8
+            0xAB010005,    // adds x5, x0, x1
+            0x9A010006,    // adc x6, x0, x1
+            0x1E602008,    // fcmp d0, #0.0
+            0x54000044,    // b.mi +8
+        ]
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .collect();
+        // `cmp x0, #5; b.lt` is one signed compare, and the flags are only
+        // written on the way out.
+        let lazy = lift_function("flags", &code, 0x1000, FlagsMode::Lazy);
+        assert_eq!(verify(&lazy), Ok(()));
+        let text = lazy.to_string();
+        let read = |reg| InstKind::ReadReg { reg };
+        let imm = |ty, bits| InstKind::Const { ty, bits };
+        let five = imm(Type::I64, 5);
+        assert_eq!(icmp(&lazy, branch_cond(&lazy, Block(0))), (IntCC::Slt, &read(Reg::X(0)), &five), "{}", text);
+        assert_eq!(icmp(&lazy, written(&lazy, Block(1), Reg::Flag(Flag::Z))), (IntCC::Eq, &read(Reg::X(0)), &five), "{}", text);
+        let InstKind::Select { cond, if_true, if_false } = *def(&lazy, written(&lazy, Block(2), Reg::X(1))) else { panic!("{}", text) };
+        assert_eq!(icmp(&lazy, cond), (IntCC::Eq, &read(Reg::X(0)), &five), "{}", text);
+        assert_eq!((def(&lazy, if_true), def(&lazy, if_false)), (&read(Reg::X(2)), &read(Reg::X(3))), "{}", text);
+        // CCMP picks between its own compare and #nzcv on the CMP's flags.
+        let conds: Vec<_> = lazy.block_insts(Block(2))
+            .iter()
+            .filter_map(|inst| match lazy.inst(*inst).kind {
+                InstKind::Select { cond, .. } => Some(icmp(&lazy, cond)),
+                _ => None,
+            })
+            .collect();
+        assert!(conds.contains(&(IntCC::Sgt, &read(Reg::X(0)), &five)), "{}", text);
+        let (cc, masked, zero) = icmp(&lazy, branch_cond(&lazy, Block(2)));
+        assert_eq!((cc, zero), (IntCC::Ne, &imm(Type::I32, 0)), "{}", text);
+        assert!(matches!(masked, InstKind::Binary { op: BinaryOp::And, .. }), "{}", text);
+        // ADC adds the carry out of the ADDS before it.
+        let (op, _, carry) = binary(&lazy, written(&lazy, Block(4), Reg::X(6)));
+        let InstKind::Cast { op: CastOp::Zext, arg: carry, .. } = *carry else { panic!("{}", text) };
+        assert_eq!(op, BinaryOp::Add, "{}", text);
+        assert_eq!(icmp(&lazy, carry), (IntCC::Ult, def(&lazy, written(&lazy, Block(4), Reg::X(5))), &read(Reg::X(0))), "{}", text);
+        assert!(matches!(def(&lazy, branch_cond(&lazy, Block(4))), InstKind::Fcmp { cond: FloatCC::Lt, .. }), "{}", text);
+        let writes = |func: &Function, block: u32| {
+            func.block_insts(Block(block))
+                .iter()
+                .filter(|inst| matches!(func.inst(**inst).kind, InstKind::WriteReg { reg: Reg::Flag(_), .. }))
+                .count()
+        };
+        assert_eq!([0, 1, 2, 3, 4, 5, 6].map(|block| writes(&lazy, block)), [0, 4, 0, 4, 0, 4, 4]);
+
+        // Faithful mode writes NZCV at every flag-setting instruction but still
+        // branches on the compared values.
+        let faithful = lift_function("flags", &code, 0x1000, FlagsMode::Faithful);
+        assert_eq!(verify(&faithful), Ok(()));
+        let text = faithful.to_string();
+        assert_eq!(icmp(&faithful, branch_cond(&faithful, Block(0))), (IntCC::Slt, &read(Reg::X(0)), &five), "{}", text);
+        assert_eq!([0, 1, 2, 3, 4, 5, 6].map(|block| writes(&faithful, block)), [4, 0, 8, 0, 8, 0, 0]);
+    }
+
+    #[test]
+    fn lift_loads_and_stores() {
+        let code: Vec<u8> = [
+            0xF9400401u32, // ldr x1, [x0, #8]
+            0xB89FCFE2,    // ldrsw x2, [sp, #-4]!
+            0xB8004403,    // str w3, [x0], #4
+            0x3861C804,    // ldrb w4, [x0, w1, sxtw]
+            0xF8617805,    // ldr x5, [x0, x1, lsl #3]
+            0xA9401806,    // ldp x6, x6, [x0]
+            0xA9BF7BFD,    // stp x29, x30, [sp, #-16]!
+            0x3DC00400,    // ldr q0, [x0, #16]
+            0xFC1F8001,    // stur d1, [x0, #-8]
+            0x18000107,    // ldr w7, #0x20
+            0xF9800010,    // prfm pstl1keep, [x0]
+            0xC8DFFC08,    // ldar x8, [x0]
+            0x889FFC09,    // stlr w9, [x0]
+        ]
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .collect();
+        assert_eq!(decode(0xA9BF7BFD).op, Op::A64_STP);
+        assert_eq!(decode(0x3DC00400).op, Op::A64_LDR_FP);
+        assert_eq!(decode(0xF9800010).op, Op::A64_PRFM);
+
+        let func = lift_function("memory", &code, 0x2000, FlagsMode::Lazy);
+        assert_eq!(verify(&func), Ok(()));
+        let text = func.to_string();
+        let entry = func.entry_block().unwrap();
+        let read = |reg| InstKind::ReadReg { reg };
+        let imm = |bits: i64| InstKind::Const { ty: Type::I64, bits: bits as u64 as u128 };
+        let stores: Vec<_> = func.block_insts(entry)
+            .iter()
+            .filter_map(|inst| match func.inst(*inst).kind {
+                InstKind::Store { addr, value, order } => Some((addr, value, order)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(stores.len(), 5, "{}", text);
+        // Pre-index: the access and the new SP use the same address, and the
+        // word is sign-extended after the load.
+        let (op, word) = cast(&func, written(&func, entry, Reg::X(2)));
+        let (ty, addr, _) = load(&func, word);
+        assert_eq!((op, ty), (CastOp::Sext, Type::I32), "{}", text);
+        assert_eq!(binary(&func, addr), (BinaryOp::Add, &read(Reg::Sp), &imm(-4)), "{}", text);
+        assert_eq!(reg_writes(&func, entry, Reg::Sp)[0], addr, "{}", text);
+        // Post-index: the store goes to the old base.
+        let InstKind::Binary { op: BinaryOp::Add, lhs: base, .. } = *def(&func, written(&func, entry, Reg::X(0))) else { panic!("{}", text) };
+        assert_eq!(stores[0].0, base, "{}", text);
+        assert_eq!(def(&func, base), &read(Reg::X(0)), "{}", text);
+        let (_, byte) = cast(&func, cast(&func, written(&func, entry, Reg::X(4))).1);
+        let (ty, addr, _) = load(&func, byte);
+        assert_eq!(ty, Type::I8, "{}", text);
+        assert!(matches!(binary(&func, addr), (BinaryOp::Add, _, InstKind::Cast { op: CastOp::Sext, .. })), "{}", text);
+        let (_, addr, _) = load(&func, written(&func, entry, Reg::X(5)));
+        assert!(matches!(binary(&func, addr), (BinaryOp::Add, _, InstKind::Binary { op: BinaryOp::Shl, .. })), "{}", text);
+        // LDP x6, x6: both loads happen and the second one wins.
+        let pair = reg_writes(&func, entry, Reg::X(6));
+        let [(_, first, _), (_, second, _)] = [load(&func, pair[0]), load(&func, pair[1])];
+        assert_eq!(binary(&func, second), (BinaryOp::Add, def(&func, first), &imm(8)), "{}", text);
+        let [(low, x29, _), (high, x30, _)] = [stores[1], stores[2]];
+        assert_eq!((def(&func, x29), def(&func, x30)), (&read(Reg::X(29)), &read(Reg::X(30))), "{}", text);
+        assert_eq!(binary(&func, low), (BinaryOp::Add, &read(Reg::Sp), &imm(-16)), "{}", text);
+        assert_eq!(binary(&func, high), (BinaryOp::Add, def(&func, low), &imm(8)), "{}", text);
+        assert_eq!(written(&func, entry, Reg::Sp), low, "{}", text);
+        assert_eq!(load(&func, written(&func, entry, Reg::V(0))).0, Type::I128, "{}", text);
+        assert_eq!(cast(&func, stores[3].1).0, CastOp::Trunc, "{}", text);
+        assert_eq!(binary(&func, stores[3].0), (BinaryOp::Add, &read(Reg::X(0)), &imm(-8)), "{}", text);
+        let (ty, addr, _) = load(&func, cast(&func, written(&func, entry, Reg::X(7))).1);
+        assert_eq!((ty, def(&func, addr)), (Type::I32, &imm(0x2044)), "{}", text);
+        assert!(func.block_insts(entry).iter().any(|inst| match func.inst(*inst).kind {
+            InstKind::Prefetch { addr, write } => write && def(&func, addr) == &read(Reg::X(0)),
+            _ => false,
+        }), "{}", text);
+        assert_eq!(load(&func, written(&func, entry, Reg::X(8))).2, MemoryOrder::Acquire, "{}", text);
+        assert_eq!(stores[4].2, MemoryOrder::Release, "{}", text);
+        assert_eq!(Module::default().memory, MemoryModel::HORIZON);
+    }
+}